@@ -0,0 +1,292 @@
+extern crate rodio;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+use self::rodio::{Endpoint, Sink, Source, Decoder};
+use circles::world::GameEvent;
+
+const VOLUME_STEP: f32 = 0.1;
+const MUSIC_VOLUME: f32 = 0.5; // relative to the effects
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Sound {
+    Shoot,
+    Jump,
+    Land,
+    Bounce,
+    Attach,
+    Pickup,
+    BugDeath,
+    ShipHurt,
+    ShipDeath,
+    Win,
+}
+
+const SOUND_FILES: [(Sound, &'static str); 10] = [(Sound::Shoot, "shoot.ogg"),
+                                                 (Sound::Jump, "jump.ogg"),
+                                                 (Sound::Land, "land.ogg"),
+                                                 (Sound::Bounce, "bounce.ogg"),
+                                                 (Sound::Attach, "attach.ogg"),
+                                                 (Sound::Pickup, "pickup.ogg"),
+                                                 (Sound::BugDeath, "bug_death.ogg"),
+                                                 (Sound::ShipHurt, "ship_hurt.ogg"),
+                                                 (Sound::ShipDeath, "ship_death.ogg"),
+                                                 (Sound::Win, "win.ogg")];
+
+/// Something that can actually make noise. `Audio` picks the backend, so the rest of the game
+/// never has to care whether there's a sound card.
+trait AudioBackend {
+    fn play(&mut self, sound: Sound, volume: f32);
+    fn set_music_volume(&mut self, volume: f32);
+}
+
+/// Used when there's no audio device, e.g. when running headless.
+struct NullBackend;
+
+impl AudioBackend for NullBackend {
+    fn play(&mut self, _sound: Sound, _volume: f32) {}
+    fn set_music_volume(&mut self, _volume: f32) {}
+}
+
+struct RodioBackend {
+    endpoint: Endpoint,
+    // the encoded sound files, decoded again each time they're played
+    effects: HashMap<Sound, Vec<u8>>,
+    // dropping the sink would stop the music
+    music: Option<Sink>,
+}
+
+impl RodioBackend {
+    fn new(endpoint: Endpoint, folder: &Path) -> Self {
+        let mut effects = HashMap::new();
+        for &(sound, file) in SOUND_FILES.iter() {
+            if let Some(data) = read_file(&folder.join(file)) {
+                effects.insert(sound, data);
+            }
+        }
+        let music = read_file(&folder.join("music.ogg")).and_then(|data| {
+            match Decoder::new(Cursor::new(data)) {
+                Ok(source) => {
+                    let sink = Sink::new(&endpoint);
+                    sink.append(source.repeat_infinite());
+                    Some(sink)
+                }
+                Err(e) => {
+                    println!("Couldn't decode music: {:?}", e);
+                    None
+                }
+            }
+        });
+        RodioBackend {
+            endpoint: endpoint,
+            effects: effects,
+            music: music,
+        }
+    }
+}
+
+impl AudioBackend for RodioBackend {
+    fn play(&mut self, sound: Sound, volume: f32) {
+        if let Some(data) = self.effects.get(&sound) {
+            match Decoder::new(Cursor::new(data.clone())) {
+                Ok(source) => {
+                    let sink = Sink::new(&self.endpoint);
+                    sink.set_volume(volume);
+                    sink.append(source);
+                    // let it finish playing on its own
+                    sink.detach();
+                }
+                Err(e) => println!("Couldn't decode {:?}: {:?}", sound, e),
+            }
+        }
+    }
+
+    fn set_music_volume(&mut self, volume: f32) {
+        if let Some(ref mut music) = self.music {
+            music.set_volume(volume);
+        }
+    }
+}
+
+fn read_file(path: &Path) -> Option<Vec<u8>> {
+    let mut data = vec![];
+    match File::open(path).and_then(|mut f| f.read_to_end(&mut data)) {
+        Ok(_) => Some(data),
+        Err(e) => {
+            println!("Not using sound {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Audio plays sound effects and the background music, from the files in `assets/sounds`. Missing
+/// files are silently skipped, and if there's no audio device at all nothing is played.
+pub struct Audio {
+    backend: Box<AudioBackend>,
+    volume: f32, // 0.0 to 1.0
+    muted: bool,
+}
+
+impl Audio {
+    pub fn new(folder: &Path) -> Self {
+        let endpoint = match rodio::get_default_endpoint() {
+            Some(endpoint) => endpoint,
+            None => {
+                println!("No audio device found, sound is disabled");
+                return Audio::null();
+            }
+        };
+        let mut audio = Audio {
+            backend: Box::new(RodioBackend::new(endpoint, folder)),
+            volume: 1.0,
+            muted: false,
+        };
+        audio.update_music_volume();
+        audio
+    }
+
+    /// An Audio that never makes any sound, for when there's no audio device.
+    pub fn null() -> Self {
+        Audio {
+            backend: Box::new(NullBackend),
+            volume: 1.0,
+            muted: false,
+        }
+    }
+
+    pub fn play(&mut self, sound: Sound) {
+        if !self.muted && self.volume > 0.0 {
+            self.backend.play(sound, self.volume);
+        }
+    }
+
+    /// Play the sound for something that happened in the world, if it has one.
+    pub fn react(&mut self, event: &GameEvent) {
+        let sound = match *event {
+            GameEvent::Shot(_) => Sound::Shoot,
+            GameEvent::Jumped(_) => Sound::Jump,
+            GameEvent::Landed { .. } |
+            GameEvent::Pounded { .. } => Sound::Land,
+            GameEvent::Bounced { .. } => Sound::Bounce,
+            GameEvent::Attached { .. } => Sound::Attach,
+            GameEvent::PickedUp { .. } => Sound::Pickup,
+            GameEvent::BugKilled { .. } => Sound::BugDeath,
+            GameEvent::ShipDamaged { .. } => Sound::ShipHurt,
+            GameEvent::ShipHit { .. } => Sound::ShipDeath,
+            GameEvent::MagicPlanetReached(_) => Sound::Win,
+            GameEvent::LevelReset => return,
+        };
+        self.play(sound);
+    }
+
+    pub fn toggle_mute(&mut self) {
+        self.muted = !self.muted;
+        self.update_music_volume();
+    }
+
+    pub fn volume_up(&mut self) {
+        self.volume = (self.volume + VOLUME_STEP).min(1.0);
+        self.update_music_volume();
+    }
+
+    pub fn volume_down(&mut self) {
+        self.volume = (self.volume - VOLUME_STEP).max(0.0);
+        self.update_music_volume();
+    }
+
+    fn update_music_volume(&mut self) {
+        let volume = if self.muted { 0.0 } else { self.volume * MUSIC_VOLUME };
+        self.backend.set_music_volume(volume);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use circles::calc::pt;
+
+    /// Remembers what it was asked to play.
+    struct Recorder {
+        played: Rc<RefCell<Vec<Sound>>>,
+    }
+
+    impl AudioBackend for Recorder {
+        fn play(&mut self, sound: Sound, _volume: f32) {
+            self.played.borrow_mut().push(sound);
+        }
+        fn set_music_volume(&mut self, _volume: f32) {}
+    }
+
+    fn every_event() -> Vec<GameEvent> {
+        let pos = pt(1.0, 2.0);
+        vec![GameEvent::Shot(0),
+             GameEvent::Jumped(0),
+             GameEvent::Landed {
+                 ship: 0,
+                 pos: pos,
+                 dir: 0.0,
+             },
+             GameEvent::Bounced {
+                 ship: 0,
+                 pos: pos,
+                 dir: 0.0,
+             },
+             GameEvent::Attached {
+                 ship: 0,
+                 pos: pos,
+                 dir: 0.0,
+             },
+             GameEvent::BugKilled { by: 0, pos: pos },
+             GameEvent::ShipHit {
+                 ship: 0,
+                 by: None,
+                 pos: pos,
+             },
+             GameEvent::ShipDamaged { ship: 0, pos: pos },
+             GameEvent::PickedUp { ship: 0, pos: pos },
+             GameEvent::Pounded { ship: 0, pos: pos },
+             GameEvent::MagicPlanetReached(0),
+             GameEvent::LevelReset]
+    }
+
+    #[test]
+    fn null_audio_reacts_headless() {
+        let mut audio = Audio::null();
+        for event in every_event() {
+            audio.react(&event);
+        }
+        for _ in 0..20 {
+            audio.volume_down();
+        }
+        assert_eq!(audio.volume, 0.0);
+        audio.volume_up();
+        assert!(audio.volume > 0.0);
+        audio.toggle_mute();
+        assert!(audio.muted);
+    }
+
+    #[test]
+    fn react_plays_a_sound_for_everything_but_level_resets() {
+        let played = Rc::new(RefCell::new(vec![]));
+        let mut audio = Audio::null();
+        audio.backend = Box::new(Recorder { played: played.clone() });
+        for event in every_event() {
+            audio.react(&event);
+        }
+        assert_eq!(played.borrow().len(), every_event().len() - 1);
+        assert_eq!(played.borrow()[0], Sound::Shoot);
+        // being hurt doesn't sound like dying
+        assert_eq!(played.borrow()[6], Sound::ShipDeath);
+        assert_eq!(played.borrow()[7], Sound::ShipHurt);
+
+        audio.toggle_mute();
+        audio.react(&GameEvent::Shot(0));
+        assert_eq!(played.borrow().len(), every_event().len() - 1);
+    }
+}
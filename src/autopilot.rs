@@ -0,0 +1,311 @@
+use std::f64::consts::PI;
+
+use calc::{Point, distance, direction_from_to};
+use space::{PlanetIndex, PlanetKind, MAGIC_PLANET_SIZE};
+use ship::{GameInput, SPEED};
+use world::{World, Mode, GameEvent};
+use env::STEP_TIME;
+use difficulty::Difficulty;
+use generator::Generator;
+
+// how close to facing the next planet the ship has to be before it takes off. Any closer than one
+// step of turning and it would never settle.
+const AIM_TOLERANCE: f64 = SPEED * STEP_TIME * 1.5;
+// gaps narrower than this are jumped across rather than flown
+const JUMP_REACH: f64 = 100.0;
+// the furthest hop the autopilot plans; it can fly further, but is more likely to miss
+const MAX_HOP: f64 = 800.0;
+// crawlers closer than this get shot
+const SHOOT_RANGE: f64 = 600.0;
+// after this long in the air, grab whatever planet is closest
+const MAX_AIR_TIME: f64 = 6.0;
+
+/// Where the autopilot is going next.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Target {
+    Planet(PlanetIndex),
+    MagicPlanet,
+}
+
+/// Autopilot plays the game by itself. Each tick it looks at the world and decides what buttons
+/// a player would be pressing: on a planet it turns to face the next planet on the way to the
+/// magic planet and takes off, jumping small gaps and flying big ones; in the air it attaches to
+/// the planet it was heading for once that's the closest one, and comes down onto it. Any crawler
+/// in range gets shot.
+pub struct Autopilot {
+    seed: u32, // the level the state below is about
+    launched_from: Option<PlanetIndex>,
+    target: Option<Target>,
+    air_time: f64,
+    // hops that went wrong, as (from, to), so they aren't tried again
+    failed: Vec<(PlanetIndex, PlanetIndex)>,
+}
+
+impl Autopilot {
+    pub fn new() -> Autopilot {
+        Autopilot {
+            seed: 0,
+            launched_from: None,
+            target: None,
+            air_time: 0.0,
+            failed: vec![],
+        }
+    }
+
+    /// Decide the controls for ship `i` for the next `time_delta` seconds.
+    pub fn update(&mut self, world: &World, i: usize, time_delta: f64) -> GameInput {
+        if world.seed != self.seed {
+            *self = Autopilot::new();
+            self.seed = world.seed;
+        }
+        self.follow_planets(world);
+        let ref ship = world.ships[i];
+        let ship_pos = ship.get_pos(&world.space);
+        let mut input = GameInput::new();
+        input.jumped = ship.input.jumped;
+        input.thrown = ship.input.thrown;
+        input.ducked = ship.input.ducked;
+
+        self.shoot(world, ship_pos, &mut input);
+        if ship.in_air() {
+            self.air_time += time_delta;
+            if ship.get_planet(&world.space).kind == PlanetKind::Bouncy &&
+               self.launched_from != Some(ship.attached_planet) {
+                // bounced; carry on from here
+                self.launch(world, ship.attached_planet);
+            }
+            self.fly(world, i, ship_pos, &mut input);
+        } else {
+            self.walk(world, i, &mut input);
+        }
+
+        if !input.jump {
+            input.jumped = false;
+        }
+        if !input.attach {
+            input.thrown = false;
+        }
+        if !input.down {
+            input.ducked = false;
+        }
+        input
+    }
+
+    /// Shoot the nearest crawler, if it's in range.
+    fn shoot(&self, world: &World, ship_pos: Point, input: &mut GameInput) {
+        let nearest = world.space
+            .get_nearby_hazards()
+            .into_iter()
+            .filter_map(|entity| world.space.entities.get_pos(entity))
+            .map(|pos| (distance(ship_pos, pos), pos))
+            .filter(|&(d, _)| d < SHOOT_RANGE)
+            .fold(None, |best: Option<(f64, Point)>, (d, pos)| {
+                match best {
+                    Some((best_d, _)) if best_d <= d => best,
+                    _ => Some((d, pos)),
+                }
+            });
+        if let Some((_, pos)) = nearest {
+            input.shooting = true;
+            input.shoot_target = Some(pos);
+        }
+    }
+
+    /// On a planet: turn towards the next planet, and take off when facing it.
+    fn walk(&mut self, world: &World, i: usize, input: &mut GameInput) {
+        let ref ship = world.ships[i];
+        let here = ship.attached_planet;
+        if let Some(from) = self.launched_from.take() {
+            // landed. If it wasn't where we were going, don't try that again.
+            if let Some(Target::Planet(target)) = self.target {
+                if target != here {
+                    self.failed.push((from, target));
+                }
+            }
+        }
+        self.air_time = 0.0;
+
+        let target = self.choose_target(world, here);
+        self.target = Some(target);
+        let planet = ship.get_planet(&world.space);
+        let (target_pos, target_radius) = self.target_circle(world, target);
+        let diff = angle_diff(direction_from_to(planet.pos, target_pos), ship.rotation);
+        if diff.abs() > AIM_TOLERANCE {
+            turn(diff, input);
+        } else {
+            let gap = distance(planet.pos, target_pos) - planet.radius - target_radius;
+            if gap < JUMP_REACH {
+                if input.jumped {
+                    // still holding jump from the last hop; let go first
+                    return;
+                }
+                input.jump = true;
+            } else {
+                input.up = true;
+            }
+            self.launched_from = Some(here);
+        }
+    }
+
+    /// In the air: keep going until the planet we're heading for is the closest one, then throw a
+    /// tether to it and hang on until we're down.
+    fn fly(&mut self, world: &World, i: usize, ship_pos: Point, input: &mut GameInput) {
+        let ref ship = world.ships[i];
+        let attached = ship.attached_planet;
+        let from_pos = ship.get_planet(&world.space).pos;
+        if self.launched_from != Some(attached) {
+            // attached to where we're going. Jumps come down by themselves.
+            input.down = ship.flying;
+            input.attach = ship.tether.is_some();
+            return;
+        }
+
+        // steer towards the target
+        if let Some(target) = self.target {
+            let (target_pos, _) = self.target_circle(world, target);
+            turn(angle_diff(direction_from_to(from_pos, target_pos), ship.rotation), input);
+        }
+        if ship.flying {
+            input.up = true;
+        } else {
+            input.jump = true;
+        }
+
+        let magic_planet = world.space.get_magic_planet();
+        let progress = |pos: Point| distance(pos, magic_planet);
+        let nearest = world.space
+            .get_nearby_planets()
+            .into_iter()
+            .filter(|&(_, planet)| planet.is_solid())
+            .map(|(idx, planet)| (distance(ship_pos, planet.pos) - planet.radius, idx, planet.pos))
+            .fold(None, |best: Option<(f64, PlanetIndex, Point)>, (d, idx, pos)| {
+                match best {
+                    Some((best_d, _, _)) if best_d <= d => best,
+                    _ => Some((d, idx, pos)),
+                }
+            });
+        if let Some((_, nearest, nearest_pos)) = nearest {
+            let worth_it = self.target == Some(Target::Planet(nearest)) ||
+                           progress(nearest_pos) < progress(from_pos) ||
+                           self.air_time > MAX_AIR_TIME;
+            if nearest != attached && worth_it {
+                input.attach = true;
+                input.up = false;
+                input.jump = false;
+            }
+        }
+    }
+
+    /// Keep up with planets that moved to another area since the last update.
+    fn follow_planets(&mut self, world: &World) {
+        let ref space = world.space;
+        self.launched_from = self.launched_from.map(|idx| space.relocated(idx));
+        if let Some(Target::Planet(idx)) = self.target {
+            self.target = Some(Target::Planet(space.relocated(idx)));
+        }
+        for &mut (ref mut from, ref mut to) in self.failed.iter_mut() {
+            *from = space.relocated(*from);
+            *to = space.relocated(*to);
+        }
+    }
+
+    fn launch(&mut self, world: &World, from: PlanetIndex) {
+        self.launched_from = Some(from);
+        self.target = Some(self.choose_target(world, from));
+        self.air_time = 0.0;
+    }
+
+    /// The planet to go to from `here`: the one closest to the magic planet that's within a hop
+    /// and closer than `here`. If there isn't one, go straight for the magic planet and hope.
+    fn choose_target(&self, world: &World, here: PlanetIndex) -> Target {
+        let magic_planet = world.space.get_magic_planet();
+        let here_planet = match world.space.get_planet(here) {
+            Some(planet) => planet,
+            None => return Target::MagicPlanet,
+        };
+        let here_progress = distance(here_planet.pos, magic_planet);
+        if here_progress - here_planet.radius - MAGIC_PLANET_SIZE < MAX_HOP {
+            return Target::MagicPlanet;
+        }
+        let mut best = None;
+        let mut best_progress = here_progress;
+        for (idx, planet) in world.space.get_nearby_planets() {
+            let hop = distance(here_planet.pos, planet.pos) - here_planet.radius - planet.radius;
+            let progress = distance(planet.pos, magic_planet);
+            if idx != here && planet.is_solid() && hop < MAX_HOP && progress < best_progress &&
+               !self.failed.contains(&(here, idx)) {
+                best = Some(idx);
+                best_progress = progress;
+            }
+        }
+        best.map_or(Target::MagicPlanet, Target::Planet)
+    }
+
+    fn target_circle(&self, world: &World, target: Target) -> (Point, f64) {
+        match target {
+            Target::Planet(idx) => {
+                match world.space.get_planet(idx) {
+                    Some(planet) => (planet.pos, planet.radius),
+                    None => (world.space.get_magic_planet(), MAGIC_PLANET_SIZE),
+                }
+            }
+            Target::MagicPlanet => (world.space.get_magic_planet(), MAGIC_PLANET_SIZE),
+        }
+    }
+}
+
+/// Press left or right to close an angle difference.
+fn turn(diff: f64, input: &mut GameInput) {
+    if diff > AIM_TOLERANCE {
+        input.right = true;
+    } else if diff < -AIM_TOLERANCE {
+        input.left = true;
+    }
+}
+
+/// The angle to turn by to get from `from` to `to`, between -π and π.
+fn angle_diff(to: f64, from: f64) -> f64 {
+    let mut diff = to - from;
+    while diff > PI {
+        diff -= 2.0 * PI;
+    }
+    while diff < -PI {
+        diff += 2.0 * PI;
+    }
+    diff
+}
+
+/// How a playtest went.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Playtest {
+    Completed { time: f64 },
+    Died { time: f64 },
+    TimedOut,
+}
+
+/// Let the autopilot play the level generated from `seed`, `difficulty` and `generator`, without a
+/// window, for up to `time_limit` seconds of game time.
+pub fn playtest(seed: u32,
+                difficulty: &Difficulty,
+                generator: Option<Generator>,
+                time_limit: f64)
+                -> Playtest {
+    let mut world = World::with_generator(Mode::Solo, 1, seed, *difficulty, generator);
+    let ship_positions = world.get_ship_positions();
+    world.space.focus(&ship_positions);
+    let mut autopilot = Autopilot::new();
+    let mut time = 0.0;
+    while time < time_limit {
+        world.ships[0].input = autopilot.update(&world, 0, STEP_TIME);
+        world.update(STEP_TIME);
+        time += STEP_TIME;
+        for event in world.drain_events() {
+            match event {
+                GameEvent::MagicPlanetReached(_) => return Playtest::Completed { time: time },
+                GameEvent::ShipHit { .. } => return Playtest::Died { time: time },
+                _ => {}
+            }
+        }
+    }
+    Playtest::TimedOut
+}
@@ -0,0 +1,127 @@
+extern crate circles;
+
+use std::env;
+
+use circles::autopilot::{playtest, Playtest};
+use circles::reach::generations;
+use circles::difficulty::Preset;
+use circles::generator::Generator;
+
+const DEFAULT_SEEDS: u32 = 100;
+// seconds of game time the autopilot gets per level
+const DEFAULT_TIME_LIMIT: f64 = 120.0;
+
+/// Let the autopilot play a run of levels and report how many of them it could finish. Levels it
+/// can't finish aren't necessarily impossible, but a drop in the completion rate after a change to
+/// level generation is worth a look. With `--check`, just count the levels that couldn't be won as
+/// they were first generated, and the ones that had to be patched up, which is much quicker.
+fn main() {
+    let mut first_seed = 0;
+    let mut seeds = DEFAULT_SEEDS;
+    let mut time_limit = DEFAULT_TIME_LIMIT;
+    let mut verbose = false;
+    let mut check = false;
+    let mut preset = Preset::Normal;
+    let mut generator = Some(Generator::RandomWalk);
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = match arg.as_str() {
+            "--verbose" => {
+                verbose = true;
+                continue;
+            }
+            "--check" => {
+                check = true;
+                continue;
+            }
+            "--easy" | "--normal" | "--hard" => {
+                preset = match arg.as_str() {
+                    "--easy" => Preset::Easy,
+                    "--hard" => Preset::Hard,
+                    _ => Preset::Normal,
+                };
+                continue;
+            }
+            "--seeds" | "--first" | "--time" | "--generator" => args.next(),
+            _ => None,
+        };
+        match (arg.as_str(), value) {
+            ("--seeds", Some(n)) => seeds = n.parse().expect("--seeds takes a number"),
+            ("--first", Some(n)) => first_seed = n.parse().expect("--first takes a seed"),
+            ("--time", Some(t)) => time_limit = t.parse().expect("--time takes seconds"),
+            ("--generator", Some(name)) => {
+                match Generator::parse_choice(&name) {
+                    Some(choice) => generator = choice,
+                    None => {
+                        println!("--generator takes one of {}", Generator::choices());
+                        return;
+                    }
+                }
+            }
+            _ => {
+                println!("usage: circles-playtest [--seeds N] [--first SEED] [--time SECONDS] \
+                          [--easy | --normal | --hard] [--generator NAME] [--verbose] [--check]");
+                return;
+            }
+        }
+    }
+
+    let difficulty = preset.difficulty();
+    if check {
+        let generations = generations(first_seed..first_seed + seeds, &difficulty, generator);
+        let mut retried = 0;
+        let mut repaired = 0;
+        let mut unsolvable = 0;
+        for &(seed, generation) in generations.iter() {
+            if let Some(problem) = generation.problem {
+                unsolvable += 1;
+                println!("seed {}: still can't be won: {:?}", seed, problem);
+            } else if generation.repaired {
+                println!("seed {}: patched up after {} tries", seed, generation.attempts);
+            } else if verbose {
+                if let Some(problem) = generation.first_problem {
+                    println!("seed {}: {:?}, won on try {}", seed, problem, generation.attempts);
+                }
+            }
+            if generation.first_problem.is_some() {
+                retried += 1;
+            }
+            if generation.repaired {
+                repaired += 1;
+            }
+        }
+        println!("{} of {} levels couldn't be won as first generated", retried, seeds);
+        println!("{} had to be patched up, and {} still can't be won",
+                 repaired,
+                 unsolvable);
+        return;
+    }
+
+    let mut completed = 0;
+    let mut died = 0;
+    let mut timed_out = 0;
+    let mut total_time = 0.0;
+    for seed in first_seed..first_seed + seeds {
+        let result = playtest(seed, &difficulty, generator, time_limit);
+        match result {
+            Playtest::Completed { time } => {
+                completed += 1;
+                total_time += time;
+            }
+            Playtest::Died { .. } => died += 1,
+            Playtest::TimedOut => timed_out += 1,
+        }
+        if verbose {
+            println!("seed {}: {:?}", seed, result);
+        }
+    }
+
+    let percent = |n: u32| 100.0 * n as f64 / seeds as f64;
+    println!("{} {:?} levels", seeds, preset);
+    println!("completed: {} ({:.1}%)", completed, percent(completed));
+    println!("died:      {} ({:.1}%)", died, percent(died));
+    println!("timed out: {} ({:.1}%)", timed_out, percent(timed_out));
+    if completed > 0 {
+        println!("average time to complete: {:.1}s", total_time / completed as f64);
+    }
+}
@@ -0,0 +1,239 @@
+extern crate circles;
+extern crate rand;
+
+use std::env;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use circles::net::{ClientMessage, ServerMessage, Snapshot, DEFAULT_PORT, MAX_PACKET_SIZE,
+                   EVENT_TICKS};
+use circles::world::{World, Mode, GameEvent, TICK_RATE, TICK_TIME};
+use circles::difficulty::Preset;
+use circles::generator::Generator;
+
+// send a snapshot every this many ticks
+const TICKS_PER_SNAPSHOT: u32 = 2;
+// drop clients we haven't heard from for this many seconds
+const TIMEOUT: u64 = 5;
+const MAX_PLAYERS: usize = 8;
+const MAX_SPECTATORS: usize = 32;
+
+/// A connected player. Connection `i` always controls ship `i` in the world. Spectators are
+/// connections too, but don't control anything.
+struct Connection {
+    addr: SocketAddr,
+    last_input: u32,
+    last_heard: Instant,
+}
+
+struct Server {
+    socket: UdpSocket,
+    world: World,
+    connections: Vec<Connection>,
+    spectators: Vec<Connection>,
+    tick: u32,
+    // the events of the last `EVENT_TICKS` ticks, and the tick each happened on
+    events: Vec<(u32, GameEvent)>,
+}
+
+impl Server {
+    fn new(socket: UdpSocket, world: World) -> Server {
+        Server {
+            socket: socket,
+            world: world,
+            connections: vec![],
+            spectators: vec![],
+            tick: 0,
+            events: vec![],
+        }
+    }
+
+    fn send(&self, addr: SocketAddr, msg: &ServerMessage) {
+        if let Err(e) = self.socket.send_to(&msg.encode(), addr) {
+            println!("Couldn't send to {}: {}", addr, e);
+        }
+    }
+
+    /// Handle every packet that's waiting.
+    fn receive(&mut self) {
+        let mut buf = [0; MAX_PACKET_SIZE];
+        loop {
+            let (len, addr) = match self.socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return,
+                Err(e) => {
+                    println!("Error receiving: {}", e);
+                    return;
+                }
+            };
+            let msg = match ClientMessage::decode(&buf[..len]) {
+                Ok(msg) => msg,
+                Err(e) => {
+                    println!("Bad packet from {}: {}", addr, e);
+                    continue;
+                }
+            };
+            let i = self.connections.iter().position(|c| c.addr == addr);
+            if let Some(i) = i {
+                self.connections[i].last_heard = Instant::now();
+            }
+            if let Some(j) = self.spectators.iter().position(|c| c.addr == addr) {
+                if msg == ClientMessage::Bye {
+                    self.spectators.remove(j);
+                    println!("{} stopped watching", addr);
+                } else {
+                    self.spectators[j].last_heard = Instant::now();
+                }
+                continue;
+            }
+            match (msg, i) {
+                (ClientMessage::Watch, None) => self.watch(addr),
+                (ClientMessage::Hello, None) => self.join(addr),
+                // they didn't get the welcome
+                (ClientMessage::Hello, Some(_)) => {
+                    self.send(addr, &ServerMessage::Welcome { mode: self.world.mode })
+                }
+                (ClientMessage::Input { seq, input }, Some(i)) => {
+                    if seq > self.connections[i].last_input {
+                        self.connections[i].last_input = seq;
+                        input.apply(&mut self.world.ships[i].input);
+                    }
+                }
+                (ClientMessage::Bye, Some(i)) => self.leave(i),
+                // players have to leave before they can watch
+                (ClientMessage::Watch, Some(_)) => {}
+                // from someone who hasn't said hello, or has already left
+                (_, None) => {}
+            }
+        }
+    }
+
+    fn join(&mut self, addr: SocketAddr) {
+        if self.connections.len() >= MAX_PLAYERS {
+            self.send(addr, &ServerMessage::Full);
+            return;
+        }
+        self.world.add_ship();
+        self.connections.push(Connection {
+            addr: addr,
+            last_input: 0,
+            last_heard: Instant::now(),
+        });
+        println!("{} joined ({} playing)", addr, self.connections.len());
+        self.send(addr, &ServerMessage::Welcome { mode: self.world.mode });
+    }
+
+    fn watch(&mut self, addr: SocketAddr) {
+        if self.spectators.len() >= MAX_SPECTATORS {
+            self.send(addr, &ServerMessage::Full);
+            return;
+        }
+        self.spectators.push(Connection {
+            addr: addr,
+            last_input: 0,
+            last_heard: Instant::now(),
+        });
+        println!("{} is watching", addr);
+        self.send(addr, &ServerMessage::Welcome { mode: self.world.mode });
+    }
+
+    fn leave(&mut self, i: usize) {
+        let connection = self.connections.remove(i);
+        self.world.remove_ship(i);
+        // the events still to be sent again have to go by the new numbers too
+        self.events = self.events
+            .iter()
+            .filter_map(|&(tick, event)| event.after_removing(i).map(|event| (tick, event)))
+            .collect();
+        println!("{} left ({} playing)", connection.addr, self.connections.len());
+    }
+
+    fn update(&mut self, time_delta: f64) {
+        let timeout = Duration::from_secs(TIMEOUT);
+        while let Some(i) = self.connections.iter().position(|c| c.last_heard.elapsed() > timeout) {
+            self.leave(i);
+        }
+        self.spectators.retain(|c| c.last_heard.elapsed() <= timeout);
+
+        if !self.world.ships.is_empty() {
+            self.world.update(time_delta);
+        }
+        let tick = self.tick;
+        self.events.extend(self.world.drain_events().into_iter().map(|event| (tick, event)));
+        self.events.retain(|&(happened, _)| tick - happened < EVENT_TICKS);
+
+        self.tick += 1;
+        if self.tick % TICKS_PER_SNAPSHOT == 0 {
+            self.send_snapshots();
+        }
+    }
+
+    fn send_snapshots(&self) {
+        let events = self.events.clone();
+        let mut snapshot = Snapshot::from_world(&self.world, self.tick, None, 0, events);
+        snapshot.fit();
+        for spectator in self.spectators.iter() {
+            self.send(spectator.addr, &ServerMessage::Snapshot(snapshot.clone()));
+        }
+        for (i, connection) in self.connections.iter().enumerate() {
+            snapshot.your_ship = Some(i as u32);
+            snapshot.last_input = connection.last_input;
+            self.send(connection.addr, &ServerMessage::Snapshot(snapshot.clone()));
+        }
+    }
+}
+
+fn main() {
+    let mut addr = format!("0.0.0.0:{}", DEFAULT_PORT);
+    let mut mode = Mode::Race;
+    let mut preset = Preset::Normal;
+    let mut generator = Some(Generator::RandomWalk);
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--race" => mode = Mode::Race,
+            "--versus" => mode = Mode::Versus,
+            "--easy" => preset = Preset::Easy,
+            "--normal" => preset = Preset::Normal,
+            "--hard" => preset = Preset::Hard,
+            "--generator" => {
+                match args.next().map(|name| Generator::parse_choice(&name)) {
+                    Some(Some(choice)) => generator = choice,
+                    _ => {
+                        println!("--generator takes one of {}", Generator::choices());
+                        return;
+                    }
+                }
+            }
+            "--help" => {
+                println!("usage: circles-server [--race | --versus] [--easy | --normal | --hard] \
+                          [--generator NAME] [ADDRESS]");
+                return;
+            }
+            _ => addr = arg,
+        }
+    }
+
+    let socket = UdpSocket::bind(addr.as_str()).expect("couldn't bind the server address");
+    socket.set_nonblocking(true).expect("couldn't make the socket non-blocking");
+    let world = World::with_generator(mode, 0, rand::random(), preset.difficulty(), generator);
+    let mut server = Server::new(socket, world);
+    println!("Serving a {:?} {:?} game on {}", preset, mode, addr);
+
+    let tick_length = Duration::new(0, 1_000_000_000 / TICK_RATE);
+    let mut next_tick = Instant::now();
+    loop {
+        server.receive();
+        server.update(TICK_TIME);
+        next_tick += tick_length;
+        let now = Instant::now();
+        if next_tick > now {
+            thread::sleep(next_tick.duration_since(now));
+        } else {
+            // we've fallen behind; don't try to catch up all at once
+            next_tick = now;
+        }
+    }
+}
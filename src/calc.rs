@@ -1,9 +1,10 @@
 extern crate nalgebra as na;
 use std::fmt;
+use std::f64::consts::PI;
 
 use na::{Isometry2, Vector2};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Point {
     pub x: f64,
     pub y: f64,
@@ -30,6 +31,10 @@ pub fn direction_from_to(from: Point, to: Point) -> f64 {
     (to.y - from.y).atan2(to.x - from.x)
 }
 
+pub fn distance(a: Point, b: Point) -> f64 {
+    ((b.x - a.x).powi(2) + (b.y - a.y).powi(2)).sqrt()
+}
+
 pub fn rotated_position(origin: Point, rotation: f64, height: f64) -> Point {
     pt(origin.x + (rotation.cos() * height),
        origin.y + (rotation.sin() * height))
@@ -39,10 +44,27 @@ pub fn lerp(a: f64, b: f64, t: f64) -> f64 {
     a + t * (b - a)
 }
 
+/// Like `lerp`, but goes the short way around the circle.
+pub fn lerp_angle(a: f64, b: f64, t: f64) -> f64 {
+    let mut diff = b - a;
+    while diff > PI {
+        diff -= 2.0 * PI;
+    }
+    while diff < -PI {
+        diff += 2.0 * PI;
+    }
+    a + diff * t
+}
+
 pub fn coll_pt(point: Point) -> Isometry2<f64> {
     Isometry2::new(Vector2::new(point.x, point.y), na::zero())
 }
 
+/// A movement as ncollide wants it, for time of impact queries.
+pub fn coll_vec(motion: Point) -> Vector2<f64> {
+    Vector2::new(motion.x, motion.y)
+}
+
 
 /// Given
 pub fn shrink_to_bounds(mini_width: f64,
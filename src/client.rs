@@ -0,0 +1,270 @@
+use std::collections::VecDeque;
+use std::f64;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+use calc::{lerp, lerp_angle};
+use net::{ClientMessage, ServerMessage, Snapshot, ShipState, InputState, MAX_PACKET_SIZE};
+use world::{World, Mode, GameEvent};
+
+// Other ships are drawn this many seconds in the past, so there's usually a snapshot either side
+// of the time being drawn.
+const INTERPOLATION_DELAY: f64 = 0.1;
+// how often to say hello until the server answers
+const HELLO_INTERVAL: f64 = 0.5;
+// spectators don't send input, so they send this often to show they're still watching
+const KEEPALIVE_INTERVAL: f64 = 1.0;
+// inputs the server hasn't acknowledged after this many are given up on
+const MAX_PENDING_INPUTS: usize = 120;
+const MAX_HISTORY: usize = 32;
+
+/// Client is the network side of a game played on a server. The server is in charge of the world;
+/// the client predicts its own ship from the local input so it responds straight away, corrects it
+/// when a snapshot arrives, and draws the other ships a little in the past, smoothly moving
+/// between the snapshots it has. A spectating client has no ship of its own, and draws them all
+/// that way.
+pub struct Client {
+    socket: UdpSocket,
+    server: SocketAddr,
+    /// The game mode, once the server has welcomed us.
+    pub mode: Option<Mode>,
+    /// Our ship in the world, once the first snapshot has arrived.
+    pub ship: Option<usize>,
+    /// True if the server said it had no room for us.
+    pub full: bool,
+    pub spectating: bool,
+    seq: u32,
+    // inputs sent but not yet seen by the server, with the time delta they were applied for
+    pending: VecDeque<(u32, InputState, f64)>,
+    last_tick: Option<u32>,
+    // the tick of the newest event we've passed on; the ones before it come round again
+    last_event_tick: Option<u32>,
+    // the ships from recent snapshots, oldest first, with the time they arrived
+    history: VecDeque<(f64, Vec<ShipState>)>,
+    time: f64,
+    since_hello: f64,
+}
+
+impl Client {
+    /// Join the game on a server.
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<Client> {
+        Client::open(addr, false)
+    }
+
+    /// Watch the game on a server without playing.
+    pub fn watch<A: ToSocketAddrs>(addr: A) -> io::Result<Client> {
+        Client::open(addr, true)
+    }
+
+    fn open<A: ToSocketAddrs>(addr: A, spectating: bool) -> io::Result<Client> {
+        let server = match addr.to_socket_addrs()?.next() {
+            Some(server) => server,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "no address")),
+        };
+        let local = if server.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+        let socket = UdpSocket::bind(local)?;
+        socket.set_nonblocking(true)?;
+        let client = Client {
+            socket: socket,
+            server: server,
+            mode: None,
+            ship: None,
+            full: false,
+            spectating: spectating,
+            seq: 0,
+            pending: VecDeque::new(),
+            last_tick: None,
+            last_event_tick: None,
+            history: VecDeque::new(),
+            time: 0.0,
+            since_hello: 0.0,
+        };
+        client.send(&client.greeting())?;
+        Ok(client)
+    }
+
+    /// Tell the server we're leaving, so it doesn't have to wait for us to time out.
+    pub fn disconnect(&self) {
+        let _ = self.send(&ClientMessage::Bye);
+    }
+
+    fn greeting(&self) -> ClientMessage {
+        if self.spectating {
+            ClientMessage::Watch
+        } else {
+            ClientMessage::Hello
+        }
+    }
+
+    fn send(&self, msg: &ClientMessage) -> io::Result<()> {
+        self.socket.send_to(&msg.encode(), self.server).map(|_| ())
+    }
+
+    /// Send our ship's input to the server, move our ship, apply any snapshots that have arrived
+    /// and move the other ships. Returns the events that happened on the server which the world
+    /// hasn't already produced locally.
+    pub fn update(&mut self, world: &mut World, time_delta: f64) -> io::Result<Vec<GameEvent>> {
+        self.time += time_delta;
+        self.since_hello += time_delta;
+        let interval = if self.mode.is_none() {
+            HELLO_INTERVAL
+        } else if self.spectating {
+            KEEPALIVE_INTERVAL
+        } else {
+            // the inputs are enough
+            f64::INFINITY
+        };
+        if self.since_hello >= interval {
+            self.since_hello = 0.0;
+            self.send(&self.greeting())?;
+        }
+
+        world.move_planets(time_delta);
+        if let Some(i) = self.ship {
+            if i < world.ships.len() {
+                let input = InputState::from_input(&world.ships[i].input);
+                self.seq = self.seq.wrapping_add(1);
+                self.send(&ClientMessage::Input {
+                        seq: self.seq,
+                        input: input,
+                    })?;
+                self.pending.push_back((self.seq, input, time_delta));
+                if self.pending.len() > MAX_PENDING_INPUTS {
+                    self.pending.pop_front();
+                }
+                world.predict_ship(i, time_delta);
+            }
+        }
+
+        let events = self.receive(world)?;
+        self.interpolate(world);
+        let ship_positions = world.get_ship_positions();
+        world.space.focus(&ship_positions);
+        Ok(events)
+    }
+
+    fn receive(&mut self, world: &mut World) -> io::Result<Vec<GameEvent>> {
+        let mut buf = [0; MAX_PACKET_SIZE];
+        let mut latest = None;
+        let mut events = vec![];
+        loop {
+            let (len, from) = match self.socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            };
+            if from != self.server {
+                continue;
+            }
+            match ServerMessage::decode(&buf[..len]) {
+                Ok(ServerMessage::Welcome { mode }) => {
+                    self.mode = Some(mode);
+                    world.mode = mode;
+                }
+                Ok(ServerMessage::Full) => self.full = true,
+                Ok(ServerMessage::Snapshot(snapshot)) => {
+                    // packets can arrive out of order; anything older than what we have is no use
+                    if self.last_tick.map_or(true, |tick| snapshot.tick > tick) {
+                        self.last_tick = Some(snapshot.tick);
+                        let me = snapshot.your_ship.map(|ship| ship as usize);
+                        let seen = self.last_event_tick;
+                        for &(tick, event) in snapshot.events.iter() {
+                            if seen.map_or(true, |seen| tick > seen) {
+                                self.last_event_tick = Some(tick);
+                                if !is_predicted(&event, me) {
+                                    events.push(event);
+                                }
+                            }
+                        }
+                        latest = Some(snapshot);
+                    }
+                }
+                // one bad packet isn't worth giving up on the server for
+                Err(_) => {}
+            }
+        }
+        if let Some(snapshot) = latest {
+            self.apply(world, snapshot);
+        }
+        Ok(events)
+    }
+
+    /// Make the world match a snapshot, then re-apply the inputs the server hadn't seen yet to our
+    /// own ship.
+    fn apply(&mut self, world: &mut World, snapshot: Snapshot) {
+        if snapshot.seed != world.seed || snapshot.level != world.level {
+            self.history.clear();
+        }
+        snapshot.sync(world);
+
+        match snapshot.your_ship.map(|ship| ship as usize) {
+            Some(me) if me < snapshot.ships.len() => {
+                self.ship = Some(me);
+                let acked = snapshot.last_input;
+                while self.pending.front().map_or(false, |&(seq, _, _)| seq <= acked) {
+                    self.pending.pop_front();
+                }
+                snapshot.ships[me].apply(world, me, false);
+                let current = InputState::from_input(&world.ships[me].input);
+                for &(_, input, time_delta) in self.pending.iter() {
+                    input.apply(&mut world.ships[me].input);
+                    world.replay_ship(me, time_delta);
+                }
+                current.apply(&mut world.ships[me].input);
+            }
+            // spectating
+            _ => {}
+        }
+
+        self.history.push_back((self.time, snapshot.ships));
+        if self.history.len() > MAX_HISTORY {
+            self.history.pop_front();
+        }
+    }
+
+    /// Put the other ships where they were `INTERPOLATION_DELAY` seconds ago.
+    fn interpolate(&self, world: &mut World) {
+        let render_time = self.time - INTERPOLATION_DELAY;
+        let before = self.history.iter().rposition(|&(time, _)| time <= render_time).unwrap_or(0);
+        let (from, to, t) = match self.history.get(before + 1) {
+            Some(&(to_time, _)) => {
+                let from_time = self.history[before].0;
+                (before, before + 1, (render_time - from_time) / (to_time - from_time))
+            }
+            None if self.history.is_empty() => return,
+            // we've run out of snapshots; leave the ships at the last one
+            None => (before, before, 0.0),
+        };
+        let t = t.max(0.0).min(1.0);
+        let ref old = self.history[from].1;
+        let ref new = self.history[to].1;
+        for i in 0..world.ships.len() {
+            if Some(i) == self.ship || i >= old.len() || i >= new.len() {
+                continue;
+            }
+            new[i].apply(world, i, true);
+            // blending between planets would make the ship cut across space
+            if old[i].attached == new[i].attached {
+                let ref mut ship = world.ships[i];
+                ship.rotation = lerp_angle(old[i].rotation, new[i].rotation, t);
+                ship.height = lerp(old[i].height, new[i].height, t);
+            }
+        }
+    }
+}
+
+/// Events for our own ship that prediction already produced, and level resets which `apply`
+/// produces when it loads the level.
+fn is_predicted(event: &GameEvent, me: Option<usize>) -> bool {
+    match *event {
+        GameEvent::Shot(ship) |
+        GameEvent::Jumped(ship) |
+        GameEvent::Landed { ship, .. } |
+        GameEvent::Bounced { ship, .. } |
+        GameEvent::Attached { ship, .. } |
+        GameEvent::PickedUp { ship, .. } |
+        GameEvent::Pounded { ship, .. } => Some(ship) == me,
+        GameEvent::LevelReset => true,
+        _ => false,
+    }
+}
@@ -0,0 +1,133 @@
+/// The knobs that make a level harder or easier. The space is generated from these, so every
+/// machine playing a level needs the same ones as well as the same seed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Difficulty {
+    pub num_planets: u32, // not counting the first one
+    pub crawler_percentage: f64, // the chance of a planet having a crawler on it
+    pub crawler_speed: f64, // radians per second
+    pub min_planet_distance: f64,
+    pub max_planet_distance: f64,
+    pub path_variance: f64, // how much the path can turn at each planet, as a fraction of π
+}
+
+impl Difficulty {
+    pub fn easy() -> Difficulty {
+        Difficulty {
+            num_planets: 2,
+            crawler_percentage: 0.25,
+            crawler_speed: 1.5,
+            min_planet_distance: 150.0,
+            max_planet_distance: 300.0,
+            path_variance: 0.05,
+        }
+    }
+
+    pub fn normal() -> Difficulty {
+        Difficulty {
+            num_planets: 3,
+            crawler_percentage: 0.5,
+            crawler_speed: 2.0,
+            min_planet_distance: 150.0,
+            max_planet_distance: 400.0,
+            path_variance: 0.1,
+        }
+    }
+
+    pub fn hard() -> Difficulty {
+        Difficulty {
+            num_planets: 5,
+            crawler_percentage: 0.75,
+            crawler_speed: 3.0,
+            min_planet_distance: 200.0,
+            max_planet_distance: 550.0,
+            path_variance: 0.2,
+        }
+    }
+
+    /// Somewhere between two difficulties: `t` of 0 is `a`, 1 is `b`.
+    pub fn lerp(a: &Difficulty, b: &Difficulty, t: f64) -> Difficulty {
+        let mix = |x: f64, y: f64| x + (y - x) * t;
+        Difficulty {
+            num_planets: mix(a.num_planets as f64, b.num_planets as f64).round() as u32,
+            crawler_percentage: mix(a.crawler_percentage, b.crawler_percentage),
+            crawler_speed: mix(a.crawler_speed, b.crawler_speed),
+            min_planet_distance: mix(a.min_planet_distance, b.min_planet_distance),
+            max_planet_distance: mix(a.max_planet_distance, b.max_planet_distance),
+            path_variance: mix(a.path_variance, b.path_variance),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Preset {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Preset {
+    pub fn difficulty(&self) -> Difficulty {
+        match *self {
+            Preset::Easy => Difficulty::easy(),
+            Preset::Normal => Difficulty::normal(),
+            Preset::Hard => Difficulty::hard(),
+        }
+    }
+
+    /// Where the preset is on the adaptive scale.
+    fn rating(&self) -> f64 {
+        match *self {
+            Preset::Easy => 0.0,
+            Preset::Normal => 1.0,
+            Preset::Hard => 2.0,
+        }
+    }
+}
+
+// how many levels count as recent
+const RECENT_LEVELS: usize = 5;
+// the adaptive difficulty aims for the player to win this often
+const TARGET_WIN_RATE: f64 = 0.6;
+// how quickly the difficulty follows the win rate, in presets per level
+const ADAPT_RATE: f64 = 0.5;
+const MAX_RATING: f64 = 2.0;
+
+/// Adaptive difficulty makes the next level harder when the player has been winning and easier
+/// when they've been dying. It keeps a rating that slides between easy (0), normal (1) and hard
+/// (2), and moves it after each level by how far the recent win rate is from the target.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Adaptive {
+    rating: f64,
+    recent: Vec<bool>, // whether each recent level was won, oldest first
+}
+
+impl Adaptive {
+    pub fn new(start: Preset) -> Adaptive {
+        Adaptive {
+            rating: start.rating(),
+            recent: vec![],
+        }
+    }
+
+    /// Record the end of a level.
+    pub fn record(&mut self, won: bool) {
+        self.recent.push(won);
+        if self.recent.len() > RECENT_LEVELS {
+            self.recent.remove(0);
+        }
+        let wins = self.recent.iter().filter(|&&won| won).count();
+        let win_rate = wins as f64 / self.recent.len() as f64;
+        self.rating = (self.rating + (win_rate - TARGET_WIN_RATE) * ADAPT_RATE)
+            .max(0.0)
+            .min(MAX_RATING);
+    }
+
+    /// The difficulty for the next level.
+    pub fn difficulty(&self) -> Difficulty {
+        if self.rating < 1.0 {
+            Difficulty::lerp(&Difficulty::easy(), &Difficulty::normal(), self.rating)
+        } else {
+            Difficulty::lerp(&Difficulty::normal(), &Difficulty::hard(), self.rating - 1.0)
+        }
+    }
+}
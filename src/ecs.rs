@@ -0,0 +1,430 @@
+use std::iter;
+use std::slice;
+
+use calc::{Point, pt, rotated_position};
+use space::PlanetIndex;
+use ship::{SHIP_SIZE, BULLET_SIZE, BULLET_SPEED};
+use world::{CRAWLER_SIZE, FUEL_CAN_SIZE};
+
+/// A handle to an entity. Slots are reused once an entity is gone, so the handle remembers which
+/// generation of its slot it was, and which `Entities` it came from; a handle that's gone stale
+/// finds nothing rather than finding whatever took its place.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct Entity {
+    owner: usize,
+    idx: usize,
+    generation: u32,
+}
+
+impl Entity {
+    /// Which slot the entity is in. Entities made in the same order get the same slots, so this is
+    /// how the server and the clients agree on which crawler is which.
+    pub fn get_index(&self) -> usize {
+        self.idx
+    }
+}
+
+/// Where an entity is and which way it's facing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub pos: Point,
+    pub rotation: f64,
+}
+
+/// Moves an entity in a straight line, in distance per second.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Velocity(pub Point);
+
+/// Keeps an entity `height` above the surface of a planet at angle `rotation`, so it goes
+/// wherever the planet goes. Its transform is worked out from this.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Attachment {
+    pub planet: PlanetIndex,
+    pub rotation: f64,
+    pub height: f64,
+}
+
+/// A circle that things can hit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Collider {
+    pub radius: f64,
+}
+
+/// Kills ships that touch it, and can be shot.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hazard;
+
+/// Fired by a ship. It kills the hazards it hits, and in versus the other ships.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Projectile {
+    pub owner: usize, // the ship that fired it
+}
+
+/// A ship or a planet. They keep their own state, a ship in its flight model and a planet in its
+/// area, and their transforms and colliders are brought up to date from it at the start of every
+/// `World::update`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Body {
+    Ship(usize), // which one, in `World::ships`
+    Planet,
+}
+
+/// Something a ship gets by flying into it. It's gone once it's been picked up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pickup {
+    Fuel(f64), // how much
+}
+
+/// How to draw an entity.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Renderable {
+    Crawler { facing_right: bool },
+    /// In the colour of the ship that fired it.
+    Bullet,
+    FuelCan,
+}
+
+/// What an entity does by itself.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ai {
+    /// Walk round and round the planet it's attached to, at the level's crawler speed.
+    Crawl { moves_right: bool },
+}
+
+/// One kind of component, kept by entity slot. Each component remembers the generation of the
+/// entity it belongs to, so a stale handle can't get at the next entity's.
+#[derive(Debug)]
+pub struct Storage<T> {
+    owner: usize,
+    items: Vec<Option<(u32, T)>>,
+}
+
+impl<T> Storage<T> {
+    fn new(owner: usize) -> Storage<T> {
+        Storage {
+            owner: owner,
+            items: vec![],
+        }
+    }
+
+    pub fn insert(&mut self, entity: Entity, component: T) {
+        if entity.owner != self.owner {
+            return;
+        }
+        while self.items.len() <= entity.idx {
+            self.items.push(None);
+        }
+        self.items[entity.idx] = Some((entity.generation, component));
+    }
+
+    pub fn remove(&mut self, entity: Entity) -> Option<T> {
+        if self.get(entity).is_none() {
+            return None;
+        }
+        self.items[entity.idx].take().map(|(_, component)| component)
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        if entity.owner != self.owner {
+            return None;
+        }
+        match self.items.get(entity.idx) {
+            Some(&Some((generation, ref component))) if generation == entity.generation => {
+                Some(component)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        if entity.owner != self.owner {
+            return None;
+        }
+        match self.items.get_mut(entity.idx) {
+            Some(&mut Some((generation, ref mut component))) if generation == entity.generation => {
+                Some(component)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.get(entity).is_some()
+    }
+
+    /// Every entity with this component, and the component, in slot order. A system that changes
+    /// the entities as it goes has to collect the handles it wants first.
+    pub fn iter<'a>(&'a self) -> Iter<'a, T> {
+        Iter {
+            owner: self.owner,
+            items: self.items.iter().enumerate(),
+        }
+    }
+
+    /// Every entity with this component, in slot order.
+    pub fn entities(&self) -> Vec<Entity> {
+        self.iter().map(|(entity, _)| entity).collect()
+    }
+}
+
+/// The entities in a `Storage`, with their components.
+pub struct Iter<'a, T: 'a> {
+    owner: usize,
+    items: iter::Enumerate<slice::Iter<'a, Option<(u32, T)>>>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = (Entity, &'a T);
+
+    fn next(&mut self) -> Option<(Entity, &'a T)> {
+        while let Some((idx, item)) = self.items.next() {
+            if let Some((generation, ref component)) = *item {
+                return Some((Entity {
+                                 owner: self.owner,
+                                 idx: idx,
+                                 generation: generation,
+                             },
+                             component));
+            }
+        }
+        None
+    }
+}
+
+/// Entities holds everything in a level: the ships and the planets, the crawlers, the bullets
+/// and the fuel cans, and whatever else comes along. An entity is only a handle; what it is comes
+/// from which components it has, and the systems (here and in `World`) work on the components
+/// rather than on kinds of thing, so a new kind of thing can usually be made out of the
+/// components there already are. Ships and planets are bodies, whose components follow the
+/// `Ship` or `Planet` they stand for.
+#[derive(Debug)]
+pub struct Entities {
+    id: usize, // the id of the space they're in, which every handle carries
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    free: Vec<usize>, // dead slots, to be reused
+    pub transforms: Storage<Transform>,
+    pub velocities: Storage<Velocity>,
+    pub attachments: Storage<Attachment>,
+    pub colliders: Storage<Collider>,
+    pub hazards: Storage<Hazard>,
+    pub projectiles: Storage<Projectile>,
+    pub pickups: Storage<Pickup>,
+    pub renderables: Storage<Renderable>,
+    pub ais: Storage<Ai>,
+    pub bodies: Storage<Body>,
+}
+
+impl Entities {
+    pub fn new(id: usize) -> Entities {
+        Entities {
+            id: id,
+            generations: vec![],
+            alive: vec![],
+            free: vec![],
+            transforms: Storage::new(id),
+            velocities: Storage::new(id),
+            attachments: Storage::new(id),
+            colliders: Storage::new(id),
+            hazards: Storage::new(id),
+            projectiles: Storage::new(id),
+            pickups: Storage::new(id),
+            renderables: Storage::new(id),
+            ais: Storage::new(id),
+            bodies: Storage::new(id),
+        }
+    }
+
+    /// Make a new entity with no components.
+    pub fn spawn(&mut self) -> Entity {
+        let idx = match self.free.pop() {
+            Some(idx) => {
+                self.generations[idx] += 1;
+                self.alive[idx] = true;
+                idx
+            }
+            None => {
+                self.generations.push(0);
+                self.alive.push(true);
+                self.generations.len() - 1
+            }
+        };
+        Entity {
+            owner: self.id,
+            idx: idx,
+            generation: self.generations[idx],
+        }
+    }
+
+    /// Remove an entity and all its components. Returns false if it was already gone.
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        if !self.is_alive(entity) {
+            return false;
+        }
+        self.transforms.remove(entity);
+        self.velocities.remove(entity);
+        self.attachments.remove(entity);
+        self.colliders.remove(entity);
+        self.hazards.remove(entity);
+        self.projectiles.remove(entity);
+        self.pickups.remove(entity);
+        self.renderables.remove(entity);
+        self.ais.remove(entity);
+        self.bodies.remove(entity);
+        self.alive[entity.idx] = false;
+        self.free.push(entity.idx);
+        true
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        entity.owner == self.id && self.generations.get(entity.idx) == Some(&entity.generation) &&
+        self.alive[entity.idx]
+    }
+
+    /// The live entity in a slot, if there is one. Used to find what a slot number from the
+    /// server refers to.
+    pub fn in_slot(&self, idx: usize) -> Option<Entity> {
+        match self.alive.get(idx) {
+            Some(&true) => {
+                Some(Entity {
+                    owner: self.id,
+                    idx: idx,
+                    generation: self.generations[idx],
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn get_pos(&self, entity: Entity) -> Option<Point> {
+        self.transforms.get(entity).map(|transform| transform.pos)
+    }
+
+    /// The body of ship `i`, at `pos` facing `rotation`.
+    pub fn spawn_ship(&mut self, i: usize, pos: Point, rotation: f64) -> Entity {
+        let entity = self.spawn();
+        self.transforms.insert(entity,
+                               Transform {
+                                   pos: pos,
+                                   rotation: rotation,
+                               });
+        self.colliders.insert(entity, Collider { radius: SHIP_SIZE / 2.0 });
+        self.bodies.insert(entity, Body::Ship(i));
+        entity
+    }
+
+    /// The body of a planet at `pos`.
+    pub fn spawn_planet(&mut self, pos: Point, radius: f64) -> Entity {
+        let entity = self.spawn();
+        self.transforms.insert(entity,
+                               Transform {
+                                   pos: pos,
+                                   rotation: 0.0,
+                               });
+        self.colliders.insert(entity, Collider { radius: radius });
+        self.bodies.insert(entity, Body::Planet);
+        entity
+    }
+
+    /// A crawler: a hazard that walks around a planet. Its transform is set once the space knows
+    /// where the planet is.
+    pub fn spawn_crawler(&mut self,
+                         planet: PlanetIndex,
+                         rotation: f64,
+                         moves_right: bool)
+                         -> Entity {
+        let entity = self.spawn();
+        self.attachments.insert(entity,
+                                Attachment {
+                                    planet: planet,
+                                    rotation: rotation,
+                                    height: CRAWLER_SIZE,
+                                });
+        self.colliders.insert(entity, Collider { radius: CRAWLER_SIZE });
+        self.hazards.insert(entity, Hazard);
+        self.renderables.insert(entity, Renderable::Crawler { facing_right: moves_right });
+        self.ais.insert(entity, Ai::Crawl { moves_right: moves_right });
+        entity
+    }
+
+    /// A bullet fired by ship `owner` from `pos`, heading in `dir`.
+    pub fn spawn_bullet(&mut self, owner: usize, pos: Point, dir: f64) -> Entity {
+        let entity = self.spawn();
+        self.place_bullet(entity, pos, dir);
+        self.colliders.insert(entity, Collider { radius: BULLET_SIZE });
+        self.projectiles.insert(entity, Projectile { owner: owner });
+        self.renderables.insert(entity, Renderable::Bullet);
+        entity
+    }
+
+    /// Put a bullet at `pos`, heading in `dir`.
+    pub fn place_bullet(&mut self, bullet: Entity, pos: Point, dir: f64) {
+        self.transforms.insert(bullet,
+                               Transform {
+                                   pos: pos,
+                                   rotation: dir,
+                               });
+        let vel = rotated_position(pt(0.0, 0.0), dir, BULLET_SPEED);
+        self.velocities.insert(bullet, Velocity(vel));
+    }
+
+    /// A can of `fuel` floating at `pos`.
+    pub fn spawn_fuel_can(&mut self, pos: Point, fuel: f64) -> Entity {
+        let entity = self.spawn();
+        self.transforms.insert(entity,
+                               Transform {
+                                   pos: pos,
+                                   rotation: 0.0,
+                               });
+        self.colliders.insert(entity, Collider { radius: FUEL_CAN_SIZE });
+        self.pickups.insert(entity, Pickup::Fuel(fuel));
+        self.renderables.insert(entity, Renderable::FuelCan);
+        entity
+    }
+
+    /// The bullets ship `owner` has fired that are still going.
+    pub fn projectiles_of(&self, owner: usize) -> Vec<Entity> {
+        self.projectiles
+            .iter()
+            .filter(|&(_, projectile)| projectile.owner == owner)
+            .map(|(entity, _)| entity)
+            .collect()
+    }
+
+    /// Everything attached to a planet.
+    pub fn attached_to(&self, planet: PlanetIndex) -> Vec<Entity> {
+        self.attachments
+            .iter()
+            .filter(|&(_, attachment)| attachment.planet == planet)
+            .map(|(entity, _)| entity)
+            .collect()
+    }
+
+    /// The AI system: let each of `entities` that has an AI do what it does.
+    pub fn update_ai(&mut self, entities: &[Entity], crawler_speed: f64, time_delta: f64) {
+        for &entity in entities.iter() {
+            match self.ais.get(entity) {
+                Some(&Ai::Crawl { moves_right }) => {
+                    if let Some(attachment) = self.attachments.get_mut(entity) {
+                        if moves_right {
+                            attachment.rotation += crawler_speed * time_delta;
+                        } else {
+                            attachment.rotation -= crawler_speed * time_delta;
+                        }
+                    }
+                }
+                None => {}
+            }
+        }
+    }
+
+    /// The velocity system: move everything that has a velocity along.
+    pub fn update_velocities(&mut self, time_delta: f64) {
+        for (entity, &Velocity(vel)) in self.velocities.iter() {
+            if let Some(transform) = self.transforms.get_mut(entity) {
+                transform.pos.x += vel.x * time_delta;
+                transform.pos.y += vel.y * time_delta;
+            }
+        }
+    }
+}
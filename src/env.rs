@@ -0,0 +1,263 @@
+use calc::{Point, pt, distance, direction_from_to};
+use space::{PlanetKind, PLANET_KINDS};
+use world::{World, Mode, GameEvent, TICK_TIME};
+
+pub use net::InputState as Action;
+
+/// How much game time one step covers: one tick of the world.
+pub const STEP_TIME: f64 = TICK_TIME;
+/// Episodes that go on longer than this many steps are cut off.
+pub const MAX_STEPS: u32 = 60 * 120;
+
+const WIN_REWARD: f64 = 100.0;
+const DEATH_REWARD: f64 = -100.0;
+const KILL_REWARD: f64 = 1.0;
+// for every unit the ship gets closer to the magic planet
+const PROGRESS_REWARD: f64 = 0.01;
+// for every step, so dawdling doesn't pay
+const STEP_REWARD: f64 = -0.001;
+
+/// A planet near the ship, as the ship sees it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlanetObservation {
+    pub offset: Point, // from the ship to the planet's center
+    pub radius: f64,
+    pub kind: PlanetKind,
+    pub attached: bool, // whether it's the planet the ship is attached to
+}
+
+/// What an agent gets to know about the game after each step. Positions are relative to the ship,
+/// so the same situation looks the same wherever it happens.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Observation {
+    pub pos: Point, // the only absolute position, in case an agent wants to keep a map
+    pub rotation: f64,
+    pub height: f64,
+    pub exit_speed: f64,
+    pub flying: bool,
+    pub jumping: bool,
+    pub heat: f64,
+    pub fuel: f64,
+    pub planets: Vec<PlanetObservation>, // closest first
+    pub bugs: Vec<Point>, // offsets from the ship, closest first
+    pub magic_planet_direction: f64,
+    pub magic_planet_distance: f64,
+}
+
+impl Observation {
+    fn new(world: &World) -> Observation {
+        let ref ship = world.ships[0];
+        let ship_pos = ship.get_pos(&world.space);
+        let offset = |p: Point| pt(p.x - ship_pos.x, p.y - ship_pos.y);
+        let length = |p: Point| distance(pt(0.0, 0.0), p);
+
+        let mut planets: Vec<PlanetObservation> = world.space
+            .get_nearby_planets()
+            .into_iter()
+            .map(|(idx, planet)| {
+                PlanetObservation {
+                    offset: offset(planet.pos),
+                    radius: planet.radius,
+                    kind: planet.kind,
+                    attached: idx == ship.attached_planet,
+                }
+            })
+            .collect();
+        planets.sort_by(|a, b| length(a.offset).partial_cmp(&length(b.offset)).unwrap());
+
+        let mut bugs: Vec<Point> = world.space
+            .get_nearby_hazards()
+            .into_iter()
+            .filter_map(|entity| world.space.entities.get_pos(entity))
+            .map(offset)
+            .collect();
+        bugs.sort_by(|a, b| length(*a).partial_cmp(&length(*b)).unwrap());
+
+        let magic_planet = world.space.get_magic_planet();
+        Observation {
+            pos: ship_pos,
+            rotation: ship.rotation,
+            height: ship.height,
+            exit_speed: ship.exit_speed,
+            flying: ship.flying,
+            jumping: ship.jumping,
+            heat: ship.heat,
+            fuel: ship.fuel,
+            planets: planets,
+            bugs: bugs,
+            magic_planet_direction: direction_from_to(ship_pos, magic_planet),
+            magic_planet_distance: distance(ship_pos, magic_planet),
+        }
+    }
+
+    /// Flatten the observation into a fixed number of numbers, for agents that want a vector.
+    /// Only the closest `max_planets` planets and `max_bugs` bugs are included; missing ones are
+    /// all zeroes. Angles are given as their sine and cosine so they don't jump at ±π, and a
+    /// planet's kind as one flag per kind.
+    pub fn to_vec(&self, max_planets: usize, max_bugs: usize) -> Vec<f64> {
+        let flag = |b: bool| if b { 1.0 } else { 0.0 };
+        let mut v = vec![self.rotation.sin(),
+                         self.rotation.cos(),
+                         self.height,
+                         self.exit_speed,
+                         flag(self.flying),
+                         flag(self.jumping),
+                         self.heat,
+                         self.fuel,
+                         self.magic_planet_direction.sin(),
+                         self.magic_planet_direction.cos(),
+                         self.magic_planet_distance];
+        for i in 0..max_planets {
+            match self.planets.get(i) {
+                Some(planet) => {
+                    v.extend_from_slice(&[planet.offset.x,
+                                          planet.offset.y,
+                                          planet.radius,
+                                          flag(planet.attached)]);
+                    v.extend(PLANET_KINDS.iter().map(|&kind| flag(planet.kind == kind)));
+                }
+                None => v.extend(vec![0.0; 4 + PLANET_KINDS.len()]),
+            }
+        }
+        for i in 0..max_bugs {
+            match self.bugs.get(i) {
+                Some(bug) => v.extend_from_slice(&[bug.x, bug.y]),
+                None => v.extend_from_slice(&[0.0; 2]),
+            }
+        }
+        v
+    }
+}
+
+/// Env wraps a one-ship world in the usual reinforcement learning interface: `reset` starts an
+/// episode on a level and `step` plays one tick of it. An episode ends when the ship reaches the
+/// magic planet, dies, or runs out of steps. Nothing here needs a window, so it can run as fast as
+/// the simulation allows.
+pub struct Env {
+    world: World,
+    steps: u32,
+    max_steps: u32,
+    last_distance: f64,
+    done: bool,
+}
+
+impl Env {
+    pub fn new() -> Env {
+        Env::with_max_steps(MAX_STEPS)
+    }
+
+    pub fn with_max_steps(max_steps: u32) -> Env {
+        let mut env = Env {
+            world: World::new(Mode::Solo, 1, 0),
+            steps: 0,
+            max_steps: max_steps,
+            last_distance: 0.0,
+            done: false,
+        };
+        env.reset(0);
+        env
+    }
+
+    /// Start a new episode on the level generated from `seed`.
+    pub fn reset(&mut self, seed: u32) -> Observation {
+        self.world = World::new(Mode::Solo, 1, seed);
+        // a finished level stays where it finished instead of going on to the next one, so the
+        // last observation of an episode is of how it ended
+        self.world.stop_at_level_end = true;
+        let ship_positions = self.world.get_ship_positions();
+        self.world.space.focus(&ship_positions);
+        self.steps = 0;
+        self.done = false;
+        let observation = Observation::new(&self.world);
+        self.last_distance = observation.magic_planet_distance;
+        observation
+    }
+
+    /// Play one step with the given controls. Returns what the ship sees afterwards, the reward
+    /// for the step and whether the episode is over. Once it's over, stepping again does nothing
+    /// and just gives the last observation back, with no reward, until `reset` is called.
+    pub fn step(&mut self, action: &Action) -> (Observation, f64, bool) {
+        if self.done {
+            return (Observation::new(&self.world), 0.0, true);
+        }
+        action.apply(&mut self.world.ships[0].input);
+        self.world.update(STEP_TIME);
+        self.steps += 1;
+
+        let mut reward = STEP_REWARD;
+        let observation = Observation::new(&self.world);
+        for event in self.world.drain_events() {
+            match event {
+                GameEvent::MagicPlanetReached(_) => {
+                    reward += WIN_REWARD;
+                    self.done = true;
+                }
+                GameEvent::ShipHit { .. } => {
+                    reward += DEATH_REWARD;
+                    self.done = true;
+                }
+                GameEvent::BugKilled { .. } => reward += KILL_REWARD,
+                _ => {}
+            }
+        }
+        reward += (self.last_distance - observation.magic_planet_distance) * PROGRESS_REWARD;
+        self.last_distance = observation.magic_planet_distance;
+        if self.steps >= self.max_steps {
+            self.done = true;
+        }
+        (observation, reward, self.done)
+    }
+
+    /// The world being played, for rendering or inspecting it.
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    pub fn steps(&self) -> u32 {
+        self.steps
+    }
+}
+
+/// An action that does nothing.
+pub fn no_action() -> Action {
+    Action {
+        left: false,
+        right: false,
+        up: false,
+        down: false,
+        jump: false,
+        shooting: false,
+        attach: false,
+        dash: false,
+        shoot_target: None,
+    }
+}
+
+/// A `shoot_target` for shooting at `angle` radians from the ship, for agents that would rather
+/// not work in the world's coordinates.
+pub fn aim(observation: &Observation, angle: f64) -> Option<Point> {
+    Some(pt(observation.pos.x + angle.cos() * 100.0,
+            observation.pos.y + angle.sin() * 100.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stepping_a_finished_episode_gives_the_last_observation() {
+        let mut env = Env::with_max_steps(3);
+        let mut last = None;
+        for _ in 0..3 {
+            let (observation, _, done) = env.step(&no_action());
+            last = Some((observation, done));
+        }
+        let (observation, done) = last.unwrap();
+        assert!(done);
+        assert_eq!(env.step(&no_action()), (observation, 0.0, true));
+        assert_eq!(env.steps(), 3);
+
+        env.reset(0);
+        assert!(!env.step(&no_action()).2);
+    }
+}
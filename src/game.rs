@@ -1,358 +1,727 @@
 use std::f64::consts::PI;
+use std::io;
+use std::path::{Path, PathBuf};
 use piston_window::{Event, G2dTexture, TextureSettings, UpdateArgs, PistonWindow, Texture, Size,
-                    Window, MouseButton, Button, Key, MouseCursorEvent, PressEvent, ReleaseEvent};
-use ncollide::query;
-use ncollide::shape::Ball;
+                    Window, MouseButton, Button, Key, MouseCursorEvent, MouseScrollEvent,
+                    PressEvent, ReleaseEvent};
+use rand;
 
 use im;
 
-use space::{Space, Bullet, PlanetIndex, MAGIC_PLANET_SIZE};
-use calc::{Point, pt, rotated_position, shrink_to_bounds, coll_pt, direction_from_to, lerp};
-
-pub const SHIP_SIZE: f64 = 50.0;
-pub const CRAWLER_SIZE: f64 = 25.0;
-pub const CRAWLER_SPEED: f64 = 2.0;
-pub const SPEED: f64 = 5.0;
-pub const FLY_SPEED: f64 = 3.0;
-pub const JUMP_SPEED: f64 = 10.0;
-pub const AIR_CONTROL_MOD: f64 = 0.40;
-pub const BULLET_SPEED: f64 = 1000.0;
-pub const BULLET_SIZE: f64 = 5.0;
-pub const ACCELERATION: f64 = 5.0;
-pub const FIRE_COOLDOWN: f64 = 0.1;
-pub const GRAVITY: f64 = 20.0;
+use circles::space::{Space, Planet, MAGIC_PLANET_SIZE};
+use circles::calc::{Point, pt, shrink_to_bounds, lerp, distance};
+use circles::ship::Ship;
+use circles::world::{World, Mode, GameEvent, TICK_TIME};
+use circles::net::DEFAULT_PORT;
+use circles::client::Client;
+use circles::replay::{Recorder, Replay};
+use circles::autopilot::Autopilot;
+use circles::difficulty::{Preset, Adaptive};
+use circles::generator::Generator;
+use particles::{ParticleSystem, EXHAUST, SPARKLE};
+use starfield::Starfield;
+use sprites::Sprites;
+use audio::Audio;
+use profile::Profile;
+use interpolation::Interpolation;
+use hud::Hud;
+use shop::Shop;
+use render::planet_color;
+
 pub const MINI_SIZE: f64 = 200.0;
+// the magic planet only sparkles when the ship is this close to it
+pub const SPARKLE_DISTANCE: f64 = 3000.0;
+// how fast the spectator's free camera moves, in pixels per second
+pub const PAN_SPEED: f64 = 800.0;
+// how much the spectator's zoom changes per second while a zoom key is held
+pub const ZOOM_SPEED: f64 = 1.0;
+// how much one notch of the mouse wheel zooms
+pub const ZOOM_STEP: f64 = 1.1;
+pub const MIN_ZOOM: f64 = 0.1;
+pub const MAX_ZOOM: f64 = 4.0;
+// the most ticks to run in one update. Any more and the game slows down rather than trying to
+// catch up, which would only make it fall further behind.
+const MAX_TICKS_PER_UPDATE: u32 = 10;
+
+pub const PLAYER_COLORS: [[f32; 4]; 8] = [[1.0, 0.0, 0.0, 1.0],
+                                          [1.0, 0.5, 0.0, 1.0],
+                                          [1.0, 1.0, 0.0, 1.0],
+                                          [0.0, 1.0, 1.0, 1.0],
+                                          [1.0, 0.0, 1.0, 1.0],
+                                          [0.5, 1.0, 0.0, 1.0],
+                                          [1.0, 1.0, 1.0, 1.0],
+                                          [0.6, 0.3, 0.0, 1.0]];
+
+/// The colour of ship `i`. There are only so many colours, so big games repeat them.
+pub fn player_color(i: usize) -> [f32; 4] {
+    PLAYER_COLORS[i % PLAYER_COLORS.len()]
+}
 
-pub struct GameInput {
+/// Input that isn't for any particular ship.
+pub struct AppInput {
     pub toggle_debug: bool,
-    pub left: bool,
-    pub right: bool,
-    pub down: bool,
-    pub up: bool,
-    pub jump: bool,
-    pub jumped: bool,
-    pub shoot_target: Option<Point>,
-    pub shooting: bool,
-    pub attach: bool,
-    pub cursor: Option<[f64; 2]>,
+    pub toggle_stats: bool,
+    pub toggle_mute: bool,
+    pub volume_up: bool,
+    pub volume_down: bool,
+    pub start: bool, // leave the title screen
 }
 
-impl GameInput {
-    pub fn new() -> GameInput {
-        GameInput {
+impl AppInput {
+    pub fn new() -> AppInput {
+        AppInput {
             toggle_debug: false,
-            left: false,
-            right: false,
-            down: false,
-            up: false,
-            jump: false,
-            jumped: false,
-            shoot_target: None,
-            attach: false,
-            cursor: None,
-            shooting: false,
+            toggle_stats: false,
+            toggle_mute: false,
+            volume_up: false,
+            volume_down: false,
+            start: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Left,
+    Right,
+    Up,
+    Down,
+    Jump,
+    Shoot,
+    Attach,
+    Dash,
+}
+
+/// Which keys control a ship. Only one ship can use the mouse, and it has to be the first one,
+/// because mouse coordinates are taken relative to the left edge of the window.
+pub struct Bindings {
+    pub keys: Vec<(Key, Action)>,
+    pub mouse: bool,
+}
+
+impl Bindings {
+    /// Everything, for when there's only one player.
+    pub fn solo() -> Bindings {
+        Bindings {
+            keys: vec![(Key::Left, Action::Left),
+                       (Key::A, Action::Left),
+                       (Key::Right, Action::Right),
+                       (Key::E, Action::Right),
+                       (Key::D, Action::Right),
+                       (Key::Up, Action::Up),
+                       (Key::Comma, Action::Up),
+                       (Key::W, Action::Up),
+                       (Key::Down, Action::Down),
+                       (Key::O, Action::Down),
+                       (Key::S, Action::Down),
+                       (Key::Space, Action::Jump),
+                       (Key::LShift, Action::Dash)],
+            mouse: true,
+        }
+    }
+
+    /// WASD, space, left shift to dash and the mouse.
+    pub fn player_one() -> Bindings {
+        Bindings {
+            keys: vec![(Key::A, Action::Left),
+                       (Key::D, Action::Right),
+                       (Key::W, Action::Up),
+                       (Key::S, Action::Down),
+                       (Key::Space, Action::Jump),
+                       (Key::LShift, Action::Dash)],
+            mouse: true,
+        }
+    }
+
+    /// The arrow keys, right shift to jump, return to shoot, right ctrl to attach and right alt to
+    /// dash.
+    pub fn player_two() -> Bindings {
+        Bindings {
+            keys: vec![(Key::Left, Action::Left),
+                       (Key::Right, Action::Right),
+                       (Key::Up, Action::Up),
+                       (Key::Down, Action::Down),
+                       (Key::RShift, Action::Jump),
+                       (Key::Return, Action::Shoot),
+                       (Key::RCtrl, Action::Attach),
+                       (Key::RAlt, Action::Dash)],
+            mouse: false,
+        }
+    }
+
+    pub fn for_mode(mode: Mode) -> Vec<Bindings> {
+        match mode {
+            Mode::Solo => vec![Bindings::solo()],
+            Mode::Race | Mode::Versus => vec![Bindings::player_one(), Bindings::player_two()],
         }
     }
+
+    fn action(&self, key: Key) -> Option<Action> {
+        self.keys.iter().find(|&&(k, _)| k == key).map(|&(_, action)| action)
+    }
+}
+
+/// One person playing at this computer: the ship they control, and how they control it. Each
+/// player gets their own column of the window.
+pub struct Player {
+    pub ship: usize,
+    pub bindings: Bindings,
+}
+
+/// What the spectator's camera is doing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CameraMode {
+    /// Panned around by hand.
+    Free,
+    /// Following a ship the way its player sees it.
+    Follow(usize),
+}
+
+/// Someone watching the game instead of playing: from a server, or a recording.
+pub struct Spectator {
+    pub camera: CameraMode,
+    pub camera_pos: Point,
+    pub zoom: f64,
+    // which of the camera keys are held down
+    pub pan_left: bool,
+    pub pan_right: bool,
+    pub pan_up: bool,
+    pub pan_down: bool,
+    pub zoom_in: bool,
+    pub zoom_out: bool,
+    pub scroll: f64, // mouse wheel notches since the last update
+}
+
+impl Spectator {
+    pub fn new() -> Spectator {
+        Spectator {
+            camera: CameraMode::Follow(0),
+            camera_pos: pt(0.0, 0.0),
+            zoom: 1.0,
+            pan_left: false,
+            pan_right: false,
+            pan_up: false,
+            pan_down: false,
+            zoom_in: false,
+            zoom_out: false,
+            scroll: 0.0,
+        }
+    }
+}
+
+/// The kind of game to play, from the command line.
+pub struct Settings {
+    pub mode: Mode,
+    pub preset: Preset,
+    pub adaptive: bool,
+    pub generator: Option<Generator>, // None to pick one for each level
+}
+
+impl Settings {
+    pub fn new() -> Settings {
+        Settings {
+            mode: Mode::Solo,
+            preset: Preset::Normal,
+            adaptive: false,
+            generator: Some(Generator::RandomWalk),
+        }
+    }
+}
+
+/// The title screen. While it's up, the autopilot plays a level in the background, and the game
+/// that starts when it's dismissed is played with `settings`.
+pub struct Title {
+    pub settings: Settings,
+    pub autopilot: Autopilot,
 }
 
 pub struct App {
-    pub input: GameInput,
+    pub input: AppInput,
     // meta-state? or something
     pub debug: bool,
+    pub show_stats: bool,
+    pub profile: Profile,
+    pub profile_path: Option<PathBuf>,
     // rendering state
     // glyphs: Glyphs
     pub minimap: G2dTexture,
     pub space_bounds: (Point, Point), // min and max
+    pub particles: ParticleSystem,
+    pub hud: Hud,
+    pub starfield: Starfield,
+    pub sprites: Sprites,
+    pub audio: Audio,
+    pub time: f64, // seconds since the game started, for animations
+    pub interpolation: Interpolation,
+    accumulator: f64, // time that hasn't been simulated yet, less than a tick
+    pub players: Vec<Player>,
+    pub spectator: Option<Spectator>,
+    pub title: Option<Title>,
+    pub shop: Option<Shop>, // open between levels
     // gameplay state
-    pub space: Space,
-    pub score: i8,
-    pub rotation: f64, // ship rotation / position along the orbit
-    pub flying: bool,
-    pub jumping: bool,
-    pub exit_speed: f64,
-    pub height: f64,
-    pub attached_planet: PlanetIndex,
-    pub closest_planet_coords: Point, // redundant data, optimization
-    // NES-style would be to make this a [(f64, f64); 3], so only three bullets can exist at once
-    pub bullets: Vec<Bullet>,
-    pub fire_cooldown: f64,
-    pub camera_pos: Point,
+    pub world: World,
+    pub net: Option<Client>, // the server, when playing or watching online
+    pub replay: Option<Replay>,
+    pub recorder: Option<Recorder>,
 }
 
 impl App {
-    pub fn new(mut window: &mut PistonWindow) -> Self {
-        // Create a new game and run it.
-        let space = Space::new();
-        let attached_planet_idx = space.get_first_planet();
-        let space_bounds = space.get_space_bounds();
+    /// Start on the title screen, with the autopilot flying around behind it. The game proper
+    /// starts with `start_game`.
+    pub fn new(window: &mut PistonWindow, assets: &Path, settings: Settings) -> Self {
+        let world = World::with_generator(Mode::Solo,
+                                          1,
+                                          rand::random(),
+                                          settings.preset.difficulty(),
+                                          settings.generator);
+        let mut app = App::with_world(window, assets, world, vec![]);
+        app.spectator = Some(Spectator::new());
+        app.title = Some(Title {
+            settings: settings,
+            autopilot: Autopilot::new(),
+        });
+        app
+    }
+
+    /// Leave the title screen and start a new game with the settings it was showing.
+    pub fn start_game(&mut self, window: &mut PistonWindow) {
+        let settings = match self.title.take() {
+            Some(title) => title.settings,
+            None => return,
+        };
+        self.players = Bindings::for_mode(settings.mode)
+            .into_iter()
+            .enumerate()
+            .map(|(i, bindings)| {
+                Player {
+                    ship: i,
+                    bindings: bindings,
+                }
+            })
+            .collect();
+        self.world = World::with_generator(settings.mode,
+                                           self.players.len(),
+                                           rand::random(),
+                                           settings.preset.difficulty(),
+                                           settings.generator);
+        if settings.adaptive {
+            self.world.adaptive = Some(Adaptive::new(settings.preset));
+        }
+        // the first player brings their credits and upgrades from the profile
+        self.world.shop = true;
+        self.world.stop_at_level_end = true;
+        if let Some(ship) = self.world.ships.first_mut() {
+            ship.credits = self.profile.credits;
+            ship.equip(self.profile.upgrades);
+        }
+        self.spectator = None;
+        self.particles.clear();
+        self.hud = Hud::new();
+        self.space_bounds = self.world.space.get_space_bounds();
+        self.minimap = generate_minimap(window, &self.world.space, self.space_bounds);
+    }
+
+    /// Join a game on a server. The world has no ships until the server sends the first
+    /// snapshot.
+    pub fn connect(window: &mut PistonWindow, assets: &Path, addr: &str) -> io::Result<Self> {
+        let client = Client::connect(with_port(addr).as_str())?;
+        let players = vec![Player {
+                               ship: 0,
+                               bindings: Bindings::solo(),
+                           }];
+        let mut app = App::with_world(window, assets, World::empty(Mode::Race), players);
+        app.net = Some(client);
+        Ok(app)
+    }
+
+    /// Watch a game on a server.
+    pub fn watch(window: &mut PistonWindow, assets: &Path, addr: &str) -> io::Result<Self> {
+        let client = Client::watch(with_port(addr).as_str())?;
+        let mut app = App::with_world(window, assets, World::empty(Mode::Race), vec![]);
+        app.net = Some(client);
+        app.spectator = Some(Spectator::new());
+        Ok(app)
+    }
+
+    /// Watch a game recorded with `record`.
+    pub fn replay(window: &mut PistonWindow, assets: &Path, path: &Path) -> io::Result<Self> {
+        let replay = Replay::load(path)?;
+        let mut app = App::with_world(window, assets, World::empty(Mode::Solo), vec![]);
+        app.replay = Some(replay);
+        app.spectator = Some(Spectator::new());
+        Ok(app)
+    }
+
+    /// Record everything that happens from now on to a file.
+    pub fn record(&mut self, path: &Path) -> io::Result<()> {
+        self.recorder = Some(Recorder::create(path)?);
+        Ok(())
+    }
+
+    fn with_world(mut window: &mut PistonWindow,
+                  assets: &Path,
+                  world: World,
+                  players: Vec<Player>)
+                  -> Self {
+        let space_bounds = world.space.get_space_bounds();
+        let profile_path = Profile::default_path();
+        let profile = match profile_path {
+            Some(ref path) => Profile::load(path),
+            None => Profile::default(),
+        };
         App {
-            input: GameInput::new(),
-            score: 0,
+            input: AppInput::new(),
             debug: false,
-            camera_pos: pt(-0.0, -0.0),
-            flying: false,
-            jumping: false,
-            fire_cooldown: 0.0,
-            exit_speed: 0.0,
-            height: space.get_planet(attached_planet_idx).radius,
-            rotation: 0.0,
-            attached_planet: attached_planet_idx,
-            closest_planet_coords: space.get_planet(attached_planet_idx).pos,
-            bullets: vec![],
+            show_stats: false,
+            profile: profile,
+            profile_path: profile_path,
             space_bounds: space_bounds,
-            minimap: generate_minimap(&mut window, &space, space_bounds),
-            space: space,
+            minimap: generate_minimap(&mut window, &world.space, space_bounds),
+            particles: ParticleSystem::new(),
+            hud: Hud::new(),
+            starfield: Starfield::new(0),
+            sprites: Sprites::load(&mut window, &assets.join("sprites")),
+            time: 0.0,
+            interpolation: Interpolation::new(),
+            accumulator: 0.0,
+            audio: Audio::new(&assets.join("sounds")),
+            players: players,
+            spectator: None,
+            title: None,
+            shop: None,
+            world: world,
+            net: None,
+            replay: None,
+            recorder: None,
+        }
+    }
+
+    /// The size of each player's part of the window. The window is split into side-by-side
+    /// columns. A spectator gets the whole window.
+    pub fn get_viewport_size(&self, window_size: Size) -> Size {
+        Size {
+            width: window_size.width / self.players.len().max(1) as u32,
+            height: window_size.height,
         }
     }
 
     pub fn update(&mut self, args: &UpdateArgs, window: &mut PistonWindow) {
-        self.input.shoot_target = if self.input.shooting {
-            self.input.cursor.map(|c| pt(self.camera_pos.x + c[0], self.camera_pos.y + c[1]))
-        } else {
-            None
-        };
+        self.time += args.dt;
 
+        if self.input.start {
+            self.start_game(window);
+            self.input.start = false;
+        }
+        if self.shop.as_ref().map_or(false, |shop| shop.all_ready()) {
+            self.close_shop();
+        }
         // annoyed that I need the whole mutable window for this function. Only because it's
         // necessary to create a texture.
-        let view_size = window.size();
+        let view_size = self.get_viewport_size(window.size());
         if self.input.toggle_debug {
             self.debug = !self.debug;
+            self.input.toggle_debug = false;
         }
-        let ship_pos = {
-            let attached_planet = self.space.get_planet(self.attached_planet);
-            rotated_position(attached_planet.pos, self.rotation, self.height)
-        };
-
-        // It would be nice if more of these methods took &self instead of &mut self, and we
-        // assigned the results
-        if let Some(target) = self.input.shoot_target {
-            self.update_shoot(target, ship_pos, args.dt)
+        if self.input.toggle_stats {
+            self.show_stats = !self.show_stats;
+            self.input.toggle_stats = false;
         }
+        self.update_audio_settings();
+        self.update_aim();
+
+        // the world moves on in whole ticks; what's left over is made up for by drawing partway
+        // between the last two
+        self.accumulator += args.dt;
+        let mut ticks = 0;
+        while self.accumulator >= TICK_TIME {
+            if ticks == MAX_TICKS_PER_UPDATE {
+                self.accumulator = 0.0;
+                break;
+            }
+            self.accumulator -= TICK_TIME;
+            self.tick(view_size, window);
+            ticks += 1;
+        }
+        self.interpolation.alpha = self.accumulator / TICK_TIME;
 
-        self.update_bullets(ship_pos, args.dt);
-        self.update_movement(args.dt);
-        let (closest_planet_idx, closest_planet_distance) = self.update_collision(window, ship_pos);
-        self.update_attach(closest_planet_idx, closest_planet_distance, ship_pos);
+        self.update_spectator(view_size, args.dt);
+        self.update_effects(args.dt);
+    }
 
-        // Put a bound on rotation, because maybe something bad will happen if someone spins in one
-        // direction for an hour
-        if self.rotation > PI {
-            self.rotation -= 2.0 * PI
-        }
-        if self.rotation < -PI {
-            self.rotation += 2.0 * PI
-        }
-        let on_planet = if self.flying || self.jumping {
-            None
+    /// Move the world on by one tick, and react to what happened.
+    fn tick(&mut self, view_size: Size, window: &mut PistonWindow) {
+        self.interpolation.capture(&self.world);
+        let server_events = if let Some(ref mut client) = self.net {
+            match client.update(&mut self.world, TICK_TIME) {
+                Ok(events) => events,
+                Err(e) => {
+                    println!("Network error: {}", e);
+                    vec![]
+                }
+            }
+        } else if let Some(ref mut replay) = self.replay {
+            replay.update(&mut self.world, TICK_TIME)
         } else {
-            Some(self.space.get_planet(self.attached_planet).pos)
+            if let Some(ref mut title) = self.title {
+                self.world.ships[0].input = title.autopilot.update(&self.world, 0, TICK_TIME);
+            }
+            self.world.update(TICK_TIME);
+            vec![]
         };
-        self.camera_pos = self.update_camera(view_size, on_planet, self.camera_pos, ship_pos);
-
-        self.update_bugs(window, ship_pos, args.dt);
-        self.space.focus(ship_pos);
+        if let Some(ref client) = self.net {
+            if let Some(ship) = client.ship {
+                self.players[0].ship = ship;
+            }
+        }
 
-        if self.input.attach {
-            self.input.attach = false;
+        let mut events = self.world.drain_events();
+        events.extend(server_events);
+        self.update_recorder(&events, TICK_TIME);
+        for event in events {
+            self.handle_event(event, window);
         }
-        if self.input.toggle_debug {
-            self.input.toggle_debug = false;
+        if self.world.level_over && self.shop.is_none() {
+            self.open_shop();
         }
+        self.update_players(view_size);
+    }
 
+    /// Stop between levels to let the players shop.
+    fn open_shop(&mut self) {
+        self.shop = Some(Shop::new(self.players.len()));
+        self.store_upgrades();
+        self.save_profile();
     }
 
-    fn update_shoot(&mut self, target: Point, ship_pos: Point, time_delta: f64) {
-        if self.fire_cooldown <= 0.0 {
-            let angle = direction_from_to(ship_pos, target);
-            let bullet = Bullet {
-                pos: ship_pos,
-                dir: angle,
-                speed: BULLET_SPEED,
-            };
-            self.bullets.push(bullet);
-            self.fire_cooldown = FIRE_COOLDOWN;
-        } else {
-            self.fire_cooldown -= time_delta;
-        }
-    }
-
-    fn update_bullets(&mut self, ship_pos: Point, time_delta: f64) {
-        let mut cull_bullets = vec![];
-        let mut cull_counter = 0;
-        for (idx, bullet) in self.bullets.iter_mut().enumerate() {
-            bullet.pos.x = bullet.pos.x + (bullet.speed * time_delta * bullet.dir.cos());
-            bullet.pos.y = bullet.pos.y + (bullet.speed * time_delta * bullet.dir.sin());
-            // kind of a dumb hack to determine when to cull bullets. It'd be better if we had
-            // the current view's bounding box (+ margin). or, alternatively, give each bullet
-            // a TTL.
-            if (bullet.pos.x - ship_pos.x).abs() > 5000.0 ||
-               (bullet.pos.y - ship_pos.y).abs() > 5000.0 {
-                // rejigger the index so when we delete the items they compensate for previous
-                // deletions
-                cull_bullets.push(idx - cull_counter);
-                cull_counter += 1;
-            }
+    /// Leave the shop for the next level.
+    fn close_shop(&mut self) {
+        self.shop = None;
+        self.store_upgrades();
+        self.save_profile();
+        self.world.next_level();
+    }
+
+    /// Copy the first player's credits and upgrades into the profile. Only local games have a
+    /// shop; online, the server's ships don't have upgrades.
+    fn store_upgrades(&mut self) {
+        if !self.world.shop {
+            return;
         }
-        for cull_idx in cull_bullets {
-            self.bullets.remove(cull_idx);
+        let i = match self.players.first() {
+            Some(player) => player.ship,
+            None => return,
+        };
+        if let Some(ship) = self.world.ships.get(i) {
+            self.profile.credits = ship.credits;
+            self.profile.upgrades = ship.upgrades;
         }
     }
 
-    fn update_movement(&mut self, time_delta: f64) {
-        if !self.flying {
-            if self.input.up {
-                self.flying = true;
-                self.exit_speed = FLY_SPEED;
-            }
-        } else {
-            self.height += self.exit_speed;
-            if self.input.down {
-                self.exit_speed -= ACCELERATION * time_delta;
-            }
-            if self.input.up {
-                self.exit_speed += ACCELERATION * time_delta;
-            }
+    fn update_recorder(&mut self, events: &[GameEvent], time_delta: f64) {
+        let result = match self.recorder {
+            Some(ref mut recorder) => recorder.record(&self.world, events, time_delta),
+            None => return,
+        };
+        if let Err(e) = result {
+            println!("Couldn't record the game, stopping recording: {}", e);
+            self.recorder = None;
         }
+    }
 
-        if !self.jumping {
-            if self.input.jump && !self.input.jumped {
-                self.jumping = true;
-                self.exit_speed = JUMP_SPEED;
-                self.input.jumped = true;
+    /// Zoom and move the spectator's camera.
+    fn update_spectator(&mut self, view_size: Size, time_delta: f64) {
+        let mut spectator = match self.spectator.take() {
+            Some(spectator) => spectator,
+            None => return,
+        };
+
+        // zoom around the middle of the view
+        let old_zoom = spectator.zoom;
+        let zoom_change = 1.0 + ZOOM_SPEED * time_delta;
+        if spectator.zoom_in {
+            spectator.zoom *= zoom_change;
+        }
+        if spectator.zoom_out {
+            spectator.zoom /= zoom_change;
+        }
+        spectator.zoom *= ZOOM_STEP.powf(spectator.scroll);
+        spectator.scroll = 0.0;
+        spectator.zoom = spectator.zoom.max(MIN_ZOOM).min(MAX_ZOOM);
+        let (half_width, half_height) = (view_size.width as f64 / 2.0,
+                                         view_size.height as f64 / 2.0);
+        spectator.camera_pos = pt(spectator.camera_pos.x + half_width / old_zoom -
+                                  half_width / spectator.zoom,
+                                  spectator.camera_pos.y + half_height / old_zoom -
+                                  half_height / spectator.zoom);
+
+        match spectator.camera {
+            CameraMode::Follow(i) if i < self.world.ships.len() => {
+                // how much of the world fits in the view at this zoom
+                let world_size = Size {
+                    width: (view_size.width as f64 / spectator.zoom) as u32,
+                    height: (view_size.height as f64 / spectator.zoom) as u32,
+                };
+                let ref ship = self.world.ships[i];
+                let ship_pos = ship.get_pos(&self.world.space);
+                let on_planet = if ship.in_air() {
+                    None
+                } else {
+                    Some(ship.get_planet(&self.world.space))
+                };
+                spectator.camera_pos =
+                    self.update_camera(world_size, on_planet, spectator.camera_pos, ship_pos);
             }
-        } else {
-            self.exit_speed -= GRAVITY * time_delta;
-            self.height += self.exit_speed;
-            if !self.input.jump {
-                if self.exit_speed > (JUMP_SPEED / 2.0) {
-                    self.exit_speed = JUMP_SPEED / 2.0;
+            _ => {
+                let pan = PAN_SPEED * time_delta / spectator.zoom;
+                if spectator.pan_left {
+                    spectator.camera_pos.x -= pan;
+                }
+                if spectator.pan_right {
+                    spectator.camera_pos.x += pan;
+                }
+                if spectator.pan_up {
+                    spectator.camera_pos.y -= pan;
+                }
+                if spectator.pan_down {
+                    spectator.camera_pos.y += pan;
                 }
             }
         }
+        self.spectator = Some(spectator);
+    }
 
-        if self.flying || self.jumping {
-            if self.input.left {
-                self.rotation -= SPEED * AIR_CONTROL_MOD * time_delta
+    /// Aim the mouse players' guns at the cursor.
+    fn update_aim(&mut self) {
+        for player in self.players.iter() {
+            if let Some(ship) = self.world.ships.get_mut(player.ship) {
+                if player.bindings.mouse {
+                    let camera_pos = ship.camera_pos;
+                    ship.input.shoot_target =
+                        ship.input.cursor.map(|c| pt(camera_pos.x + c[0], camera_pos.y + c[1]));
+                }
             }
-            if self.input.right {
-                self.rotation += SPEED * AIR_CONTROL_MOD * time_delta
+        }
+    }
+
+    /// Move each player's camera after their ship, and keep track of how far the first player
+    /// has flown.
+    fn update_players(&mut self, view_size: Size) {
+        for i in 0..self.players.len() {
+            let ship_idx = self.players[i].ship;
+            if ship_idx >= self.world.ships.len() {
+                continue;
             }
-        } else {
-            if self.input.left {
-                self.rotation -= SPEED * time_delta;
+            let ship_pos = self.world.ships[ship_idx].get_pos(&self.world.space);
+            if i == 0 && self.world.ships[ship_idx].in_air() {
+                self.profile.distance_flown += distance(self.world.ships[ship_idx].prev_pos,
+                                                        ship_pos);
             }
-            if self.input.right {
-                self.rotation += SPEED * time_delta;
+            self.world.ships[ship_idx].prev_pos = ship_pos;
+
+            let on_planet = if self.world.ships[ship_idx].in_air() {
+                None
+            } else {
+                Some(self.world.ships[ship_idx].get_planet(&self.world.space))
+            };
+            let camera_pos = self.update_camera(view_size,
+                                                on_planet,
+                                                self.world.ships[ship_idx].camera_pos,
+                                                ship_pos);
+            self.world.ships[ship_idx].camera_pos = camera_pos;
+        }
+    }
+
+    /// Hand something that happened in the world to everything that reacts to it: the sounds,
+    /// the particles, the HUD and the statistics of the first player's ship.
+    fn handle_event(&mut self, event: GameEvent, window: &mut PistonWindow) {
+        self.audio.react(&event);
+        self.particles.react(&event);
+        self.hud.react(&event);
+        if let Some(ship) = self.players.first().map(|player| player.ship) {
+            if self.profile.record(&event, ship) {
+                self.save_profile();
             }
         }
+        if event == GameEvent::LevelReset {
+            self.space_bounds = self.world.space.get_space_bounds();
+            self.minimap = generate_minimap(window, &self.world.space, self.space_bounds);
+        }
     }
 
-    fn update_reset(&mut self, window: &mut PistonWindow, won: bool) {
-        self.score += if won { 1 } else { -1 };
-        self.space = Space::new();
-        let attached_planet_idx = self.space.get_first_planet();
-        self.attached_planet = attached_planet_idx;
-        let attached_planet = self.space.get_planet(attached_planet_idx);
-        self.height = attached_planet.radius;
-        self.closest_planet_coords = attached_planet.pos;
-        self.bullets = vec![];
-        self.flying = false;
-        self.jumping = false;
-        self.fire_cooldown = 0.0;
-        self.exit_speed = 0.0;
-        self.rotation = 0.0;
-        self.space_bounds = self.space.get_space_bounds();
-        self.minimap = generate_minimap(window, &self.space, self.space_bounds);
-    }
-
-    /// Update game state based on collision.
-    /// Returns the closest planet and the distance to it (for use in attachment).
-    fn update_collision(&mut self,
-                        window: &mut PistonWindow,
-                        ship_pos: Point)
-                        -> (PlanetIndex, f64) {
-        let ship_ball = Ball::new(SHIP_SIZE / 2.0);
-        let na_ship_pos = coll_pt(ship_pos);
-        let mut closest_planet_distance = self.height;
-        let mut closest_planet_idx: PlanetIndex = self.attached_planet;
-
-        // check if the player found the magic planet
-        {
-            let planet_ball = Ball::new(MAGIC_PLANET_SIZE);
-            let planet_pos = coll_pt(self.space.get_magic_planet());
-            if let Some(_) = query::contact(&na_ship_pos,
-                                            &ship_ball,
-                                            &planet_pos,
-                                            &planet_ball,
-                                            0.0) {
-                self.update_reset(window, true);
-                return (self.attached_planet, self.height);
+    /// Spawn the particles that are emitted continuously rather than by a single event, and move
+    /// all the particles along.
+    fn update_effects(&mut self, time_delta: f64) {
+        let ship_positions = self.world.get_ship_positions();
+        for (ship, &ship_pos) in self.world.ships.iter().zip(ship_positions.iter()) {
+            if ship.flying && ship.input.up {
+                self.particles.emit(&EXHAUST, ship_pos, ship.rotation + PI);
             }
         }
+        let magic_planet = self.world.space.get_magic_planet();
+        if ship_positions.iter().any(|ship_pos| {
+            (magic_planet.x - ship_pos.x).abs() < SPARKLE_DISTANCE &&
+            (magic_planet.y - ship_pos.y).abs() < SPARKLE_DISTANCE
+        }) {
+            self.particles.emit_ring(&SPARKLE, magic_planet, MAGIC_PLANET_SIZE);
+        }
+        self.particles.update(time_delta);
+        self.hud.update(time_delta);
+    }
 
-        for (planet_index, planet) in self.space.get_nearby_planets() {
-            let planet_ball = Ball::new(planet.radius);
-            let planet_pos = coll_pt(planet.pos);
+    fn update_audio_settings(&mut self) {
+        if self.input.toggle_mute {
+            self.audio.toggle_mute();
+            self.input.toggle_mute = false;
+        }
+        if self.input.volume_up {
+            self.audio.volume_up();
+            self.input.volume_up = false;
+        }
+        if self.input.volume_down {
+            self.audio.volume_down();
+            self.input.volume_down = false;
+        }
+    }
 
-            // Check if this is the closest planet
-            let distance = query::distance(&na_ship_pos, &ship_ball, &planet_pos, &planet_ball);
-            if distance < closest_planet_distance {
-                closest_planet_distance = distance;
-                closest_planet_idx = planet_index;
-                self.closest_planet_coords = pt(planet.pos.x, planet.pos.y);
-            }
-            let collided =
-                query::contact(&na_ship_pos, &ship_ball, &planet_pos, &planet_ball, -1.0);
-            if let Some(_) = collided {
-                // We are landing on a new planet
-                self.attached_planet = planet_index;
-                self.flying = false;
-                self.height = planet.radius + (SHIP_SIZE / 2.0);
-                self.rotation = direction_from_to(planet.pos, ship_pos);
-
-                if planet.bouncy {
-                    self.jumping = true;
-                    self.exit_speed = JUMP_SPEED;
-                } else {
-                    self.jumping = false;
-                    self.exit_speed = 0.0;
-                }
+    /// Save the profile and put this session's score in the high score table. Call this when the
+    /// game is closing.
+    pub fn quit(&mut self) {
+        match self.net {
+            Some(ref client) => client.disconnect(),
+            // high scores only make sense when playing alone
+            None if self.world.mode == Mode::Solo && self.spectator.is_none() => {
+                self.profile.add_high_score(self.world.ships[0].score);
             }
+            None => {}
         }
-        (closest_planet_idx, closest_planet_distance)
+        self.store_upgrades();
+        self.save_profile();
     }
 
-    /// Handle use of the "attach" ability
-    fn update_attach(&mut self,
-                     closest_planet_idx: PlanetIndex,
-                     closest_planet_distance: f64,
-                     ship_pos: Point) {
-        if self.input.attach && closest_planet_idx != self.attached_planet {
-            self.attached_planet = closest_planet_idx;
-            self.exit_speed = 0.0;
-            self.rotation = (ship_pos.y - self.closest_planet_coords.y)
-                .atan2(ship_pos.x - self.closest_planet_coords.x);
-            self.height = closest_planet_distance +
-                          self.space.get_planet(closest_planet_idx).radius +
-                          (SHIP_SIZE / 2.0);
+    fn save_profile(&self) {
+        if let Some(ref path) = self.profile_path {
+            if let Err(e) = self.profile.save(path) {
+                println!("Couldn't save profile to {}: {}", path.display(), e);
+            }
         }
-
     }
 
-    // Update the camera so that the ship stays in view with a margin around the screen.
+    // Update the camera so that the ship stays in view with a margin around the screen. On a
+    // planet, the camera moves along with the planet and slowly centers on it.
     fn update_camera(&self,
                      view_size: Size,
-                     on_planet: Option<Point>,
+                     on_planet: Option<&Planet>,
                      camera_pos: Point,
                      ship_pos: Point)
                      -> Point {
         match on_planet {
-            Some(planet_center) => {
+            Some(planet) => {
+                let camera_pos = pt(camera_pos.x + planet.moved.x, camera_pos.y + planet.moved.y);
                 pt(lerp(camera_pos.x,
-                        planet_center.x - (view_size.width as f64 / 2.0),
+                        planet.pos.x - (view_size.width as f64 / 2.0),
                         0.03),
                    lerp(camera_pos.y,
-                        planet_center.y - (view_size.height as f64 / 2.0),
+                        planet.pos.y - (view_size.height as f64 / 2.0),
                         0.03))
             }
             None => {
@@ -362,19 +731,19 @@ impl App {
                 let view_height_with_margin = view_size.height as f64 * (2.0 / 3.0);
 
                 // this seems like it's way more complicated than it should be
-                let x = if ship_pos.x > self.camera_pos.x + view_width_with_margin {
-                    self.camera_pos.x + ship_pos.x - (self.camera_pos.x + view_width_with_margin)
-                } else if ship_pos.x < self.camera_pos.x + x_margin {
-                    self.camera_pos.x + ship_pos.x - self.camera_pos.x - x_margin
+                let x = if ship_pos.x > camera_pos.x + view_width_with_margin {
+                    camera_pos.x + ship_pos.x - (camera_pos.x + view_width_with_margin)
+                } else if ship_pos.x < camera_pos.x + x_margin {
+                    camera_pos.x + ship_pos.x - camera_pos.x - x_margin
                 } else {
-                    self.camera_pos.x
+                    camera_pos.x
                 };
-                let y = if ship_pos.y > self.camera_pos.y + view_height_with_margin {
-                    self.camera_pos.y + ship_pos.y - (self.camera_pos.y + view_height_with_margin)
-                } else if ship_pos.y < self.camera_pos.y + y_margin {
-                    self.camera_pos.y + ship_pos.y - self.camera_pos.y - y_margin
+                let y = if ship_pos.y > camera_pos.y + view_height_with_margin {
+                    camera_pos.y + ship_pos.y - (camera_pos.y + view_height_with_margin)
+                } else if ship_pos.y < camera_pos.y + y_margin {
+                    camera_pos.y + ship_pos.y - camera_pos.y - y_margin
                 } else {
-                    self.camera_pos.y
+                    camera_pos.y
                 };
                 // pt((camera_pos.x + x) / 2.0, (camera_pos.y + y) / 2.0)
                 pt(lerp(camera_pos.x, x, 0.1), lerp(camera_pos.y, y, 0.1))
@@ -384,66 +753,38 @@ impl App {
         }
     }
 
-    fn update_bugs(&mut self, mut window: &mut PistonWindow, ship_pos: Point, time_delta: f64) {
-        let bball = Ball::new(BULLET_SIZE);
-        let crawler_ball = Ball::new(CRAWLER_SIZE);
-        for (area, crawler_idx) in self.space.get_nearby_bugs() {
-            {
-                let bug = self.space.get_bug_mut(area, crawler_idx);
-                if bug.moves_right {
-                    bug.rotation += CRAWLER_SPEED * time_delta;
-                } else {
-                    bug.rotation -= CRAWLER_SPEED * time_delta;
-                }
-            }
-            let crawler_pos = {
-                let crawler = self.space.get_bug(area, crawler_idx);
-                let planet = self.space.get_planet(crawler.attached);
-                let bug_pos =
-                    rotated_position(planet.pos, crawler.rotation, planet.radius + CRAWLER_SIZE);
-                coll_pt(bug_pos)
-            };
-            {
-                let na_ship_pos = coll_pt(ship_pos);
-                let ship_ball = Ball::new(SHIP_SIZE / 2.0);
-                let uhoh =
-                    query::contact(&crawler_pos, &crawler_ball, &na_ship_pos, &ship_ball, 0.0);
-                if let Some(_) = uhoh {
-                    self.update_reset(&mut window, false);
-                    return;
-                }
+    pub fn gather_input(&mut self, event: &Event) {
+        if let Some(scroll) = event.mouse_scroll_args() {
+            if let Some(ref mut spectator) = self.spectator {
+                spectator.scroll += scroll[1];
             }
-            for bullet in self.bullets.iter() {
-                let bpos = coll_pt(bullet.pos);
-                let collided = query::contact(&crawler_pos, &crawler_ball, &bpos, &bball, 0.0);
-                if let Some(_) = collided {
-                    self.space.delete_bug(area, crawler_idx);
+        }
+        if let Some(cursor) = event.mouse_cursor_args() {
+            for player in self.players.iter() {
+                if let Some(ship) = self.world.ships.get_mut(player.ship) {
+                    if player.bindings.mouse {
+                        ship.input.cursor = Some(cursor);
+                    }
                 }
             }
         }
-    }
-
-    pub fn gather_input(&mut self, event: &Event) {
-        self.input.cursor = match event.mouse_cursor_args() {
-            None => self.input.cursor,
-            x => x,
-        };
         if let Some(press) = event.press_args() {
             match press {
                 Button::Keyboard(key) => {
-                    match key {
-                        Key::Left | Key::A => self.input.left = true,
-                        Key::Right | Key::E | Key::D => self.input.right = true,
-                        Key::Up | Key::Comma | Key::W => self.input.up = true,
-                        Key::Down | Key::O | Key::S => self.input.down = true,
-                        Key::Space => self.input.jump = true,
-                        x => println!("Keyboard Key {:?}", x),
+                    if self.title.is_some() && (key == Key::Space || key == Key::Return) {
+                        self.input.start = true;
+                    } else if self.shop.is_some() {
+                        self.set_shop_key(key);
+                    } else if !self.set_action(key, true) && !self.set_spectator_key(key, true) {
+                        println!("Keyboard Key {:?}", key);
                     }
                 }
+                // nothing in the shop needs the mouse
+                Button::Mouse(_) if self.shop.is_some() => {}
                 Button::Mouse(key) => {
                     match key {
-                        MouseButton::Left => self.input.shooting = true,
-                        MouseButton::Right => self.input.attach = true,
+                        MouseButton::Left => self.set_mouse_action(Action::Shoot, true),
+                        MouseButton::Right => self.set_mouse_action(Action::Attach, true),
                         x => println!("Mouse Key {:?}", x),
                     }
                 }
@@ -455,21 +796,20 @@ impl App {
                 Button::Keyboard(key) => {
                     match key {
                         Key::B => self.input.toggle_debug = true,
-                        Key::Left | Key::A => self.input.left = false,
-                        Key::Right | Key::E | Key::D => self.input.right = false,
-                        Key::Up | Key::Comma | Key::W => self.input.up = false,
-                        Key::Down | Key::O | Key::S => self.input.down = false,
-                        Key::Space => {
-                            self.input.jump = false;
-                            self.input.jumped = false;
+                        Key::Tab => self.input.toggle_stats = true,
+                        Key::M => self.input.toggle_mute = true,
+                        Key::Equals => self.input.volume_up = true,
+                        Key::Minus => self.input.volume_down = true,
+                        _ => {
+                            self.set_action(key, false);
+                            self.set_spectator_key(key, false);
                         }
-                        _ => {}
                     }
                 }
                 Button::Mouse(key) => {
                     match key {
-                        MouseButton::Left => self.input.shooting = false,
-                        MouseButton::Right => self.input.attach = false,
+                        MouseButton::Left => self.set_mouse_action(Action::Shoot, false),
+                        MouseButton::Right => self.set_mouse_action(Action::Attach, false),
                         _ => {}
                     }
                 }
@@ -478,6 +818,143 @@ impl App {
         }
 
     }
+
+    /// Press or release the action bound to `key` on every player's ship that has it bound.
+    /// Returns false if no player uses the key.
+    fn set_action(&mut self, key: Key, pressed: bool) -> bool {
+        let mut used = false;
+        for player in self.players.iter() {
+            if let Some(action) = player.bindings.action(key) {
+                if let Some(ship) = self.world.ships.get_mut(player.ship) {
+                    set_input(ship, action, pressed);
+                }
+                used = true;
+            }
+        }
+        used
+    }
+
+    /// In the shop, up and down move a player's cursor and jump buys what it's on.
+    fn set_shop_key(&mut self, key: Key) {
+        let shop = match self.shop {
+            Some(ref mut shop) => shop,
+            None => return,
+        };
+        for (i, player) in self.players.iter().enumerate() {
+            match player.bindings.action(key) {
+                Some(Action::Up) => shop.move_cursor(i, false),
+                Some(Action::Down) => shop.move_cursor(i, true),
+                Some(Action::Jump) => {
+                    if let Some(ship) = self.world.ships.get_mut(player.ship) {
+                        shop.choose(i, ship);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Handle the spectator's camera keys. Returns false if there's no spectator or it's not one of
+    /// their keys.
+    fn set_spectator_key(&mut self, key: Key, pressed: bool) -> bool {
+        let num_ships = self.world.ships.len();
+        let spectator = match self.spectator {
+            Some(ref mut spectator) => spectator,
+            None => return false,
+        };
+        match key {
+            Key::Left | Key::A => spectator.pan_left = pressed,
+            Key::Right | Key::D => spectator.pan_right = pressed,
+            Key::Up | Key::W => spectator.pan_up = pressed,
+            Key::Down | Key::S => spectator.pan_down = pressed,
+            Key::PageUp | Key::E => spectator.zoom_in = pressed,
+            Key::PageDown | Key::Q => spectator.zoom_out = pressed,
+            Key::F => {
+                if pressed {
+                    spectator.camera = CameraMode::Free;
+                }
+            }
+            Key::N => {
+                if pressed && num_ships > 0 {
+                    let next = match spectator.camera {
+                        CameraMode::Follow(i) => (i + 1) % num_ships,
+                        CameraMode::Free => 0,
+                    };
+                    spectator.camera = CameraMode::Follow(next);
+                }
+            }
+            _ => {
+                match ship_key(key) {
+                    Some(i) => {
+                        if pressed && i < num_ships {
+                            spectator.camera = CameraMode::Follow(i);
+                        }
+                    }
+                    None => return false,
+                }
+            }
+        }
+        // panning by hand stops following
+        if pressed && (spectator.pan_left || spectator.pan_right || spectator.pan_up ||
+                       spectator.pan_down) {
+            spectator.camera = CameraMode::Free;
+        }
+        true
+    }
+
+    fn set_mouse_action(&mut self, action: Action, pressed: bool) {
+        for player in self.players.iter() {
+            if let Some(ship) = self.world.ships.get_mut(player.ship) {
+                if player.bindings.mouse {
+                    set_input(ship, action, pressed);
+                }
+            }
+        }
+    }
+}
+
+/// The ship that a number key picks for the spectator to follow: 1 for the first ship, and so on.
+fn ship_key(key: Key) -> Option<usize> {
+    let keys = [Key::D1, Key::D2, Key::D3, Key::D4, Key::D5, Key::D6, Key::D7, Key::D8, Key::D9];
+    keys.iter().position(|&k| k == key)
+}
+
+/// Add the default port to an address that doesn't have one.
+fn with_port(addr: &str) -> String {
+    if addr.contains(':') {
+        addr.to_string()
+    } else {
+        format!("{}:{}", addr, DEFAULT_PORT)
+    }
+}
+
+fn set_input(ship: &mut Ship, action: Action, pressed: bool) {
+    let ref mut input = ship.input;
+    match action {
+        Action::Left => input.left = pressed,
+        Action::Right => input.right = pressed,
+        Action::Up => input.up = pressed,
+        Action::Down => {
+            input.down = pressed;
+            if !pressed {
+                input.ducked = false;
+            }
+        }
+        Action::Jump => {
+            input.jump = pressed;
+            if !pressed {
+                input.jumped = false;
+            }
+        }
+        Action::Shoot => input.shooting = pressed,
+        Action::Attach => {
+            input.attach = pressed;
+            if !pressed {
+                input.thrown = false;
+            }
+        }
+        Action::Dash => input.dash = pressed,
+    }
 }
 
 fn fill_circle(canvas: &mut im::ImageBuffer<im::Rgba<u8>, Vec<u8>>,
@@ -516,8 +993,6 @@ fn generate_minimap(window: &mut PistonWindow,
                     space: &Space,
                     (min, max): (Point, Point))
                     -> G2dTexture {
-    let planet_pixel = im::Rgba([0, 0, 255, 255]);
-    let bouncy_pixel = im::Rgba([127, 127, 255, 255]);
     let magic_pixel = im::Rgba([255, 0, 0, 255]);
 
     let mut canvas: im::ImageBuffer<im::Rgba<u8>, Vec<u8>> =
@@ -531,9 +1006,16 @@ fn generate_minimap(window: &mut PistonWindow,
             fill_circle(&mut canvas, color, rad as i32, mini_x as i32, mini_y as i32);
         };
 
+        // moving planets are drawn over the top as they go
         for planet in space.get_all_planets() {
-            let pixel = if planet.bouncy { bouncy_pixel } else { planet_pixel };
-            render_planet(pixel, planet.pos, planet.radius);
+            if planet.radius > 0.0 && planet.motion.is_none() {
+                let color = planet_color(planet.kind);
+                let pixel = im::Rgba([(color[0] * 255.0) as u8,
+                                      (color[1] * 255.0) as u8,
+                                      (color[2] * 255.0) as u8,
+                                      255]);
+                render_planet(pixel, planet.pos, planet.radius);
+            }
         }
         render_planet(magic_pixel, space.get_magic_planet(), MAGIC_PLANET_SIZE);
     }
@@ -0,0 +1,585 @@
+use std::f64::consts::PI;
+
+use rand::XorShiftRng;
+use rand::distributions::{IndependentSample, Range};
+
+use calc::{Point, pt, distance, rotated_position, direction_from_to};
+use space::{Space, Planet, PlanetKind, PlanetIndex, Motion, MAGIC_PLANET_SIZE};
+use ship::SHIP_SIZE;
+use world::FUEL_CAN_SIZE;
+use difficulty::Difficulty;
+use reach::{self, Envelope, Problem};
+
+const START_PLANET_SIZE: f64 = 50.0;
+const MIN_PLANET_SIZE: f64 = 35.0;
+const MAX_PLANET_SIZE: f64 = 100.0;
+// how often each kind of planet comes up, out of the total
+const KIND_WEIGHTS: [(PlanetKind, u32); 7] = [(PlanetKind::Normal, 30),
+                                              (PlanetKind::Bouncy, 30),
+                                              (PlanetKind::Icy, 10),
+                                              (PlanetKind::Sticky, 8),
+                                              (PlanetKind::Hot, 8),
+                                              (PlanetKind::GasGiant, 6),
+                                              (PlanetKind::Crumbling, 8)];
+// how far past the end of the path the magic planet is
+const MAGIC_PLANET_DISTANCE: f64 = 400.0;
+// how likely a planet on a path is to have a moon
+const MOON_PERCENTAGE: f64 = 0.15;
+const MIN_MOON_SIZE: f64 = 20.0;
+// how much further out than just clear of its planet a moon's orbit can be
+const MAX_MOON_CLEARANCE: f64 = 150.0;
+// radians per second
+const MIN_MOON_SPEED: f64 = 0.2;
+const MAX_MOON_SPEED: f64 = 0.6;
+// how close anything moving can come to the start planet
+const START_CLEARANCE: f64 = START_PLANET_SIZE + SHIP_SIZE * 2.0;
+
+/// A way of laying out a level. Generators get an empty space and fill it with planets, crawlers
+/// and a magic planet. They have to add the first planet first, at the origin, because that's
+/// where the ships start. Levels that can't be won are thrown away by the space, so generators
+/// don't have to be careful about that, only about not making it happen often.
+pub trait LevelGenerator {
+    fn generate(&self, space: &mut Space, rng: &mut XorShiftRng, difficulty: &Difficulty);
+}
+
+/// Which generator to use. These are what can be picked from the command line or sent over the
+/// network; `get` gives the generator itself.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Generator {
+    RandomWalk,
+    Tree,
+    Spiral,
+    AsteroidField,
+    BouncyMaze,
+}
+
+pub const GENERATORS: [Generator; 5] = [Generator::RandomWalk,
+                                        Generator::Tree,
+                                        Generator::Spiral,
+                                        Generator::AsteroidField,
+                                        Generator::BouncyMaze];
+
+impl Generator {
+    pub fn get(&self) -> Box<LevelGenerator> {
+        match *self {
+            Generator::RandomWalk => Box::new(RandomWalk),
+            Generator::Tree => Box::new(Tree),
+            Generator::Spiral => Box::new(Spiral),
+            Generator::AsteroidField => Box::new(AsteroidField),
+            Generator::BouncyMaze => Box::new(BouncyMaze),
+        }
+    }
+
+    /// Pick a generator from a level's seed, for when it's left up to chance.
+    pub fn for_seed(seed: u32) -> Generator {
+        // the low bits of successive level seeds go round in short cycles, so use the high ones
+        let mixed = seed.wrapping_mul(0x9E3779B1) >> 16;
+        GENERATORS[mixed as usize % GENERATORS.len()]
+    }
+
+    /// The name used on the command line.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Generator::RandomWalk => "walk",
+            Generator::Tree => "tree",
+            Generator::Spiral => "spiral",
+            Generator::AsteroidField => "asteroids",
+            Generator::BouncyMaze => "maze",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Generator> {
+        GENERATORS.iter().find(|generator| generator.name() == name).cloned()
+    }
+
+    /// Read a choice of generator from the command line: a generator's name, or "any" to pick
+    /// one for each level. Returns None if it's neither.
+    pub fn parse_choice(name: &str) -> Option<Option<Generator>> {
+        if name == "any" {
+            Some(None)
+        } else {
+            Generator::from_name(name).map(Some)
+        }
+    }
+
+    /// What `parse_choice` understands, for usage messages.
+    pub fn choices() -> String {
+        let names: Vec<&str> = GENERATORS.iter().map(|generator| generator.name()).collect();
+        format!("{} or any", names.join(", "))
+    }
+}
+
+/// A path of planets wandering off in roughly one direction, with the magic planet at the end.
+pub struct RandomWalk;
+
+impl LevelGenerator for RandomWalk {
+    fn generate(&self, space: &mut Space, rng: &mut XorShiftRng, difficulty: &Difficulty) {
+        add_start(space);
+        let direction = Range::new(-2.0 * PI, 2.0 * PI).ind_sample(rng);
+        let (end, direction) =
+            walk(space, rng, difficulty, pt(0.0, 0.0), direction, difficulty.num_planets);
+        space.set_magic_planet(rotated_position(end, direction, MAGIC_PLANET_DISTANCE));
+    }
+}
+
+// the chance of a dead end going off each planet of the path
+const BRANCH_PERCENTAGE: f64 = 0.5;
+const MAX_BRANCH_LENGTH: u32 = 3;
+
+/// A random walk with dead ends going off to the sides of it.
+pub struct Tree;
+
+impl LevelGenerator for Tree {
+    fn generate(&self, space: &mut Space, rng: &mut XorShiftRng, difficulty: &Difficulty) {
+        let range_percent = Range::new(0.0, 1.0);
+        let range_length = Range::new(1, MAX_BRANCH_LENGTH + 1);
+        add_start(space);
+        let mut pos = pt(0.0, 0.0);
+        let mut direction = Range::new(-2.0 * PI, 2.0 * PI).ind_sample(rng);
+        for _ in 0..difficulty.num_planets {
+            let (next_pos, next_direction) = walk(space, rng, difficulty, pos, direction, 1);
+            pos = next_pos;
+            direction = next_direction;
+            if range_percent.ind_sample(rng) < BRANCH_PERCENTAGE {
+                let side = if range_percent.ind_sample(rng) < 0.5 {
+                    PI / 2.0
+                } else {
+                    -PI / 2.0
+                };
+                let length = range_length.ind_sample(rng);
+                walk(space, rng, difficulty, pos, direction + side, length);
+            }
+        }
+        space.set_magic_planet(rotated_position(pos, direction, MAGIC_PLANET_DISTANCE));
+    }
+}
+
+// how far apart the turns of each arm of the spiral are
+const ARM_SPACING: f64 = 1200.0;
+// planets per arm, per planet of the difficulty
+const SPIRAL_LENGTH: u32 = 4;
+
+/// Two arms of planets spiralling out from the start, like a galaxy. One of them leads to the
+/// magic planet; the other goes nowhere. The arms are close enough to hop between.
+pub struct Spiral;
+
+impl LevelGenerator for Spiral {
+    fn generate(&self, space: &mut Space, rng: &mut XorShiftRng, difficulty: &Difficulty) {
+        let range_distance = Range::new(difficulty.min_planet_distance,
+                                        difficulty.max_planet_distance);
+        let range_wobble = Range::new(-difficulty.path_variance, difficulty.path_variance);
+        add_start(space);
+        let start_angle = Range::new(-PI, PI).ind_sample(rng);
+        let turn = if Range::new(0.0, 1.0).ind_sample(rng) < 0.5 {
+            1.0
+        } else {
+            -1.0
+        };
+        // the radius grows by ARM_SPACING every turn
+        let growth = ARM_SPACING / (2.0 * PI);
+        let mut end_angle = 0.0;
+        for arm in 0..2 {
+            let offset = start_angle + arm as f64 * PI;
+            // start a little way out, or the first planets are on top of each other
+            let mut angle = PI / 2.0;
+            for _ in 0..difficulty.num_planets * SPIRAL_LENGTH {
+                let radius = growth * angle;
+                // step along the arm by about the planet distance
+                angle += range_distance.ind_sample(rng) / (radius.powi(2) + growth.powi(2)).sqrt();
+                let wobble = 1.0 + range_wobble.ind_sample(rng);
+                let pos = rotated_position(pt(0.0, 0.0),
+                                           offset + turn * angle,
+                                           growth * angle * wobble);
+                let planet = add_planet(space, rng, pos);
+                add_crawler(space, rng, difficulty, planet);
+                if arm == 0 {
+                    end_angle = angle;
+                }
+            }
+        }
+        // just past the end of the first arm
+        let angle = end_angle + MAGIC_PLANET_DISTANCE / (growth * end_angle);
+        space.set_magic_planet(rotated_position(pt(0.0, 0.0),
+                                                start_angle + turn * angle,
+                                                growth * angle));
+    }
+}
+
+// asteroids per planet of the difficulty
+const ASTEROIDS_PER_PLANET: u32 = 8;
+const MAX_ASTEROID_SIZE: f64 = MIN_PLANET_SIZE * 1.5;
+// how many places to try putting each asteroid before giving up on it
+const PLACEMENT_TRIES: u32 = 20;
+// how likely an asteroid is to drift back and forth
+const DRIFT_PERCENTAGE: f64 = 0.2;
+const MAX_DRIFT: f64 = 300.0;
+const MIN_DRIFT_SPEED: f64 = 30.0;
+const MAX_DRIFT_SPEED: f64 = 90.0;
+
+/// Lots of small planets scattered over a band between the start and the magic planet. Some of
+/// them drift back and forth.
+pub struct AsteroidField;
+
+impl LevelGenerator for AsteroidField {
+    fn generate(&self, space: &mut Space, rng: &mut XorShiftRng, difficulty: &Difficulty) {
+        add_start(space);
+        let direction = Range::new(-2.0 * PI, 2.0 * PI).ind_sample(rng);
+        let length = difficulty.num_planets as f64 * difficulty.max_planet_distance;
+        let width = length / 2.0;
+        space.set_magic_planet(rotated_position(pt(0.0, 0.0),
+                                                direction,
+                                                length + MAGIC_PLANET_DISTANCE));
+
+        let range_along = Range::new(difficulty.min_planet_distance, length);
+        let range_across = Range::new(-width / 2.0, width / 2.0);
+        let range_radius = Range::new(MIN_PLANET_SIZE, MAX_ASTEROID_SIZE);
+        for _ in 0..difficulty.num_planets * ASTEROIDS_PER_PLANET {
+            for _ in 0..PLACEMENT_TRIES {
+                let along = rotated_position(pt(0.0, 0.0), direction, range_along.ind_sample(rng));
+                let across = range_across.ind_sample(rng);
+                let pos = rotated_position(along, direction + PI / 2.0, across);
+                let radius = range_radius.ind_sample(rng);
+                if fits(space, pos, radius) {
+                    let kind = random_kind(rng);
+                    let mut asteroid = Planet::new(pos, radius, kind);
+                    asteroid.motion = drift(rng, pos);
+                    let planet = space.add_planet(asteroid);
+                    add_crawler(space, rng, difficulty, planet);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+// extra room between the cells of the maze, on top of what stops the ship getting across
+const MAZE_MARGIN: f64 = 100.0;
+
+/// A square grid of bouncy planets, just too far apart to get between. Stepping stones between
+/// some of them make the passages of a maze, with the start in one corner and the magic planet
+/// past the opposite one.
+pub struct BouncyMaze;
+
+impl LevelGenerator for BouncyMaze {
+    fn generate(&self, space: &mut Space, rng: &mut XorShiftRng, difficulty: &Difficulty) {
+        let envelope = Envelope::ship();
+        let reach = envelope.flight.max(envelope.jump * 2.0);
+        let spacing = reach + 2.0 * MAX_PLANET_SIZE + SHIP_SIZE + MAZE_MARGIN;
+        let side = 2 + (difficulty.num_planets as usize + 1) / 2;
+        let rotation = Range::new(-PI, PI).ind_sample(rng);
+        let range_radius = Range::new(MIN_PLANET_SIZE, MAX_PLANET_SIZE);
+        let cell_pos = |x: f64, y: f64| {
+            let (x, y) = (x * spacing, y * spacing);
+            pt(x * rotation.cos() - y * rotation.sin(),
+               x * rotation.sin() + y * rotation.cos())
+        };
+        let add_bouncy = |space: &mut Space, rng: &mut XorShiftRng, pos: Point| {
+            let radius = range_radius.ind_sample(rng);
+            let planet = space.add_planet(Planet::new(pos, radius, PlanetKind::Bouncy));
+            add_crawler(space, rng, difficulty, planet);
+        };
+
+        add_start(space);
+        for y in 0..side {
+            for x in 0..side {
+                if x != 0 || y != 0 {
+                    add_bouncy(space, rng, cell_pos(x as f64, y as f64));
+                }
+            }
+        }
+
+        // carve the maze depth first, so there's exactly one way between any two cells
+        let mut visited = vec![false; side * side];
+        visited[0] = true;
+        let mut stack = vec![(0, 0)];
+        while let Some(&(x, y)) = stack.last() {
+            let mut neighbours = vec![];
+            if x > 0 {
+                neighbours.push((x - 1, y));
+            }
+            if x + 1 < side {
+                neighbours.push((x + 1, y));
+            }
+            if y > 0 {
+                neighbours.push((x, y - 1));
+            }
+            if y + 1 < side {
+                neighbours.push((x, y + 1));
+            }
+            neighbours.retain(|&(nx, ny)| !visited[ny * side + nx]);
+            if neighbours.is_empty() {
+                stack.pop();
+                continue;
+            }
+            let (nx, ny) = neighbours[Range::new(0, neighbours.len()).ind_sample(rng)];
+            visited[ny * side + nx] = true;
+            let between = cell_pos((x + nx) as f64 / 2.0, (y + ny) as f64 / 2.0);
+            add_bouncy(space, rng, between);
+            stack.push((nx, ny));
+        }
+
+        let corner = (side - 1) as f64 + MAGIC_PLANET_DISTANCE / spacing / 2.0f64.sqrt();
+        space.set_magic_planet(cell_pos(corner, corner));
+    }
+}
+
+/// The planet everyone starts on.
+pub fn add_start(space: &mut Space) -> PlanetIndex {
+    space.add_planet(Planet::new(pt(0.0, 0.0), START_PLANET_SIZE, PlanetKind::Normal))
+}
+
+/// Add a planet of random size and kind.
+fn add_planet(space: &mut Space, rng: &mut XorShiftRng, pos: Point) -> PlanetIndex {
+    let radius = Range::new(MIN_PLANET_SIZE, MAX_PLANET_SIZE).ind_sample(rng);
+    let kind = random_kind(rng);
+    space.add_planet(Planet::new(pos, radius, kind))
+}
+
+fn random_kind(rng: &mut XorShiftRng) -> PlanetKind {
+    let total = KIND_WEIGHTS.iter().map(|&(_, weight)| weight).sum();
+    let mut pick = Range::new(0, total).ind_sample(rng);
+    for &(kind, weight) in KIND_WEIGHTS.iter() {
+        if pick < weight {
+            return kind;
+        }
+        pick -= weight;
+    }
+    PlanetKind::Normal
+}
+
+/// Maybe give a planet a moon.
+fn add_moon(space: &mut Space,
+            rng: &mut XorShiftRng,
+            difficulty: &Difficulty,
+            parent: PlanetIndex) {
+    if Range::new(0.0, 1.0).ind_sample(rng) > MOON_PERCENTAGE {
+        return;
+    }
+    let radius = Range::new(MIN_MOON_SIZE, MIN_PLANET_SIZE).ind_sample(rng);
+    let (parent_pos, parent_radius) = match space.get_planet(parent) {
+        Some(planet) => (planet.pos, planet.radius),
+        None => return,
+    };
+    let orbit = parent_radius + radius + SHIP_SIZE * 2.0 +
+                Range::new(0.0, MAX_MOON_CLEARANCE).ind_sample(rng);
+    let speed = Range::new(MIN_MOON_SPEED, MAX_MOON_SPEED).ind_sample(rng);
+    let clockwise = Range::new(0, 2).ind_sample(rng) == 1;
+    let phase = Range::new(-PI, PI).ind_sample(rng);
+    let kind = random_kind(rng);
+    if distance(parent_pos, pt(0.0, 0.0)) - orbit - radius < START_CLEARANCE {
+        return;
+    }
+    let mut moon = Planet::new(parent_pos, radius, kind);
+    moon.motion = Some(Motion::Orbit {
+        parent: parent,
+        distance: orbit,
+        speed: if clockwise { -speed } else { speed },
+        phase: phase,
+    });
+    let moon = space.add_planet(moon);
+    add_crawler(space, rng, difficulty, moon);
+}
+
+/// Maybe set an asteroid drifting back and forth from `pos`, as long as it stays clear of the
+/// start.
+fn drift(rng: &mut XorShiftRng, pos: Point) -> Option<Motion> {
+    if Range::new(0.0, 1.0).ind_sample(rng) > DRIFT_PERCENTAGE {
+        return None;
+    }
+    let direction = Range::new(-PI, PI).ind_sample(rng);
+    let to = rotated_position(pos, direction, Range::new(0.0, MAX_DRIFT).ind_sample(rng));
+    let speed = Range::new(MIN_DRIFT_SPEED, MAX_DRIFT_SPEED).ind_sample(rng);
+    if distance(to, pt(0.0, 0.0)) - MAX_ASTEROID_SIZE < START_CLEARANCE {
+        return None;
+    }
+    Some(Motion::Path {
+        points: vec![pos, to],
+        speed: speed,
+    })
+}
+
+/// Maybe put a crawler on a planet, depending on the difficulty. Crawlers can't walk on gas.
+fn add_crawler(space: &mut Space,
+               rng: &mut XorShiftRng,
+               difficulty: &Difficulty,
+               planet: PlanetIndex) {
+    match space.get_planet(planet) {
+        Some(planet) if planet.is_solid() => {}
+        _ => return,
+    }
+    if Range::new(0.0, 1.0).ind_sample(rng) > 1.0 - difficulty.crawler_percentage {
+        let rotation = Range::new(-2.0 * PI, 2.0 * PI).ind_sample(rng);
+        let moves_right = Range::new(0, 2).ind_sample(rng) == 1;
+        space.add_bug(planet, rotation, moves_right);
+    }
+}
+
+// how far a line of stepping stones may turn aside to get around a planet, in radians, in the
+// order the turns are tried. None of them turn away from the magic planet.
+const STONE_TURNS: [f64; 7] = [0.0, 0.3, -0.3, 0.6, -0.6, 0.9, -0.9];
+// however far away the magic planet is, there's a limit to how far a level gets patched up
+const MAX_STEPPING_STONES: u32 = 50;
+const MAX_REPAIRS: u32 = 10;
+
+/// Change a level that can't be won so that it can: crawlers that walk over the start are taken
+/// away, and if the magic planet can't be got to, a line of planets is put in leading up to it, or
+/// if there's no room for one, the magic planet is brought closer. Gives up after a few goes, with
+/// what's still wrong. Like `add_fuel_cans`, this doesn't use any random numbers.
+pub fn make_solvable(space: &mut Space) -> Result<(), Problem> {
+    let envelope = Envelope::ship();
+    for _ in 0..MAX_REPAIRS {
+        match reach::check(space, &envelope) {
+            Ok(_) => return Ok(()),
+            Err(Problem::CrawlerAtStart(crawler)) => {
+                space.entities.despawn(crawler);
+            }
+            Err(Problem::Unreachable { closest }) => {
+                if !add_stepping_stones(space, closest, &envelope) {
+                    move_magic_planet(space, closest, &envelope);
+                }
+            }
+        }
+    }
+    reach::check(space, &envelope).map(|_| ())
+}
+
+/// Put planets in a line from `from` towards the magic planet, each a short jump from the one
+/// before, until the last one is close enough to get to the magic planet from. Where the line is
+/// blocked by another planet it goes around it, and if it can't, it stops. Returns false if it
+/// couldn't put in any planets at all.
+fn add_stepping_stones(space: &mut Space, from: PlanetIndex, envelope: &Envelope) -> bool {
+    let (mut pos, mut radius) = match space.get_planet(from) {
+        Some(planet) => (planet.pos, planet.radius),
+        None => return false,
+    };
+    let magic_planet = space.get_magic_planet();
+    // a jump that gets halfway is enough, but there's no need to make it that hard
+    let gap = envelope.jump;
+    let mut added = 0;
+    while added < MAX_STEPPING_STONES &&
+          !envelope.can_cross(distance(pos, magic_planet) - radius - MAGIC_PLANET_SIZE -
+                              SHIP_SIZE) {
+        let direction = direction_from_to(pos, magic_planet);
+        let step = radius + gap + SHIP_SIZE + MIN_PLANET_SIZE;
+        let stone = STONE_TURNS.iter()
+            .map(|&turn| rotated_position(pos, direction + turn, step))
+            .find(|&stone| is_clear(space, stone, MIN_PLANET_SIZE));
+        match stone {
+            Some(stone) => {
+                space.add_planet(Planet::new(stone, MIN_PLANET_SIZE, PlanetKind::Normal));
+                pos = stone;
+                radius = MIN_PLANET_SIZE;
+                added += 1;
+            }
+            None => break,
+        }
+    }
+    added > 0
+}
+
+/// Whether a planet of `radius` at `pos` would be clear of the planets there are, with room for a
+/// ship in between, and of everywhere a moving planet goes.
+fn is_clear(space: &Space, pos: Point, radius: f64) -> bool {
+    let clearance = radius + SHIP_SIZE * 2.0;
+    distance(pos, space.get_magic_planet()) > MAGIC_PLANET_SIZE + clearance &&
+    space.get_all_planets().iter().all(|planet| {
+        let (center, sweep) = match planet.motion {
+            Some(ref motion) => motion.sweep(space),
+            None => (planet.pos, 0.0),
+        };
+        distance(pos, center) > sweep + planet.radius + clearance
+    })
+}
+
+/// Bring the magic planet a short jump from `next_to`, or from the first planet if that's gone.
+fn move_magic_planet(space: &mut Space, next_to: PlanetIndex, envelope: &Envelope) {
+    let (pos, radius) = {
+        let planet = space.get_planet(next_to).unwrap_or(space.get_start_planet());
+        (planet.pos, planet.radius)
+    };
+    let direction = direction_from_to(pos, space.get_magic_planet());
+    let magic_planet =
+        rotated_position(pos, direction, radius + envelope.jump + SHIP_SIZE + MAGIC_PLANET_SIZE);
+    space.set_magic_planet(magic_planet);
+}
+
+/// Put a fuel can halfway across every gap between planets that's too wide to jump, so a ship
+/// that flies across can fill up on the way. Only still, solid planets count. This works from the
+/// planets alone, without the random numbers, so it can follow any generator.
+pub fn add_fuel_cans(space: &mut Space) {
+    let envelope = Envelope::ship();
+    let mut planets: Vec<(Point, f64)> = space.get_all_planets()
+        .into_iter()
+        .filter(|planet| planet.is_solid() && planet.motion.is_none())
+        .map(|planet| (planet.pos, planet.radius))
+        .collect();
+    // the planets come out of a HashMap; the cans have to be made in the same order everywhere
+    // for their entities to match between the server and the clients
+    planets.sort_by(|a, b| (a.0.x, a.0.y).partial_cmp(&(b.0.x, b.0.y)).unwrap());
+    let gap = |a: (Point, f64), b: (Point, f64)| distance(a.0, b.0) - a.1 - b.1;
+    let mut cans: Vec<Point> = vec![];
+    for (i, &planet) in planets.iter().enumerate() {
+        let nearest = planets.iter()
+            .enumerate()
+            .filter(|&(j, _)| j != i)
+            .map(|(_, &other)| other)
+            .fold(None, |best: Option<(Point, f64)>, other| {
+                match best {
+                    Some(best) if gap(planet, best) <= gap(planet, other) => Some(best),
+                    _ => Some(other),
+                }
+            });
+        let other = match nearest {
+            Some(other) if gap(planet, other) > envelope.jump * 2.0 => other,
+            _ => continue,
+        };
+        // halfway between the surfaces
+        let from = rotated_position(planet.0, direction_from_to(planet.0, other.0), planet.1);
+        let to = rotated_position(other.0, direction_from_to(other.0, planet.0), other.1);
+        let pos = pt((from.x + to.x) / 2.0, (from.y + to.y) / 2.0);
+        // two planets that are each other's nearest share a can
+        if cans.iter().all(|&can| distance(can, pos) > FUEL_CAN_SIZE * 2.0) {
+            cans.push(pos);
+            space.add_fuel_can(pos);
+        }
+    }
+}
+
+/// Add `count` planets in a wandering line from `from`, heading roughly in `direction`. Returns
+/// where the line ended up and which way it was heading.
+fn walk(space: &mut Space,
+        rng: &mut XorShiftRng,
+        difficulty: &Difficulty,
+        from: Point,
+        direction: f64,
+        count: u32)
+        -> (Point, f64) {
+    let range_distance = Range::new(difficulty.min_planet_distance,
+                                    difficulty.max_planet_distance);
+    let range_radius = Range::new(MIN_PLANET_SIZE, MAX_PLANET_SIZE);
+    let range_direction = Range::new(-PI * difficulty.path_variance,
+                                     PI * difficulty.path_variance);
+    let mut prev_pos = from;
+    let mut prev_rot = direction;
+    for _ in 0..count {
+        let radius = range_radius.ind_sample(rng);
+        let distance = range_distance.ind_sample(rng);
+        let direction = range_direction.ind_sample(rng) + prev_rot;
+        let pos = rotated_position(prev_pos, direction, distance);
+        let kind = random_kind(rng);
+        let planet = space.add_planet(Planet::new(pos, radius, kind));
+        add_crawler(space, rng, difficulty, planet);
+        add_moon(space, rng, difficulty, planet);
+        prev_pos = pos;
+        prev_rot = direction;
+    }
+    (prev_pos, prev_rot)
+}
+
+/// Whether a planet would have room around it for a ship, clear of every other planet.
+fn fits(space: &Space, pos: Point, radius: f64) -> bool {
+    distance(pos, space.get_magic_planet()) > radius + MAGIC_PLANET_SIZE + SHIP_SIZE &&
+    space.get_all_planets()
+        .iter()
+        .all(|planet| distance(pos, planet.pos) > radius + planet.radius + SHIP_SIZE)
+}
@@ -0,0 +1,168 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+use calc::{Point, pt};
+use space::AREA_WIDTH;
+
+/// How big the cells of a grid are. An area is split evenly into cells, so no cell straddles two
+/// areas.
+pub const CELL_SIZE: f64 = AREA_WIDTH / 10.0;
+
+pub type Cell = (i32, i32);
+
+/// Grid is the broad phase for collisions. Things are filed by which cells of a uniform grid they
+/// cover, keyed the same way as `Space`'s areas only finer, so that checking what something hits
+/// means looking at what's in the cells around it instead of at everything nearby. Cells are
+/// kept when the grid is cleared, so refilling it every tick doesn't allocate.
+#[derive(Debug)]
+pub struct Grid<T> {
+    cells: HashMap<Cell, Vec<T>>,
+    occupied: Vec<Cell>, // the cells with something in them, in the order they were filled
+}
+
+impl<T: Copy + Eq + Hash> Grid<T> {
+    pub fn new() -> Grid<T> {
+        Grid {
+            cells: HashMap::new(),
+            occupied: vec![],
+        }
+    }
+
+    /// Empty every cell.
+    pub fn clear(&mut self) {
+        for cell in self.occupied.drain(..) {
+            if let Some(items) = self.cells.get_mut(&cell) {
+                items.clear();
+            }
+        }
+    }
+
+    /// File something that covers a circle.
+    pub fn insert(&mut self, item: T, pos: Point, radius: f64) {
+        let (min, max) = cell_range(pos, radius);
+        for x in min.0..max.0 + 1 {
+            for y in min.1..max.1 + 1 {
+                let items = self.cells.entry((x, y)).or_insert_with(Vec::new);
+                if items.is_empty() {
+                    self.occupied.push((x, y));
+                }
+                items.push(item);
+            }
+        }
+    }
+
+    /// Everything that might overlap a circle, each once.
+    pub fn query(&self, pos: Point, radius: f64) -> Vec<T> {
+        let (min, max) = cell_range(pos, radius);
+        let mut found = vec![];
+        let mut seen = HashSet::new();
+        let mut add = |items: &Vec<T>| {
+            for &item in items.iter() {
+                if seen.insert(item) {
+                    found.push(item);
+                }
+            }
+        };
+        let num_cells = (max.0 - min.0 + 1) as usize * (max.1 - min.1 + 1) as usize;
+        if num_cells > self.occupied.len() {
+            // a big circle; it's quicker to go through the cells that have something in them
+            for &(x, y) in self.occupied.iter() {
+                if x >= min.0 && x <= max.0 && y >= min.1 && y <= max.1 {
+                    add(&self.cells[&(x, y)]);
+                }
+            }
+        } else {
+            for x in min.0..max.0 + 1 {
+                for y in min.1..max.1 + 1 {
+                    if let Some(items) = self.cells.get(&(x, y)) {
+                        add(items);
+                    }
+                }
+            }
+        }
+        found
+    }
+
+    /// Everything that a circle might touch while moving by `motion` from `from`.
+    pub fn query_sweep(&self, from: Point, motion: Point, radius: f64) -> Vec<T> {
+        let middle = pt(from.x + motion.x / 2.0, from.y + motion.y / 2.0);
+        let half_length = (motion.x * motion.x + motion.y * motion.y).sqrt() / 2.0;
+        self.query(middle, half_length + radius)
+    }
+}
+
+pub fn cell_for_point(p: Point) -> Cell {
+    ((p.x / CELL_SIZE).floor() as i32, (p.y / CELL_SIZE).floor() as i32)
+}
+
+/// The corners of the block of cells a circle covers.
+fn cell_range(pos: Point, radius: f64) -> (Cell, Cell) {
+    (cell_for_point(pt(pos.x - radius, pos.y - radius)),
+     cell_for_point(pt(pos.x + radius, pos.y + radius)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use calc::distance;
+
+    // things of different sizes scattered over a few areas either way of the origin
+    fn scattered(n: usize) -> Vec<(usize, Point, f64)> {
+        let mut state = 12345u32;
+        let mut next = || {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            state as f64 / 4294967296.0
+        };
+        (0..n)
+            .map(|i| {
+                let x = (next() - 0.5) * AREA_WIDTH * 4.0;
+                let y = (next() - 0.5) * AREA_WIDTH * 4.0;
+                (i, pt(x, y), 10.0 + next() * 90.0)
+            })
+            .collect()
+    }
+
+    fn filled(things: &[(usize, Point, f64)]) -> Grid<usize> {
+        let mut grid = Grid::new();
+        for &(i, pos, radius) in things.iter() {
+            grid.insert(i, pos, radius);
+        }
+        grid
+    }
+
+    #[test]
+    fn finds_everything_in_range_once() {
+        let things = scattered(5000);
+        let mut grid = filled(&things);
+        let circles = [(pt(0.0, 0.0), 50.0),
+                       (pt(1000.0, -700.0), 400.0),
+                       (pt(-3000.0, 2500.0), 1500.0),
+                       (pt(0.0, 0.0), AREA_WIDTH * 4.0)];
+        for &(pos, radius) in circles.iter() {
+            let found = grid.query(pos, radius);
+            let unique: HashSet<usize> = found.iter().cloned().collect();
+            assert_eq!(unique.len(), found.len());
+            for &(i, thing_pos, thing_radius) in things.iter() {
+                if distance(pos, thing_pos) < radius + thing_radius {
+                    assert!(unique.contains(&i));
+                }
+            }
+        }
+        assert_eq!(grid.query(pt(0.0, 0.0), AREA_WIDTH * 4.0).len(), things.len());
+
+        grid.clear();
+        assert!(grid.query(pt(0.0, 0.0), AREA_WIDTH * 4.0).is_empty());
+        grid.insert(7, pt(10.0, 10.0), 5.0);
+        assert_eq!(grid.query(pt(0.0, 0.0), AREA_WIDTH * 4.0), vec![7]);
+    }
+
+    #[test]
+    fn small_circles_only_find_their_neighbourhood() {
+        let things = scattered(5000);
+        let grid = filled(&things);
+        // a handful of things to a cell, and a small circle covers no more than four cells
+        for &(_, pos, radius) in things.iter() {
+            assert!(grid.query(pos, radius).len() < 50);
+        }
+    }
+}
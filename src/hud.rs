@@ -0,0 +1,64 @@
+use circles::world::GameEvent;
+use render::{GREEN, RED};
+
+// how long a popup floats for, in seconds
+const POPUP_TIME: f64 = 1.0;
+
+/// A bit of text, like "+1", that floats up next to a ship's score and fades away.
+pub struct Popup {
+    pub ship: usize,
+    pub text: &'static str,
+    pub color: [f32; 4],
+    pub age: f64, // seconds since it popped up
+}
+
+impl Popup {
+    /// How far through its life the popup is, from 0 to 1.
+    pub fn progress(&self) -> f64 {
+        (self.age / POPUP_TIME).min(1.0)
+    }
+}
+
+/// Hud is the part of the on-screen display that reacts to what happens in the world, rather than
+/// just showing how things are: it shows the score going up and down as it happens.
+pub struct Hud {
+    popups: Vec<Popup>,
+}
+
+impl Hud {
+    pub fn new() -> Hud {
+        Hud { popups: vec![] }
+    }
+
+    /// Pop up the score and health changes from something that happened in the world.
+    pub fn react(&mut self, event: &GameEvent) {
+        match *event {
+            GameEvent::MagicPlanetReached(ship) => self.pop(ship, "+1", GREEN),
+            GameEvent::ShipHit { ship, by: None, .. } => self.pop(ship, "-1", RED),
+            GameEvent::ShipHit { by: Some(shooter), .. } => self.pop(shooter, "+1", GREEN),
+            GameEvent::ShipDamaged { ship, .. } => self.pop(ship, "-health", RED),
+            _ => {}
+        }
+    }
+
+    pub fn update(&mut self, time_delta: f64) {
+        for popup in self.popups.iter_mut() {
+            popup.age += time_delta;
+        }
+        self.popups.retain(|popup| popup.age < POPUP_TIME);
+    }
+
+    /// The popups for a ship, oldest first.
+    pub fn get_popups(&self, ship: usize) -> Vec<&Popup> {
+        self.popups.iter().filter(|popup| popup.ship == ship).collect()
+    }
+
+    fn pop(&mut self, ship: usize, text: &'static str, color: [f32; 4]) {
+        self.popups.push(Popup {
+            ship: ship,
+            text: text,
+            color: color,
+            age: 0.0,
+        });
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+
+use circles::calc::{Point, pt, lerp, lerp_angle, distance, rotated_position};
+use circles::space::Planet;
+use circles::world::{World, TICK_TIME};
+use circles::ecs::{Entity, Transform, Velocity};
+
+// anything that moves further than this in one tick was put somewhere new (respawned, or on a new
+// level) rather than moved there, so it isn't blended
+const MAX_BLEND_DISTANCE: f64 = 200.0;
+
+/// The world only moves on in whole ticks, but frames come whenever they like. Interpolation
+/// remembers what things were like before the last tick, so that a frame can be drawn partway
+/// between the last two ticks and movement looks smooth at any frame rate.
+pub struct Interpolation {
+    ships: Vec<(Point, f64)>, // position and rotation
+    cameras: Vec<Point>,
+    entities: HashMap<Entity, Transform>,
+    pub alpha: f64, // how far the frame is from the previous tick to the last one
+}
+
+impl Interpolation {
+    pub fn new() -> Interpolation {
+        Interpolation {
+            ships: vec![],
+            cameras: vec![],
+            entities: HashMap::new(),
+            alpha: 1.0,
+        }
+    }
+
+    /// Remember the world as it is, just before it's updated.
+    pub fn capture(&mut self, world: &World) {
+        self.ships = world.ships
+            .iter()
+            .map(|ship| (ship.get_pos(&world.space), ship.rotation))
+            .collect();
+        self.cameras = world.ships.iter().map(|ship| ship.camera_pos).collect();
+        self.entities = world.space
+            .entities
+            .transforms
+            .iter()
+            .map(|(entity, &transform)| (entity, transform))
+            .collect();
+    }
+
+    /// Where to draw ship `i`, and which way it's facing.
+    pub fn ship(&self, world: &World, i: usize) -> (Point, f64) {
+        let ref ship = world.ships[i];
+        let pos = ship.get_pos(&world.space);
+        match self.ships.get(i) {
+            Some(&(old_pos, old_rotation)) if distance(old_pos, pos) < MAX_BLEND_DISTANCE => {
+                (self.blend(old_pos, pos), lerp_angle(old_rotation, ship.rotation, self.alpha))
+            }
+            _ => (pos, ship.rotation),
+        }
+    }
+
+    /// Where the camera following ship `i` is.
+    pub fn camera(&self, world: &World, i: usize) -> Point {
+        let pos = world.ships[i].camera_pos;
+        match self.cameras.get(i) {
+            Some(&old_pos) if distance(old_pos, pos) < MAX_BLEND_DISTANCE => {
+                self.blend(old_pos, pos)
+            }
+            _ => pos,
+        }
+    }
+
+    /// Where to draw a planet. Only moving planets move, and they know how far they went.
+    pub fn planet(&self, planet: &Planet) -> Point {
+        let old_pos = pt(planet.pos.x - planet.moved.x, planet.pos.y - planet.moved.y);
+        if distance(old_pos, planet.pos) < MAX_BLEND_DISTANCE {
+            self.blend(old_pos, planet.pos)
+        } else {
+            planet.pos
+        }
+    }
+
+    /// Where to draw an entity, and which way it's facing, if it's still there. Things on a
+    /// planet go round it and move with it, and things with a velocity go in straight lines, so
+    /// where they were is easy to tell.
+    pub fn entity(&self, world: &World, entity: Entity) -> Option<Transform> {
+        let ref entities = world.space.entities;
+        let transform = match entities.transforms.get(entity) {
+            Some(&transform) => transform,
+            None => return None,
+        };
+        if let Some(&Velocity(vel)) = entities.velocities.get(entity) {
+            let back = TICK_TIME * (1.0 - self.alpha);
+            return Some(Transform {
+                pos: pt(transform.pos.x - vel.x * back, transform.pos.y - vel.y * back),
+                rotation: transform.rotation,
+            });
+        }
+        let old = match self.entities.get(&entity) {
+            Some(&old) => old,
+            None => return Some(transform),
+        };
+        let rotation = lerp_angle(old.rotation, transform.rotation, self.alpha);
+        let attached = entities.attachments
+            .get(entity)
+            .and_then(|attachment| {
+                world.space.get_planet(attachment.planet).map(|planet| (attachment, planet))
+            });
+        match attached {
+            Some((attachment, planet)) => {
+                Some(Transform {
+                    pos: rotated_position(self.planet(planet),
+                                          rotation,
+                                          planet.radius + attachment.height),
+                    rotation: rotation,
+                })
+            }
+            None if distance(old.pos, transform.pos) < MAX_BLEND_DISTANCE => {
+                Some(Transform {
+                    pos: self.blend(old.pos, transform.pos),
+                    rotation: rotation,
+                })
+            }
+            None => Some(transform),
+        }
+    }
+
+    fn blend(&self, from: Point, to: Point) -> Point {
+        pt(lerp(from.x, to.x, self.alpha), lerp(from.y, to.y, self.alpha))
+    }
+}
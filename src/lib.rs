@@ -0,0 +1,22 @@
+//! The parts of the game that don't need a window: the space, the ships and the rules that move
+//! them, and the network protocol. The game and the server are both built on this.
+
+extern crate ncollide;
+extern crate nalgebra as na;
+extern crate rand;
+
+pub mod calc;
+pub mod space;
+pub mod ship;
+pub mod world;
+pub mod net;
+pub mod client;
+pub mod replay;
+pub mod env;
+pub mod autopilot;
+pub mod reach;
+pub mod difficulty;
+pub mod generator;
+pub mod grid;
+pub mod ecs;
+pub mod upgrade;
@@ -1,21 +1,60 @@
 // apparently I will be able to add this soon to make this app not open a console window on start
 // #![windows_subsystem = "Windows"]
 extern crate piston_window;
-extern crate ncollide;
-extern crate nalgebra as na;
+extern crate rand;
 extern crate find_folder;
 extern crate fps_counter;
 extern crate image as im; // "image" conflicts with something from piston_window
+extern crate circles;
+
+use std::env;
+use std::path::Path;
 
 use piston_window::{PistonWindow, WindowSettings, Glyphs, EventLoop, UpdateEvent};
-mod space;
-mod calc;
 mod game;
 mod render;
+mod particles;
+mod starfield;
+mod sprites;
+mod audio;
+mod profile;
+mod interpolation;
+mod hud;
+mod shop;
 
-use game::App;
+use circles::world::Mode;
+use circles::difficulty::Preset;
+use circles::generator::Generator;
+use game::{App, Settings};
 
 fn main() {
+    let mut settings = Settings::new();
+    let mut server = None;
+    let mut watch = None;
+    let mut replay = None;
+    let mut record = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--race" => settings.mode = Mode::Race,
+            "--versus" => settings.mode = Mode::Versus,
+            "--easy" => settings.preset = Preset::Easy,
+            "--normal" => settings.preset = Preset::Normal,
+            "--hard" => settings.preset = Preset::Hard,
+            "--adaptive" => settings.adaptive = true,
+            "--generator" => {
+                match args.next().map(|name| Generator::parse_choice(&name)) {
+                    Some(Some(generator)) => settings.generator = generator,
+                    _ => println!("--generator takes one of {}", Generator::choices()),
+                }
+            }
+            "--connect" => server = args.next(),
+            "--watch" => watch = args.next(),
+            "--replay" => replay = args.next(),
+            "--record" => record = args.next(),
+            _ => println!("Unknown argument {}", arg),
+        }
+    }
     let mut window: PistonWindow = WindowSettings::new("Circles", [1024, 768])
         .exit_on_esc(true)
         .vsync(true)
@@ -32,7 +71,20 @@ fn main() {
     // do that.
     let mut glyphs: Glyphs = Glyphs::new(font, factory).unwrap();
 
-    let mut app = App::new(&mut window);
+    let mut app = if let Some(addr) = server {
+        App::connect(&mut window, &assets, &addr).expect("couldn't connect to the server")
+    } else if let Some(addr) = watch {
+        App::watch(&mut window, &assets, &addr).expect("couldn't connect to the server")
+    } else if let Some(path) = replay {
+        App::replay(&mut window, &assets, Path::new(&path)).expect("couldn't load the replay")
+    } else {
+        App::new(&mut window, &assets, settings)
+    };
+    if let Some(path) = record {
+        // a recording is of the game, not the title screen
+        app.start_game(&mut window);
+        app.record(Path::new(&path)).expect("couldn't create the recording");
+    }
 
     let mut fps_counter = fps_counter::FPSCounter::new();
     while let Some(e) = window.next() {
@@ -42,4 +94,5 @@ fn main() {
         }
         app.render(&mut window, &e, &mut glyphs, &mut fps_counter);
     }
+    app.quit();
 }
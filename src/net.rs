@@ -0,0 +1,1150 @@
+use std::io;
+
+use space::Area;
+use calc::{Point, pt, distance};
+use ship::GameInput;
+use world::{World, Mode, GameEvent};
+use difficulty::Difficulty;
+use generator::{Generator, GENERATORS};
+
+pub const DEFAULT_PORT: u16 = 7777;
+// bigger than any packet we send, and smaller than the biggest UDP packet
+pub const MAX_PACKET_SIZE: usize = 16384;
+// other players only see this many of each ship's bullets, the ones closest to it. At the fastest
+// fire rate there can be over a hundred, which with a few ships wouldn't fit in a packet.
+pub const MAX_SENT_BULLETS: usize = 32;
+// events are sent in every snapshot for this many ticks after they happen, so one lost packet
+// doesn't lose them
+pub const EVENT_TICKS: u32 = 30;
+// bumped whenever the format of the messages changes
+const PROTOCOL_VERSION: u8 = 13;
+// how far the moving planets can get from the server's before they're put back in step
+const MAX_TIME_DRIFT: f64 = 0.2;
+
+/// The parts of `GameInput` that the server needs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InputState {
+    pub left: bool,
+    pub right: bool,
+    pub up: bool,
+    pub down: bool,
+    pub jump: bool,
+    pub shooting: bool,
+    pub attach: bool,
+    pub dash: bool,
+    pub shoot_target: Option<Point>,
+}
+
+impl InputState {
+    pub fn from_input(input: &GameInput) -> InputState {
+        InputState {
+            left: input.left,
+            right: input.right,
+            up: input.up,
+            down: input.down,
+            jump: input.jump,
+            shooting: input.shooting,
+            attach: input.attach,
+            dash: input.dash,
+            shoot_target: input.shoot_target,
+        }
+    }
+
+    /// Copy this state into a ship's input.
+    pub fn apply(&self, input: &mut GameInput) {
+        input.left = self.left;
+        input.right = self.right;
+        input.up = self.up;
+        input.down = self.down;
+        if !self.down {
+            input.ducked = false;
+        }
+        input.jump = self.jump;
+        if !self.jump {
+            input.jumped = false;
+        }
+        input.shooting = self.shooting;
+        input.attach = self.attach;
+        if !self.attach {
+            input.thrown = false;
+        }
+        input.dash = self.dash;
+        input.shoot_target = self.shoot_target;
+    }
+}
+
+/// Everything about a ship that's needed to draw it and to predict where it goes next.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShipState {
+    pub rotation: f64,
+    pub height: f64,
+    pub exit_speed: f64,
+    pub flying: bool,
+    pub jumping: bool,
+    pub double_jumped: bool,
+    pub pounding: bool,
+    pub charge: f64,
+    pub dash_time: f64,
+    pub dash_dir: f64,
+    pub dash_cooldown: f64,
+    pub slide: f64,
+    pub swing: f64,
+    pub tether: Option<f64>,
+    pub heat: f64,
+    pub fuel: f64,
+    pub attached: (Area, usize),
+    pub score: i64,
+    pub bullets: Vec<(Point, f64)>, // position and direction
+}
+
+impl ShipState {
+    pub fn from_world(world: &World, i: usize) -> ShipState {
+        let ref ship = world.ships[i];
+        let ref entities = world.space.entities;
+        let ship_pos = ship.get_pos(&world.space);
+        let mut bullets: Vec<(Point, f64)> = entities.projectiles_of(i)
+            .into_iter()
+            .filter_map(|bullet| entities.transforms.get(bullet))
+            .map(|transform| (transform.pos, transform.rotation))
+            .collect();
+        bullets.sort_by(|a, b| {
+            distance(a.0, ship_pos).partial_cmp(&distance(b.0, ship_pos)).unwrap()
+        });
+        bullets.truncate(MAX_SENT_BULLETS);
+        ShipState {
+            rotation: ship.rotation,
+            height: ship.height,
+            exit_speed: ship.exit_speed,
+            flying: ship.flying,
+            jumping: ship.jumping,
+            double_jumped: ship.double_jumped,
+            pounding: ship.pounding,
+            charge: ship.charge,
+            dash_time: ship.dash_time,
+            dash_dir: ship.dash_dir,
+            dash_cooldown: ship.dash_cooldown,
+            slide: ship.slide,
+            swing: ship.swing,
+            tether: ship.tether,
+            heat: ship.heat,
+            fuel: ship.fuel,
+            attached: ship.attached_planet.to_parts(),
+            score: ship.score,
+            bullets: bullets,
+        }
+    }
+
+    /// Overwrite ship `i` with this state. The bullets are only copied if `bullets` is true, since
+    /// the player's own bullets are simulated locally, once a tick by `World::predict_ship`.
+    pub fn apply(&self, world: &mut World, i: usize, bullets: bool) {
+        let (area, idx) = self.attached;
+        let ref mut ship = world.ships[i];
+        match world.space.planet_index(area, idx) {
+            Some(planet) => ship.attached_planet = planet,
+            // the snapshot is probably from a different level
+            None => return,
+        }
+        ship.rotation = self.rotation;
+        ship.height = self.height;
+        ship.exit_speed = self.exit_speed;
+        ship.flying = self.flying;
+        ship.jumping = self.jumping;
+        ship.double_jumped = self.double_jumped;
+        ship.pounding = self.pounding;
+        ship.charge = self.charge;
+        ship.dash_time = self.dash_time;
+        ship.dash_dir = self.dash_dir;
+        ship.dash_cooldown = self.dash_cooldown;
+        ship.slide = self.slide;
+        ship.swing = self.swing;
+        ship.tether = self.tether;
+        ship.heat = self.heat;
+        ship.fuel = self.fuel;
+        ship.score = self.score;
+        if bullets {
+            // the bullets there are already are moved rather than made again, so they keep their
+            // slots
+            let ref mut entities = world.space.entities;
+            let mut existing = entities.projectiles_of(i).into_iter();
+            for &(pos, dir) in self.bullets.iter() {
+                match existing.next() {
+                    Some(bullet) => entities.place_bullet(bullet, pos, dir),
+                    None => {
+                        entities.spawn_bullet(i, pos, dir);
+                    }
+                }
+            }
+            for bullet in existing {
+                entities.despawn(bullet);
+            }
+        }
+    }
+}
+
+/// The state of the world as the server sees it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub tick: u32,
+    pub level: u32,
+    pub seed: u32,
+    // the level is generated from these and the seed
+    pub difficulty: Difficulty,
+    pub generator: Option<Generator>,
+    pub time: f64, // how long the level has been going, for the moving planets
+    pub your_ship: Option<u32>, // None for spectators
+    // the sequence number of the last input the server got from this client
+    pub last_input: u32,
+    pub ships: Vec<ShipState>,
+    pub bugs: Vec<(usize, f64)>, // entity slot and rotation of every live bug
+    pub planets: Vec<(Area, usize, f64)>, // area, index and radius of every crumbling planet
+    pub pickups: Vec<usize>, // entity slot of every pickup that's still there
+    // what happened in the last `EVENT_TICKS` ticks, and on which tick, oldest first. Clients
+    // have to leave out the ones they've seen in earlier snapshots.
+    pub events: Vec<(u32, GameEvent)>,
+}
+
+impl Snapshot {
+    pub fn from_world(world: &World,
+                      tick: u32,
+                      your_ship: Option<u32>,
+                      last_input: u32,
+                      events: Vec<(u32, GameEvent)>)
+                      -> Snapshot {
+        Snapshot {
+            tick: tick,
+            level: world.level,
+            seed: world.seed,
+            difficulty: world.difficulty,
+            generator: world.generator,
+            time: world.space.get_time(),
+            your_ship: your_ship,
+            last_input: last_input,
+            ships: (0..world.ships.len()).map(|i| ShipState::from_world(world, i)).collect(),
+            bugs: world.space.get_all_bugs(),
+            planets: world.space.get_crumbling_planets(),
+            pickups: world.space.get_all_pickups(),
+            events: events,
+        }
+    }
+
+    /// Cut the snapshot down until it fits in a packet: the bullets furthest from their ships go
+    /// first, then the oldest events. The rest is never big enough to matter.
+    pub fn fit(&mut self) {
+        while self.encoded_len() > MAX_PACKET_SIZE {
+            let most = self.ships.iter().map(|ship| ship.bullets.len()).max().unwrap_or(0);
+            if most > 0 {
+                for ship in self.ships.iter_mut().filter(|ship| ship.bullets.len() == most) {
+                    ship.bullets.pop();
+                }
+            } else if !self.events.is_empty() {
+                self.events.remove(0);
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// How big the snapshot is as a `ServerMessage`.
+    fn encoded_len(&self) -> usize {
+        let mut w = Writer::new();
+        w.u8(2);
+        w.snapshot(self);
+        w.buf.len()
+    }
+
+    /// Make the world's level, moving planets, number of ships, bugs, crumbling planets and pickups
+    /// match the snapshot. The ships themselves are left alone, since how to move them depends on
+    /// who's watching. The planets are only moved if they've drifted, since clients keep them
+    /// moving between snapshots.
+    pub fn sync(&self, world: &mut World) {
+        if self.seed != world.seed || self.level != world.level ||
+           self.difficulty != world.difficulty || self.generator != world.generator {
+            world.level = self.level;
+            world.difficulty = self.difficulty;
+            world.generator = self.generator;
+            world.load_level(self.seed);
+        }
+        if (world.space.get_time() - self.time).abs() > MAX_TIME_DRIFT {
+            world.set_time(self.time);
+        }
+        while world.ships.len() < self.ships.len() {
+            world.add_ship();
+        }
+        while world.ships.len() > self.ships.len() {
+            let last = world.ships.len() - 1;
+            world.remove_ship(last);
+        }
+        world.space.sync_bugs(&self.bugs);
+        world.space.sync_crumbling_planets(&self.planets);
+        world.space.sync_pickups(&self.pickups);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ClientMessage {
+    Hello,
+    Input { seq: u32, input: InputState },
+    Bye,
+    /// Like `Hello`, but just to watch. Spectators send this every so often to show they're still
+    /// there.
+    Watch,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ServerMessage {
+    Welcome { mode: Mode },
+    /// The server has no room for another ship.
+    Full,
+    Snapshot(Snapshot),
+}
+
+impl ClientMessage {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut w = Writer::new();
+        match *self {
+            ClientMessage::Hello => w.u8(0),
+            ClientMessage::Input { seq, ref input } => {
+                w.u8(1);
+                w.u32(seq);
+                w.input(input);
+            }
+            ClientMessage::Bye => w.u8(2),
+            ClientMessage::Watch => w.u8(3),
+        }
+        w.buf
+    }
+
+    pub fn decode(data: &[u8]) -> io::Result<ClientMessage> {
+        let mut r = Reader::new(data)?;
+        match r.u8()? {
+            0 => Ok(ClientMessage::Hello),
+            1 => {
+                Ok(ClientMessage::Input {
+                    seq: r.u32()?,
+                    input: r.input()?,
+                })
+            }
+            2 => Ok(ClientMessage::Bye),
+            3 => Ok(ClientMessage::Watch),
+            x => Err(bad_data(&format!("unknown client message {}", x))),
+        }
+    }
+}
+
+impl ServerMessage {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut w = Writer::new();
+        match *self {
+            ServerMessage::Welcome { mode } => {
+                w.u8(0);
+                w.u8(match mode {
+                    Mode::Solo => 0,
+                    Mode::Race => 1,
+                    Mode::Versus => 2,
+                });
+            }
+            ServerMessage::Full => w.u8(1),
+            ServerMessage::Snapshot(ref snapshot) => {
+                w.u8(2);
+                w.snapshot(snapshot);
+            }
+        }
+        w.buf
+    }
+
+    pub fn decode(data: &[u8]) -> io::Result<ServerMessage> {
+        let mut r = Reader::new(data)?;
+        match r.u8()? {
+            0 => {
+                let mode = match r.u8()? {
+                    0 => Mode::Solo,
+                    1 => Mode::Race,
+                    2 => Mode::Versus,
+                    x => return Err(bad_data(&format!("unknown mode {}", x))),
+                };
+                Ok(ServerMessage::Welcome { mode: mode })
+            }
+            1 => Ok(ServerMessage::Full),
+            2 => Ok(ServerMessage::Snapshot(r.snapshot()?)),
+            x => Err(bad_data(&format!("unknown server message {}", x))),
+        }
+    }
+}
+
+fn bad_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Builds a packet. Everything is big-endian.
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn new() -> Writer {
+        Writer { buf: vec![PROTOCOL_VERSION] }
+    }
+
+    fn u8(&mut self, n: u8) {
+        self.buf.push(n);
+    }
+
+    fn bool(&mut self, b: bool) {
+        self.u8(if b { 1 } else { 0 });
+    }
+
+    fn u16(&mut self, n: u16) {
+        self.u8((n >> 8) as u8);
+        self.u8(n as u8);
+    }
+
+    fn u32(&mut self, n: u32) {
+        for shift in [24, 16, 8, 0].iter() {
+            self.buf.push((n >> *shift) as u8);
+        }
+    }
+
+    fn u64(&mut self, n: u64) {
+        self.u32((n >> 32) as u32);
+        self.u32(n as u32);
+    }
+
+    fn i32(&mut self, n: i32) {
+        self.u32(n as u32);
+    }
+
+    fn i64(&mut self, n: i64) {
+        self.u64(n as u64);
+    }
+
+    fn f64(&mut self, n: f64) {
+        self.u64(n.to_bits());
+    }
+
+    fn point(&mut self, p: Point) {
+        self.f64(p.x);
+        self.f64(p.y);
+    }
+
+    fn area(&mut self, area: Area) {
+        self.i32(area.0);
+        self.i32(area.1);
+    }
+
+    fn input(&mut self, input: &InputState) {
+        let flags = [input.left,
+                     input.right,
+                     input.up,
+                     input.down,
+                     input.jump,
+                     input.shooting,
+                     input.attach,
+                     input.shoot_target.is_some(),
+                     input.dash];
+        let mut bits = 0;
+        for (i, &flag) in flags.iter().enumerate() {
+            if flag {
+                bits |= 1 << i;
+            }
+        }
+        self.u16(bits);
+        if let Some(target) = input.shoot_target {
+            self.point(target);
+        }
+    }
+
+    fn ship(&mut self, ship: &ShipState) {
+        self.f64(ship.rotation);
+        self.f64(ship.height);
+        self.f64(ship.exit_speed);
+        self.bool(ship.flying);
+        self.bool(ship.jumping);
+        self.bool(ship.double_jumped);
+        self.bool(ship.pounding);
+        self.f64(ship.charge);
+        self.f64(ship.dash_time);
+        self.f64(ship.dash_dir);
+        self.f64(ship.dash_cooldown);
+        self.f64(ship.slide);
+        self.f64(ship.swing);
+        self.bool(ship.tether.is_some());
+        self.f64(ship.tether.unwrap_or(0.0));
+        self.f64(ship.heat);
+        self.f64(ship.fuel);
+        self.area(ship.attached.0);
+        self.u32(ship.attached.1 as u32);
+        self.i64(ship.score);
+        self.u32(ship.bullets.len() as u32);
+        for &(pos, dir) in ship.bullets.iter() {
+            self.point(pos);
+            self.f64(dir);
+        }
+    }
+
+    fn difficulty(&mut self, difficulty: &Difficulty) {
+        self.u32(difficulty.num_planets);
+        self.f64(difficulty.crawler_percentage);
+        self.f64(difficulty.crawler_speed);
+        self.f64(difficulty.min_planet_distance);
+        self.f64(difficulty.max_planet_distance);
+        self.f64(difficulty.path_variance);
+    }
+
+    /// 0 for none, otherwise one more than its place in GENERATORS.
+    fn generator(&mut self, generator: Option<Generator>) {
+        let n = match generator {
+            Some(generator) => GENERATORS.iter().position(|&g| g == generator).unwrap() + 1,
+            None => 0,
+        };
+        self.u8(n as u8);
+    }
+
+    fn event(&mut self, event: &GameEvent) {
+        match *event {
+            GameEvent::Shot(ship) => {
+                self.u8(0);
+                self.u32(ship as u32);
+            }
+            GameEvent::Jumped(ship) => {
+                self.u8(1);
+                self.u32(ship as u32);
+            }
+            GameEvent::Landed { ship, pos, dir } => {
+                self.u8(2);
+                self.u32(ship as u32);
+                self.point(pos);
+                self.f64(dir);
+            }
+            GameEvent::Bounced { ship, pos, dir } => {
+                self.u8(3);
+                self.u32(ship as u32);
+                self.point(pos);
+                self.f64(dir);
+            }
+            GameEvent::BugKilled { by, pos } => {
+                self.u8(4);
+                self.u32(by as u32);
+                self.point(pos);
+            }
+            GameEvent::ShipHit { ship, by, pos } => {
+                self.u8(5);
+                self.u32(ship as u32);
+                self.bool(by.is_some());
+                self.u32(by.unwrap_or(0) as u32);
+                self.point(pos);
+            }
+            GameEvent::MagicPlanetReached(ship) => {
+                self.u8(6);
+                self.u32(ship as u32);
+            }
+            GameEvent::LevelReset => self.u8(7),
+            GameEvent::Attached { ship, pos, dir } => {
+                self.u8(8);
+                self.u32(ship as u32);
+                self.point(pos);
+                self.f64(dir);
+            }
+            GameEvent::ShipDamaged { ship, pos } => {
+                self.u8(9);
+                self.u32(ship as u32);
+                self.point(pos);
+            }
+            GameEvent::PickedUp { ship, pos } => {
+                self.u8(10);
+                self.u32(ship as u32);
+                self.point(pos);
+            }
+            GameEvent::Pounded { ship, pos } => {
+                self.u8(11);
+                self.u32(ship as u32);
+                self.point(pos);
+            }
+        }
+    }
+
+    fn snapshot(&mut self, snapshot: &Snapshot) {
+        self.u32(snapshot.tick);
+        self.u32(snapshot.level);
+        self.u32(snapshot.seed);
+        self.difficulty(&snapshot.difficulty);
+        self.generator(snapshot.generator);
+        self.f64(snapshot.time);
+        self.bool(snapshot.your_ship.is_some());
+        self.u32(snapshot.your_ship.unwrap_or(0));
+        self.u32(snapshot.last_input);
+        self.u32(snapshot.ships.len() as u32);
+        for ship in snapshot.ships.iter() {
+            self.ship(ship);
+        }
+        self.u32(snapshot.bugs.len() as u32);
+        for &(idx, rotation) in snapshot.bugs.iter() {
+            self.u32(idx as u32);
+            self.f64(rotation);
+        }
+        self.u32(snapshot.planets.len() as u32);
+        for &(area, idx, radius) in snapshot.planets.iter() {
+            self.area(area);
+            self.u32(idx as u32);
+            self.f64(radius);
+        }
+        self.u32(snapshot.pickups.len() as u32);
+        for &idx in snapshot.pickups.iter() {
+            self.u32(idx as u32);
+        }
+        self.u32(snapshot.events.len() as u32);
+        for &(tick, ref event) in snapshot.events.iter() {
+            self.u32(tick);
+            self.event(event);
+        }
+    }
+}
+
+/// Reads a packet built by `Writer`.
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8]) -> io::Result<Reader<'a>> {
+        let mut r = Reader { buf: buf, pos: 0 };
+        let version = r.u8()?;
+        if version != PROTOCOL_VERSION {
+            return Err(bad_data(&format!("protocol version {}, expected {}",
+                                         version,
+                                         PROTOCOL_VERSION)));
+        }
+        Ok(r)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        match self.buf.get(self.pos) {
+            Some(&n) => {
+                self.pos += 1;
+                Ok(n)
+            }
+            None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "packet too short")),
+        }
+    }
+
+    fn bool(&mut self) -> io::Result<bool> {
+        Ok(self.u8()? != 0)
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        Ok((self.u8()? as u16) << 8 | self.u8()? as u16)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let mut n = 0;
+        for _ in 0..4 {
+            n = (n << 8) | self.u8()? as u32;
+        }
+        Ok(n)
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        let high = self.u32()? as u64;
+        let low = self.u32()? as u64;
+        Ok((high << 32) | low)
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        Ok(self.u32()? as i32)
+    }
+
+    fn i64(&mut self) -> io::Result<i64> {
+        Ok(self.u64()? as i64)
+    }
+
+    fn f64(&mut self) -> io::Result<f64> {
+        Ok(f64::from_bits(self.u64()?))
+    }
+
+    fn point(&mut self) -> io::Result<Point> {
+        let x = self.f64()?;
+        let y = self.f64()?;
+        Ok(pt(x, y))
+    }
+
+    fn area(&mut self) -> io::Result<Area> {
+        let x = self.i32()?;
+        let y = self.i32()?;
+        Ok((x, y))
+    }
+
+    /// Read a length, refusing lengths that can't possibly fit in the rest of the packet, so a
+    /// corrupt packet can't make us allocate gigabytes.
+    fn len(&mut self) -> io::Result<usize> {
+        let len = self.u32()? as usize;
+        if len > self.buf.len() - self.pos {
+            Err(bad_data("length is longer than the packet"))
+        } else {
+            Ok(len)
+        }
+    }
+
+    fn input(&mut self) -> io::Result<InputState> {
+        let bits = self.u16()?;
+        let flag = |i: u16| bits & (1 << i) != 0;
+        Ok(InputState {
+            left: flag(0),
+            right: flag(1),
+            up: flag(2),
+            down: flag(3),
+            jump: flag(4),
+            shooting: flag(5),
+            attach: flag(6),
+            dash: flag(8),
+            shoot_target: if flag(7) { Some(self.point()?) } else { None },
+        })
+    }
+
+    fn ship(&mut self) -> io::Result<ShipState> {
+        let rotation = self.f64()?;
+        let height = self.f64()?;
+        let exit_speed = self.f64()?;
+        let flying = self.bool()?;
+        let jumping = self.bool()?;
+        let double_jumped = self.bool()?;
+        let pounding = self.bool()?;
+        let charge = self.f64()?;
+        let dash_time = self.f64()?;
+        let dash_dir = self.f64()?;
+        let dash_cooldown = self.f64()?;
+        let slide = self.f64()?;
+        let swing = self.f64()?;
+        let has_tether = self.bool()?;
+        let tether = self.f64()?;
+        let tether = if has_tether { Some(tether) } else { None };
+        let heat = self.f64()?;
+        let fuel = self.f64()?;
+        let attached = (self.area()?, self.u32()? as usize);
+        let score = self.i64()?;
+        let mut bullets = vec![];
+        for _ in 0..self.len()? {
+            let pos = self.point()?;
+            let dir = self.f64()?;
+            bullets.push((pos, dir));
+        }
+        Ok(ShipState {
+            rotation: rotation,
+            height: height,
+            exit_speed: exit_speed,
+            flying: flying,
+            jumping: jumping,
+            double_jumped: double_jumped,
+            pounding: pounding,
+            charge: charge,
+            dash_time: dash_time,
+            dash_dir: dash_dir,
+            dash_cooldown: dash_cooldown,
+            slide: slide,
+            swing: swing,
+            tether: tether,
+            heat: heat,
+            fuel: fuel,
+            attached: attached,
+            score: score,
+            bullets: bullets,
+        })
+    }
+
+    fn difficulty(&mut self) -> io::Result<Difficulty> {
+        Ok(Difficulty {
+            num_planets: self.u32()?,
+            crawler_percentage: self.f64()?,
+            crawler_speed: self.f64()?,
+            min_planet_distance: self.f64()?,
+            max_planet_distance: self.f64()?,
+            path_variance: self.f64()?,
+        })
+    }
+
+    fn generator(&mut self) -> io::Result<Option<Generator>> {
+        match self.u8()? as usize {
+            0 => Ok(None),
+            n if n <= GENERATORS.len() => Ok(Some(GENERATORS[n - 1])),
+            n => Err(bad_data(&format!("unknown generator {}", n))),
+        }
+    }
+
+    fn event(&mut self) -> io::Result<GameEvent> {
+        Ok(match self.u8()? {
+            0 => GameEvent::Shot(self.u32()? as usize),
+            1 => GameEvent::Jumped(self.u32()? as usize),
+            2 => {
+                GameEvent::Landed {
+                    ship: self.u32()? as usize,
+                    pos: self.point()?,
+                    dir: self.f64()?,
+                }
+            }
+            3 => {
+                GameEvent::Bounced {
+                    ship: self.u32()? as usize,
+                    pos: self.point()?,
+                    dir: self.f64()?,
+                }
+            }
+            4 => {
+                GameEvent::BugKilled {
+                    by: self.u32()? as usize,
+                    pos: self.point()?,
+                }
+            }
+            5 => {
+                let ship = self.u32()? as usize;
+                let has_by = self.bool()?;
+                let by = self.u32()? as usize;
+                GameEvent::ShipHit {
+                    ship: ship,
+                    by: if has_by { Some(by) } else { None },
+                    pos: self.point()?,
+                }
+            }
+            6 => GameEvent::MagicPlanetReached(self.u32()? as usize),
+            7 => GameEvent::LevelReset,
+            8 => {
+                GameEvent::Attached {
+                    ship: self.u32()? as usize,
+                    pos: self.point()?,
+                    dir: self.f64()?,
+                }
+            }
+            9 => {
+                GameEvent::ShipDamaged {
+                    ship: self.u32()? as usize,
+                    pos: self.point()?,
+                }
+            }
+            10 => {
+                GameEvent::PickedUp {
+                    ship: self.u32()? as usize,
+                    pos: self.point()?,
+                }
+            }
+            11 => {
+                GameEvent::Pounded {
+                    ship: self.u32()? as usize,
+                    pos: self.point()?,
+                }
+            }
+            x => return Err(bad_data(&format!("unknown event {}", x))),
+        })
+    }
+
+    fn snapshot(&mut self) -> io::Result<Snapshot> {
+        let tick = self.u32()?;
+        let level = self.u32()?;
+        let seed = self.u32()?;
+        let difficulty = self.difficulty()?;
+        let generator = self.generator()?;
+        let time = self.f64()?;
+        let has_ship = self.bool()?;
+        let ship = self.u32()?;
+        let your_ship = if has_ship { Some(ship) } else { None };
+        let last_input = self.u32()?;
+        let mut ships = vec![];
+        for _ in 0..self.len()? {
+            ships.push(self.ship()?);
+        }
+        let mut bugs = vec![];
+        for _ in 0..self.len()? {
+            let idx = self.u32()? as usize;
+            let rotation = self.f64()?;
+            bugs.push((idx, rotation));
+        }
+        let mut planets = vec![];
+        for _ in 0..self.len()? {
+            let area = self.area()?;
+            let idx = self.u32()? as usize;
+            let radius = self.f64()?;
+            planets.push((area, idx, radius));
+        }
+        let mut pickups = vec![];
+        for _ in 0..self.len()? {
+            pickups.push(self.u32()? as usize);
+        }
+        let mut events = vec![];
+        for _ in 0..self.len()? {
+            let tick = self.u32()?;
+            events.push((tick, self.event()?));
+        }
+        Ok(Snapshot {
+            tick: tick,
+            level: level,
+            seed: seed,
+            difficulty: difficulty,
+            generator: generator,
+            time: time,
+            your_ship: your_ship,
+            last_input: last_input,
+            ships: ships,
+            bugs: bugs,
+            planets: planets,
+            pickups: pickups,
+            events: events,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ship_state(tether: Option<f64>) -> ShipState {
+        ShipState {
+            rotation: 1.5,
+            height: 120.0,
+            exit_speed: -3.25,
+            flying: true,
+            jumping: false,
+            double_jumped: true,
+            pounding: false,
+            charge: 0.5,
+            dash_time: 0.1,
+            dash_dir: -1.0,
+            dash_cooldown: 0.75,
+            slide: 0.0,
+            swing: 2.5,
+            tether: tether,
+            heat: 1.0,
+            fuel: 2.0,
+            attached: ((-1, 2), 3),
+            score: -4,
+            bullets: vec![(pt(10.0, -20.0), 0.5), (pt(0.0, 1.0), -3.0)],
+        }
+    }
+
+    fn every_event() -> Vec<GameEvent> {
+        let pos = pt(-5.0, 7.5);
+        vec![GameEvent::Shot(1),
+             GameEvent::Jumped(0),
+             GameEvent::Landed {
+                 ship: 1,
+                 pos: pos,
+                 dir: 0.25,
+             },
+             GameEvent::Bounced {
+                 ship: 0,
+                 pos: pos,
+                 dir: -0.25,
+             },
+             GameEvent::Attached {
+                 ship: 1,
+                 pos: pos,
+                 dir: 3.0,
+             },
+             GameEvent::BugKilled { by: 1, pos: pos },
+             GameEvent::ShipHit {
+                 ship: 0,
+                 by: Some(1),
+                 pos: pos,
+             },
+             GameEvent::ShipHit {
+                 ship: 1,
+                 by: None,
+                 pos: pos,
+             },
+             GameEvent::ShipDamaged { ship: 0, pos: pos },
+             GameEvent::PickedUp { ship: 1, pos: pos },
+             GameEvent::Pounded { ship: 0, pos: pos },
+             GameEvent::MagicPlanetReached(1),
+             GameEvent::LevelReset]
+    }
+
+    fn round_trip(message: ServerMessage) {
+        let decoded = ServerMessage::decode(&message.encode()).unwrap();
+        assert_eq!(decoded, message);
+    }
+
+    #[test]
+    fn snapshots_survive_encoding() {
+        for &generator in [None, Some(GENERATORS[0]), Some(GENERATORS[GENERATORS.len() - 1])]
+            .iter() {
+            round_trip(ServerMessage::Snapshot(Snapshot {
+                tick: 1234,
+                level: 3,
+                seed: 0xdeadbeef,
+                difficulty: Difficulty::hard(),
+                generator: generator,
+                time: 12.5,
+                your_ship: Some(1),
+                last_input: 99,
+                ships: vec![ship_state(Some(240.0)), ship_state(None)],
+                bugs: vec![(0, 0.5), (7, -1.25)],
+                planets: vec![((0, 0), 2, 30.0), ((-3, 4), 0, 12.5)],
+                pickups: vec![4, 9],
+                events: every_event().into_iter().map(|event| (1230, event)).collect(),
+            }));
+        }
+        round_trip(ServerMessage::Snapshot(Snapshot {
+            tick: 0,
+            level: 1,
+            seed: 0,
+            difficulty: Difficulty::normal(),
+            generator: None,
+            time: 0.0,
+            your_ship: None,
+            last_input: 0,
+            ships: vec![],
+            bugs: vec![],
+            planets: vec![],
+            pickups: vec![],
+            events: vec![],
+        }));
+    }
+
+    #[test]
+    fn other_server_messages_survive_encoding() {
+        for &mode in [Mode::Solo, Mode::Race, Mode::Versus].iter() {
+            round_trip(ServerMessage::Welcome { mode: mode });
+        }
+        round_trip(ServerMessage::Full);
+    }
+
+    #[test]
+    fn client_messages_survive_encoding() {
+        let input = InputState {
+            left: true,
+            right: false,
+            up: true,
+            down: false,
+            jump: true,
+            shooting: false,
+            attach: true,
+            dash: true,
+            shoot_target: Some(pt(3.0, -4.0)),
+        };
+        let messages = vec![ClientMessage::Hello,
+                            ClientMessage::Input {
+                                seq: 42,
+                                input: input,
+                            },
+                            ClientMessage::Input {
+                                seq: 43,
+                                input: InputState { shoot_target: None, ..input },
+                            },
+                            ClientMessage::Bye,
+                            ClientMessage::Watch];
+        for message in messages {
+            assert_eq!(ClientMessage::decode(&message.encode()).unwrap(), message);
+        }
+    }
+
+    #[test]
+    fn other_protocol_versions_are_rejected() {
+        let mut data = ClientMessage::Hello.encode();
+        data[0] = PROTOCOL_VERSION.wrapping_add(1);
+        assert!(ClientMessage::decode(&data).is_err());
+    }
+
+    // the most ships a server takes
+    const MAX_SHIPS: usize = 8;
+
+    fn crowded_snapshot(bullets: usize) -> Snapshot {
+        let mut ship = ship_state(Some(100.0));
+        ship.bullets = (0..bullets).map(|i| (pt(i as f64, -(i as f64)), 0.5)).collect();
+        let mut events = vec![];
+        for tick in 0..10 {
+            events.extend(every_event().into_iter().map(|event| (tick, event)));
+        }
+        Snapshot {
+            tick: 1,
+            level: 1,
+            seed: 1,
+            difficulty: Difficulty::normal(),
+            generator: Some(GENERATORS[0]),
+            time: 0.0,
+            your_ship: Some(0),
+            last_input: 0,
+            ships: vec![ship; MAX_SHIPS],
+            bugs: (0..100).map(|i| (i, 0.0)).collect(),
+            planets: (0..50).map(|i| ((0, 0), i, 10.0)).collect(),
+            pickups: (0..100).collect(),
+            events: events,
+        }
+    }
+
+    #[test]
+    fn the_biggest_snapshots_fit_in_a_packet() {
+        let mut snapshot = crowded_snapshot(MAX_SENT_BULLETS);
+        let message = ServerMessage::Snapshot(snapshot.clone());
+        assert!(message.encode().len() <= MAX_PACKET_SIZE);
+        snapshot.fit();
+        assert_eq!(ServerMessage::Snapshot(snapshot), message);
+        round_trip(message);
+    }
+
+    #[test]
+    fn snapshots_are_cut_down_to_fit() {
+        let mut snapshot = crowded_snapshot(200);
+        assert!(ServerMessage::Snapshot(snapshot.clone()).encode().len() > MAX_PACKET_SIZE);
+        snapshot.fit();
+        let data = ServerMessage::Snapshot(snapshot.clone()).encode();
+        assert!(data.len() <= MAX_PACKET_SIZE);
+        assert_eq!(snapshot.events.len(), 10 * every_event().len());
+        assert_eq!(ServerMessage::decode(&data).unwrap(), ServerMessage::Snapshot(snapshot));
+    }
+
+    #[test]
+    fn only_the_closest_bullets_are_sent() {
+        let mut world = World::empty(Mode::Versus);
+        let i = world.add_ship();
+        let ship_pos = world.ships[i].get_pos(&world.space);
+        for n in 0..150 {
+            let pos = pt(ship_pos.x + n as f64 * 10.0, ship_pos.y);
+            world.space.entities.spawn_bullet(i, pos, 0.0);
+        }
+        let bullets = ShipState::from_world(&world, i).bullets;
+        assert_eq!(bullets.len(), MAX_SENT_BULLETS);
+        let furthest = MAX_SENT_BULLETS as f64 * 10.0;
+        assert!(bullets.iter().all(|&(pos, _)| pos.x - ship_pos.x < furthest));
+    }
+
+    #[test]
+    fn bullets_are_moved_in_place() {
+        let mut world = World::empty(Mode::Versus);
+        let i = world.add_ship();
+        let mut state = ShipState::from_world(&world, i);
+        state.bullets = vec![(pt(0.0, 0.0), 0.0), (pt(10.0, 0.0), 1.0)];
+        state.apply(&mut world, i, true);
+        let first = world.space.entities.projectiles_of(i);
+
+        state.bullets = vec![(pt(5.0, 0.0), 0.0), (pt(15.0, 0.0), 1.0), (pt(0.0, 5.0), 2.0)];
+        state.apply(&mut world, i, true);
+        let second = world.space.entities.projectiles_of(i);
+        assert_eq!(&second[..2], &first[..]);
+        assert_eq!(world.space.entities.get_pos(second[2]), Some(pt(0.0, 5.0)));
+
+        state.bullets = vec![(pt(20.0, 0.0), 1.0)];
+        state.apply(&mut world, i, true);
+        assert_eq!(world.space.entities.projectiles_of(i), vec![first[0]]);
+        assert_eq!(world.space.entities.get_pos(first[0]), Some(pt(20.0, 0.0)));
+    }
+
+    #[test]
+    fn events_keep_to_their_ships_when_one_leaves() {
+        let pos = pt(1.0, 2.0);
+        assert_eq!(GameEvent::Jumped(0).after_removing(1), Some(GameEvent::Jumped(0)));
+        assert_eq!(GameEvent::Jumped(1).after_removing(1), None);
+        assert_eq!(GameEvent::Jumped(2).after_removing(1), Some(GameEvent::Jumped(1)));
+        let hit = GameEvent::ShipHit {
+            ship: 3,
+            by: Some(2),
+            pos: pos,
+        };
+        assert_eq!(hit.after_removing(0),
+                   Some(GameEvent::ShipHit {
+                       ship: 2,
+                       by: Some(1),
+                       pos: pos,
+                   }));
+        assert_eq!(hit.after_removing(2), None);
+        assert_eq!(GameEvent::LevelReset.after_removing(0), Some(GameEvent::LevelReset));
+        for event in every_event() {
+            assert_eq!(event.after_removing(2), Some(event));
+        }
+    }
+}
@@ -0,0 +1,237 @@
+extern crate rand;
+
+use std::f64::consts::PI;
+
+use self::rand::Rng;
+use circles::calc::{Point, pt, rotated_position, lerp};
+use circles::world::GameEvent;
+
+const MAX_PARTICLES: usize = 2048;
+// particles lose this fraction of their velocity every second
+const DRAG: f64 = 0.8;
+
+/// The parameters for one kind of particle effect. `emit` and `emit_ring` spawn `count` particles
+/// using these.
+pub struct Effect {
+    pub count: u32,
+    pub spread: f64, // radians either side of the emit direction
+    pub min_speed: f64,
+    pub max_speed: f64,
+    pub min_life: f64,
+    pub max_life: f64,
+    pub size: f64,
+    pub from: [f32; 4], // colour at birth
+    pub to: [f32; 4], // colour at death
+}
+
+pub const EXHAUST: Effect = Effect {
+    count: 2,
+    spread: 0.3,
+    min_speed: 100.0,
+    max_speed: 200.0,
+    min_life: 0.2,
+    max_life: 0.4,
+    size: 4.0,
+    from: [1.0, 0.8, 0.2, 1.0],
+    to: [1.0, 0.0, 0.0, 0.0],
+};
+
+pub const DUST: Effect = Effect {
+    count: 12,
+    spread: PI / 2.0,
+    min_speed: 30.0,
+    max_speed: 90.0,
+    min_life: 0.3,
+    max_life: 0.6,
+    size: 3.0,
+    from: [0.7, 0.7, 0.7, 1.0],
+    to: [0.4, 0.4, 0.4, 0.0],
+};
+
+/// Dust thrown out all round where a ground pound lands, for `emit_ring`.
+pub const SHOCKWAVE: Effect = Effect {
+    count: 24,
+    spread: 0.2,
+    min_speed: 150.0,
+    max_speed: 250.0,
+    min_life: 0.3,
+    max_life: 0.5,
+    size: 4.0,
+    from: [0.8, 0.8, 0.8, 1.0],
+    to: [0.5, 0.5, 0.5, 0.0],
+};
+
+pub const EXPLOSION: Effect = Effect {
+    count: 40,
+    spread: PI,
+    min_speed: 50.0,
+    max_speed: 250.0,
+    min_life: 0.4,
+    max_life: 0.9,
+    size: 5.0,
+    from: [1.0, 1.0, 0.5, 1.0],
+    to: [0.5, 0.0, 0.0, 0.0],
+};
+
+pub const SPARKLE: Effect = Effect {
+    count: 1,
+    spread: 0.5,
+    min_speed: 10.0,
+    max_speed: 40.0,
+    min_life: 0.5,
+    max_life: 1.5,
+    size: 3.0,
+    from: [1.0, 1.0, 1.0, 1.0],
+    to: [1.0, 0.2, 0.2, 0.0],
+};
+
+// a quick streak towards the planet a ship attached to
+pub const TETHER: Effect = Effect {
+    count: 6,
+    spread: 0.1,
+    min_speed: 150.0,
+    max_speed: 300.0,
+    min_life: 0.1,
+    max_life: 0.3,
+    size: 2.0,
+    from: [0.5, 0.5, 1.0, 1.0],
+    to: [0.0, 0.0, 1.0, 0.0],
+};
+
+// a puff where a fuel can was picked up
+pub const REFUEL: Effect = Effect {
+    count: 12,
+    spread: PI,
+    min_speed: 20.0,
+    max_speed: 80.0,
+    min_life: 0.2,
+    max_life: 0.6,
+    size: 3.0,
+    from: [0.5, 0.5, 1.0, 1.0],
+    to: [1.0, 1.0, 1.0, 0.0],
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Particle {
+    pub pos: Point,
+    pub vel: Point, // units per second
+    pub life: f64, // seconds left to live
+    pub max_life: f64,
+    pub size: f64,
+    pub from: [f32; 4],
+    pub to: [f32; 4],
+}
+
+impl Particle {
+    /// The particle's current colour, faded from `from` to `to` over its lifetime.
+    pub fn color(&self) -> [f32; 4] {
+        let t = 1.0 - (self.life / self.max_life);
+        let mut color = [0.0; 4];
+        for i in 0..4 {
+            color[i] = lerp(self.from[i] as f64, self.to[i] as f64, t) as f32;
+        }
+        color
+    }
+
+    fn alive(&self) -> bool {
+        self.life > 0.0
+    }
+}
+
+/// ParticleSystem is a fixed-size pool of particles. Dead particles stay in the pool and get
+/// reused by the next emit, so effects that fire every frame (like exhaust) don't allocate.
+pub struct ParticleSystem {
+    particles: Vec<Particle>,
+    // indices of dead particles in `particles`
+    free: Vec<usize>,
+}
+
+impl ParticleSystem {
+    pub fn new() -> Self {
+        ParticleSystem {
+            particles: vec![],
+            free: vec![],
+        }
+    }
+
+    /// Spawn particles at `pos`, heading in `dir` (radians).
+    pub fn emit(&mut self, effect: &Effect, pos: Point, dir: f64) {
+        let mut rng = rand::thread_rng();
+        for _ in 0..effect.count {
+            let angle = dir + lerp(-effect.spread, effect.spread, rng.gen());
+            let speed = lerp(effect.min_speed, effect.max_speed, rng.gen());
+            let life = lerp(effect.min_life, effect.max_life, rng.gen());
+            self.spawn(Particle {
+                pos: pos,
+                vel: rotated_position(pt(0.0, 0.0), angle, speed),
+                life: life,
+                max_life: life,
+                size: effect.size,
+                from: effect.from,
+                to: effect.to,
+            });
+        }
+    }
+
+    /// Spawn the effect for something that happened in the world, if it has one.
+    pub fn react(&mut self, event: &GameEvent) {
+        match *event {
+            GameEvent::Landed { pos, dir, .. } |
+            GameEvent::Bounced { pos, dir, .. } => self.emit(&DUST, pos, dir),
+            GameEvent::Attached { pos, dir, .. } => self.emit(&TETHER, pos, dir),
+            GameEvent::PickedUp { pos, .. } => self.emit(&REFUEL, pos, 0.0),
+            GameEvent::Pounded { pos, .. } => self.emit_ring(&SHOCKWAVE, pos, 10.0),
+            GameEvent::BugKilled { pos, .. } |
+            GameEvent::ShipHit { pos, .. } |
+            GameEvent::ShipDamaged { pos, .. } => self.emit(&EXPLOSION, pos, 0.0),
+            // the old level's particles would be floating about in the new one
+            GameEvent::LevelReset => self.clear(),
+            _ => {}
+        }
+    }
+
+    /// Spawn particles at random points on the edge of a circle, heading outwards.
+    pub fn emit_ring(&mut self, effect: &Effect, center: Point, radius: f64) {
+        let mut rng = rand::thread_rng();
+        for _ in 0..effect.count {
+            let angle = lerp(-PI, PI, rng.gen());
+            let pos = rotated_position(center, angle, radius);
+            self.emit(&Effect { count: 1, ..*effect }, pos, angle);
+        }
+    }
+
+    fn spawn(&mut self, particle: Particle) {
+        if let Some(idx) = self.free.pop() {
+            self.particles[idx] = particle;
+        } else if self.particles.len() < MAX_PARTICLES {
+            self.particles.push(particle);
+        }
+        // otherwise the pool is full and the particle is dropped.
+    }
+
+    pub fn update(&mut self, time_delta: f64) {
+        let drag = 1.0 - (DRAG * time_delta).min(1.0);
+        for (idx, particle) in self.particles.iter_mut().enumerate() {
+            if !particle.alive() {
+                continue;
+            }
+            particle.pos.x += particle.vel.x * time_delta;
+            particle.pos.y += particle.vel.y * time_delta;
+            particle.vel.x *= drag;
+            particle.vel.y *= drag;
+            particle.life -= time_delta;
+            if !particle.alive() {
+                self.free.push(idx);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.particles.clear();
+        self.free.clear();
+    }
+
+    pub fn get_alive(&self) -> Vec<&Particle> {
+        self.particles.iter().filter(|p| p.alive()).collect()
+    }
+}
@@ -0,0 +1,159 @@
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use circles::world::GameEvent;
+use circles::upgrade::{Upgrade, Upgrades, UPGRADES};
+
+const MAX_HIGH_SCORES: usize = 10;
+
+/// Profile holds the player's lifetime statistics and best scores, and the credits and ship
+/// upgrades they carry from game to game. It's saved as a plain text file
+/// with one `key value` pair per line, so it's easy to look at (or cheat with).
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    pub levels_completed: u64,
+    pub deaths: u64,
+    pub crawlers_killed: u64,
+    pub shots_fired: u64,
+    pub shots_hit: u64,
+    pub distance_flown: f64,
+    pub streak: u64, // wins in a row, reset by dying
+    pub best_streak: u64,
+    pub high_scores: Vec<i64>, // best first
+    pub credits: u64,
+    pub upgrades: Upgrades,
+}
+
+impl Profile {
+    /// Where the profile is kept: `.circles_profile` in the user's home directory.
+    pub fn default_path() -> Option<PathBuf> {
+        env::home_dir().map(|home| home.join(".circles_profile"))
+    }
+
+    /// Load a profile, starting a fresh one if the file doesn't exist or can't be read.
+    pub fn load(path: &Path) -> Profile {
+        let mut profile = Profile::default();
+        let file = match File::open(path) {
+            Ok(f) => f,
+            Err(e) => {
+                println!("Starting a new profile, couldn't read {}: {}", path.display(), e);
+                return profile;
+            }
+        };
+        for line in BufReader::new(file).lines() {
+            let line = match line {
+                Ok(l) => l,
+                Err(_) => break,
+            };
+            let mut parts = line.split_whitespace();
+            if let (Some(key), Some(value)) = (parts.next(), parts.next()) {
+                if let Err(e) = profile.set(key, value) {
+                    println!("Ignoring bad profile line {:?}: {}", line, e);
+                }
+            }
+        }
+        profile
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let int = || value.parse::<u64>().map_err(|e| e.to_string());
+        let float = || value.parse::<f64>().map_err(|e| e.to_string());
+        let signed = || value.parse::<i64>().map_err(|e| e.to_string());
+        match key {
+            "levels_completed" => self.levels_completed = int()?,
+            "deaths" => self.deaths = int()?,
+            "crawlers_killed" => self.crawlers_killed = int()?,
+            "shots_fired" => self.shots_fired = int()?,
+            "shots_hit" => self.shots_hit = int()?,
+            "distance_flown" => self.distance_flown = float()?,
+            "streak" => self.streak = int()?,
+            "best_streak" => self.best_streak = int()?,
+            "high_score" => {
+                let score = signed()?;
+                self.add_high_score(score);
+            }
+            "credits" => self.credits = int()?,
+            _ => {
+                match Upgrade::from_key(key) {
+                    Some(upgrade) => self.upgrades.set(upgrade, int()? as u32),
+                    None => return Err(format!("unknown key {}", key)),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut f = File::create(path)?;
+        writeln!(f, "levels_completed {}", self.levels_completed)?;
+        writeln!(f, "deaths {}", self.deaths)?;
+        writeln!(f, "crawlers_killed {}", self.crawlers_killed)?;
+        writeln!(f, "shots_fired {}", self.shots_fired)?;
+        writeln!(f, "shots_hit {}", self.shots_hit)?;
+        writeln!(f, "distance_flown {}", self.distance_flown)?;
+        writeln!(f, "streak {}", self.streak)?;
+        writeln!(f, "best_streak {}", self.best_streak)?;
+        for score in self.high_scores.iter() {
+            writeln!(f, "high_score {}", score)?;
+        }
+        writeln!(f, "credits {}", self.credits)?;
+        for upgrade in UPGRADES.iter() {
+            writeln!(f, "{} {}", upgrade.key(), self.upgrades.get(*upgrade))?;
+        }
+        Ok(())
+    }
+
+    /// Fraction of shots that hit a crawler, between 0 and 1.
+    pub fn accuracy(&self) -> f64 {
+        if self.shots_fired == 0 {
+            0.0
+        } else {
+            self.shots_hit as f64 / self.shots_fired as f64
+        }
+    }
+
+    /// Count something that happened in the world towards the statistics of the player flying
+    /// `ship`. Returns true for a win or a death, after which the profile should be saved.
+    pub fn record(&mut self, event: &GameEvent, ship: usize) -> bool {
+        match *event {
+            GameEvent::Shot(by) if by == ship => self.shots_fired += 1,
+            GameEvent::BugKilled { by, .. } if by == ship => {
+                self.crawlers_killed += 1;
+                self.shots_hit += 1;
+            }
+            GameEvent::ShipHit { ship: victim, .. } if victim == ship => {
+                self.record_death();
+                return true;
+            }
+            GameEvent::MagicPlanetReached(winner) if winner == ship => {
+                self.record_win();
+                return true;
+            }
+            _ => {}
+        }
+        false
+    }
+
+    pub fn record_win(&mut self) {
+        self.levels_completed += 1;
+        self.streak += 1;
+        self.best_streak = self.best_streak.max(self.streak);
+    }
+
+    pub fn record_death(&mut self) {
+        self.deaths += 1;
+        self.streak = 0;
+    }
+
+    /// Put a score in the high score table, if it's good enough.
+    pub fn add_high_score(&mut self, score: i64) {
+        let pos = self.high_scores
+            .iter()
+            .position(|&s| score > s)
+            .unwrap_or(self.high_scores.len());
+        self.high_scores.insert(pos, score);
+        self.high_scores.truncate(MAX_HIGH_SCORES);
+    }
+}
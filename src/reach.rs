@@ -0,0 +1,236 @@
+use std::ops::Range;
+
+use calc::distance;
+use space::{Space, Planet, PlanetIndex, Generation, MAGIC_PLANET_SIZE};
+use ship::{SHIP_SIZE, JUMP_SPEED, GRAVITY, FLY_SPEED, ACCELERATION, MAX_FUEL, FLY_BURN,
+           BOOST_BURN};
+use world::TICK_TIME;
+use ecs::Entity;
+use difficulty::Difficulty;
+use generator::Generator;
+
+// the longest flight a level can ask for. Ships can fly forever, but crossing the whole of space
+// in one go isn't much of a game.
+const FLIGHT_TIME: f64 = 1.5;
+
+/// How far from a planet's surface the ship can get in one go.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Envelope {
+    pub jump: f64, // the top of a jump
+    pub flight: f64,
+}
+
+impl Envelope {
+    /// The envelope of the ship as it is.
+    pub fn ship() -> Envelope {
+        let mut jump = 0.0;
+        let mut speed = JUMP_SPEED;
+        while speed > 0.0 {
+            speed -= GRAVITY * TICK_TIME;
+            jump += speed;
+        }
+
+        // flying flat out, until the fuel runs out if it runs out first
+        let flight_time = FLIGHT_TIME.min(MAX_FUEL / (FLY_BURN + BOOST_BURN));
+        let mut flight = 0.0;
+        let mut speed = FLY_SPEED;
+        let mut time = 0.0;
+        while time < flight_time {
+            flight += speed;
+            speed += ACCELERATION * TICK_TIME;
+            time += TICK_TIME;
+        }
+        Envelope {
+            jump: jump,
+            flight: flight,
+        }
+    }
+
+    /// Whether the ship can get across a gap between the surfaces of two planets. A jump only
+    /// has to get halfway: at the top, the far planet is the closer one, so it can be attached to
+    /// and fallen onto.
+    pub fn can_cross(&self, gap: f64) -> bool {
+        gap < self.jump * 2.0 || gap < self.flight
+    }
+}
+
+/// Why a level can't be won.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Problem {
+    /// There's no way from the first planet to the magic planet. `closest` is the planet closest
+    /// to the magic planet that can be got to.
+    Unreachable { closest: PlanetIndex },
+    /// A crawler walks over the start, so ships die before they can get going.
+    CrawlerAtStart(Entity),
+}
+
+/// Check that a level can be won with the ship's `envelope`. Returns the shortest route, in hops,
+/// from the first planet to the last planet before the magic planet.
+pub fn check(space: &Space, envelope: &Envelope) -> Result<Vec<PlanetIndex>, Problem> {
+    let start = space.get_first_planet();
+    let start_planet = space.get_start_planet();
+    // hazards that stay on a planet; ones that move freely aren't part of the level's layout
+    let ref entities = space.entities;
+    for (entity, attachment) in entities.attachments.iter() {
+        let size = match entities.colliders.get(entity) {
+            Some(collider) if entities.hazards.contains(entity) => collider.radius,
+            _ => continue,
+        };
+        let planet = match space.get_planet(attachment.planet) {
+            Some(planet) => planet,
+            None => continue,
+        };
+        let orbit = planet.radius + attachment.height;
+        // a moving planet might be anywhere it goes
+        let (center, sweep) = match planet.motion {
+            Some(ref motion) => motion.sweep(space),
+            None => (planet.pos, 0.0),
+        };
+        // the closest the crawler gets to anywhere a ship can start
+        let apart = (distance(center, start_planet.pos) - sweep).max(0.0);
+        let closest = if apart > orbit + start_planet.radius {
+            apart - orbit - start_planet.radius
+        } else if apart < (orbit - start_planet.radius).abs() {
+            (orbit - start_planet.radius).abs() - apart
+        } else {
+            0.0
+        };
+        if closest < size + SHIP_SIZE / 2.0 {
+            return Err(Problem::CrawlerAtStart(entity));
+        }
+    }
+
+    // breadth first over the planets that can be landed on, so the route has the fewest hops.
+    // Moving planets are left out, since whether they help depends on when you get to them.
+    // Planets are numbered by where they are in `planets`.
+    let planets: Vec<(PlanetIndex, &Planet)> = space.get_all_planet_indices()
+        .into_iter()
+        .filter_map(|idx| space.get_planet(idx).map(|planet| (idx, planet)))
+        .filter(|&(_, planet)| planet.is_solid() && planet.motion.is_none())
+        .collect();
+    let magic_planet = space.get_magic_planet();
+    let mut came_from: Vec<Option<usize>> = vec![None; planets.len()];
+    let mut seen = vec![false; planets.len()];
+    let mut queue = vec![];
+    if let Some(first) = planets.iter().position(|&(p, _)| p == start) {
+        seen[first] = true;
+        queue.push(first);
+    }
+    let mut next = 0;
+    let mut closest = (start, f64::INFINITY);
+    while next < queue.len() {
+        let here = queue[next];
+        next += 1;
+        let here_planet = planets[here].1;
+        let gap = distance(here_planet.pos, magic_planet) - here_planet.radius -
+                  MAGIC_PLANET_SIZE - SHIP_SIZE;
+        if gap < closest.1 {
+            closest = (planets[here].0, gap);
+        }
+        if envelope.can_cross(gap) {
+            let mut route = vec![planets[here].0];
+            let mut at = here;
+            while let Some(from) = came_from[at] {
+                route.push(planets[from].0);
+                at = from;
+            }
+            route.reverse();
+            return Ok(route);
+        }
+        for there in 0..planets.len() {
+            if seen[there] {
+                continue;
+            }
+            let there_planet = planets[there].1;
+            let gap = distance(here_planet.pos, there_planet.pos) - here_planet.radius -
+                      there_planet.radius - SHIP_SIZE;
+            if envelope.can_cross(gap) {
+                seen[there] = true;
+                came_from[there] = Some(here);
+                queue.push(there);
+            }
+        }
+    }
+    Err(Problem::Unreachable { closest: closest.0 })
+}
+
+/// Whether the level can be won by the ship as it is.
+pub fn is_solvable(space: &Space) -> bool {
+    check(space, &Envelope::ship()).is_ok()
+}
+
+/// Generate the level for every seed in `seeds`, and say how each one went. The levels that come
+/// out can always be won, but not always as the generator first made them.
+pub fn generations(seeds: Range<u32>,
+                   difficulty: &Difficulty,
+                   generator: Option<Generator>)
+                   -> Vec<(u32, Generation)> {
+    seeds.map(|seed| {
+            let generator = generator.unwrap_or_else(|| Generator::for_seed(seed));
+            (seed, Space::generate(seed, difficulty, &*generator.get()).1)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use calc::pt;
+    use space::PlanetKind;
+    use difficulty::Preset;
+    use generator::{GENERATORS, make_solvable};
+
+    #[test]
+    fn generators_make_levels_that_can_be_won() {
+        // `circles-playtest --check` goes through many more
+        for preset in [Preset::Easy, Preset::Normal, Preset::Hard].iter() {
+            for &generator in GENERATORS.iter() {
+                let generations = generations(0..40, &preset.difficulty(), Some(generator));
+                let retried = generations.iter()
+                    .filter(|&&(_, generation)| generation.first_problem.is_some())
+                    .count();
+                let what = format!("{:?} levels from {}", preset, generator.name());
+                // a few bad tries are what the retries are for, but not many
+                assert!(retried < generations.len() / 4, "{}: {} retried", what, retried);
+                for (seed, generation) in generations {
+                    assert!(!generation.repaired, "{}: {} patched up", what, seed);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn levels_that_cant_be_won_are_patched_up() {
+        let mut space = Space::empty();
+        space.set_magic_planet(pt(4000.0, 3000.0));
+        let start = space.get_first_planet();
+        let crawler = space.entities.spawn_crawler(start, 0.0, true);
+        assert_eq!(check(&space, &Envelope::ship()),
+                   Err(Problem::CrawlerAtStart(crawler)));
+
+        assert_eq!(make_solvable(&mut space), Ok(()));
+        assert!(is_solvable(&space));
+        assert!(!space.entities.hazards.contains(crawler));
+    }
+
+    #[test]
+    fn patching_up_goes_around_planets_or_moves_the_magic_planet() {
+        let mut space = Space::empty();
+        space.set_magic_planet(pt(4000.0, 0.0));
+        space.add_planet(Planet::new(pt(1000.0, 0.0), 200.0, PlanetKind::GasGiant));
+        assert_eq!(make_solvable(&mut space), Ok(()));
+        assert_eq!(space.get_magic_planet(), pt(4000.0, 0.0));
+        for planet in space.get_all_planets() {
+            if planet.kind != PlanetKind::GasGiant {
+                assert!(distance(planet.pos, pt(1000.0, 0.0)) > 200.0 + planet.radius);
+            }
+        }
+
+        // nowhere to put anything
+        let mut space = Space::empty();
+        space.set_magic_planet(pt(8000.0, 0.0));
+        space.add_planet(Planet::new(pt(0.0, 0.0), 3000.0, PlanetKind::GasGiant));
+        assert_eq!(make_solvable(&mut space), Ok(()));
+        assert!(distance(space.get_magic_planet(), pt(0.0, 0.0)) < 1000.0);
+    }
+}
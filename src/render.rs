@@ -1,12 +1,19 @@
 use std::f64::consts::PI;
 
-use piston_window::{G2d, Glyphs, Window, PistonWindow, Event, Context, Rectangle, Size,
-                    Transformed, clear, rectangle, ellipse, line, image, text};
+use piston_window::{G2d, G2dTexture, Glyphs, Window, PistonWindow, Event, Context, Rectangle,
+                    Ellipse, Line, Image, Size, ImageSize, Transformed, clear, rectangle,
+                    ellipse, text};
 use fps_counter;
 
-use game::{App, BULLET_SIZE, MINI_SIZE, SHIP_SIZE, CRAWLER_SIZE};
-use calc::{Point, shrink_to_bounds, rotated_position, direction_from_to};
-use space::{Area, Planet, PlanetIndex, MAGIC_PLANET_SIZE};
+use circles::ship::{SHIP_SIZE, MAX_HEAT, MAX_FUEL, MAX_CHARGE, DASH_COOLDOWN};
+use circles::calc::{Point, shrink_to_bounds, rotated_position, direction_from_to};
+use circles::space::{Planet, PlanetIndex, PlanetKind, MAGIC_PLANET_SIZE};
+use circles::ecs::{Entity, Renderable};
+use circles::upgrade::MAX_UPGRADE_LEVEL;
+use game::{App, Spectator, CameraMode, MINI_SIZE, player_color};
+use shop::Shop;
+use starfield::LAYERS;
+use sprites::CRAWLER_FRAME_TIME;
 
 
 pub const GREEN: [f32; 4] = [0.0, 1.0, 0.0, 1.0];
@@ -14,12 +21,30 @@ pub const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
 pub const DARKRED: [f32; 4] = [0.5, 0.0, 0.0, 1.0];
 pub const LIGHTBLUE: [f32; 4] = [0.5, 0.5, 1.0, 1.0];
 pub const BLUE: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+pub const ICE: [f32; 4] = [0.8, 1.0, 1.0, 1.0];
+pub const PURPLE: [f32; 4] = [0.5, 0.0, 0.5, 1.0];
+pub const ORANGE: [f32; 4] = [1.0, 0.5, 0.0, 1.0];
+pub const TAN: [f32; 4] = [0.8, 0.6, 0.3, 0.5];
+pub const BROWN: [f32; 4] = [0.5, 0.3, 0.1, 1.0];
 pub const BLACK: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
 pub const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+pub const GRAY: [f32; 4] = [0.5, 0.5, 0.5, 1.0];
 
 
 type Transform = [[f64; 3]; 2];
 
+/// One player's part of the window, or the spectator's. Everything drawn through `context` is
+/// clipped to the viewport, which is why the drawing code passes `context.draw_state` around
+/// instead of using the `rectangle`/`ellipse` shortcuts.
+struct View {
+    ship: Option<usize>, // the ship whose score and hint are shown
+    context: Context, // translated to the viewport's corner
+    camera: Transform, // context.transform zoomed and moved to the camera position
+    camera_pos: Point,
+    size: Size,
+    zoom: f64,
+}
+
 impl App {
     fn debug(&self,
              g: &mut G2d,
@@ -39,63 +64,214 @@ impl App {
                   glyphs: &mut Glyphs,
                   fps_counter: &mut fps_counter::FPSCounter) {
 
-        let view_size = window.size();
+        let window_size = window.size();
+        let view_size = self.get_viewport_size(window_size);
 
         window.draw_2d(event, |c, g| {
-            let ship_pos = self.space.get_focus();
-            let planets = self.space.get_nearby_planets();
-            let bugs = self.space.get_nearby_bugs();
-            let bullet_gfx = rectangle::square(-BULLET_SIZE / 2.0, -BULLET_SIZE / 2.0, BULLET_SIZE);
-            let camera = c.transform.trans(-self.camera_pos.x, -self.camera_pos.y);
             clear(BLACK, g);
+            for (i, player) in self.players.iter().enumerate() {
+                let left = view_size.width * i as u32;
+                let context = Context {
+                    transform: c.transform.trans(left as f64, 0.0),
+                    draw_state: c.draw_state
+                        .scissor([left, 0, view_size.width, view_size.height]),
+                    ..c
+                };
+                if player.ship >= self.world.ships.len() {
+                    // online, before the server has sent anything
+                    text::Text::new_color(WHITE, 20).draw("Connecting...",
+                                                          glyphs,
+                                                          &context.draw_state,
+                                                          context.transform.trans(20.0, 40.0),
+                                                          g);
+                    continue;
+                }
+                let camera_pos = self.interpolation.camera(&self.world, player.ship);
+                let view = View {
+                    ship: Some(player.ship),
+                    context: context,
+                    camera: context.transform.trans(-camera_pos.x, -camera_pos.y),
+                    camera_pos: camera_pos,
+                    size: view_size,
+                    zoom: 1.0,
+                };
+                self.render_view(glyphs, &view, g);
+                if let Some(ref shop) = self.shop {
+                    self.render_shop(glyphs, shop, i, &view, g);
+                }
+            }
+
+            if let Some(ref spectator) = self.spectator {
+                let following = match spectator.camera {
+                    CameraMode::Follow(i) if i < self.world.ships.len() => Some(i),
+                    _ => None,
+                };
+                let view = View {
+                    ship: following,
+                    context: c,
+                    camera: c.transform
+                        .zoom(spectator.zoom)
+                        .trans(-spectator.camera_pos.x, -spectator.camera_pos.y),
+                    camera_pos: spectator.camera_pos,
+                    size: view_size,
+                    zoom: spectator.zoom,
+                };
+                self.render_view(glyphs, &view, g);
+                if self.title.is_some() {
+                    self.render_title(glyphs, &c, g, window_size);
+                } else {
+                    self.render_spectator_hud(glyphs, spectator, following, &c, g, window_size);
+                }
+            }
+
+            // borders between the viewports
+            for i in 1..self.players.len() {
+                let x = (view_size.width * i as u32) as f64;
+                rectangle(WHITE,
+                          [x - 1.0, 0.0, 2.0, view_size.height as f64],
+                          c.transform,
+                          g);
+            }
 
-            let nearest_beam = [ship_pos.x,
-                                ship_pos.y,
-                                self.closest_planet_coords.x,
-                                self.closest_planet_coords.y];
-            line(GREEN, 1.0, nearest_beam, camera, g);
-
-            // Draw the attached beam
-            let attached = &self.space.get_planet(self.attached_planet);
-            let attached_beam = [ship_pos.x, ship_pos.y, attached.pos.x, attached.pos.y];
-            line(BLUE, 1.0, attached_beam, camera, g);
-
-            self.render_ship(glyphs, ship_pos, camera, &c, g);
-            self.render_planets(glyphs, planets, camera, &c, g, view_size);
-            self.render_bugs(bugs, camera, g, view_size);
-            self.render_bullets(bullet_gfx, camera, g, view_size);
             let fps = fps_counter.tick();
             if self.debug {
                 self.render_fps(glyphs, fps, &c, g);
             }
-
-            self.render_score(glyphs, &c, g);
-            self.render_minimap(&c, g);
-            self.render_hint(ship_pos, camera, g);
+            if self.show_stats {
+                self.render_stats(glyphs, &c, g, window_size);
+            }
         });
     }
 
-    fn render_minimap(&self, context: &Context, g: &mut G2d) {
-        let trans = context.transform.trans(50.0, 50.0);
-        image(&self.minimap, trans, g);
-        // draw a dot representing the ship
-        {
+    fn render_view(&self, glyphs: &mut Glyphs, view: &View, g: &mut G2d) {
+        let planets = self.world.space.get_nearby_planets();
+        let entities = self.world.space.get_nearby_entities();
+        self.render_starfield(view, g);
+        if let Some(i) = view.ship {
+            self.render_beams(i, view, g);
+        }
+
+        for i in 0..self.world.ships.len() {
+            self.render_tether(i, view, g);
+            self.render_ship(glyphs, i, view, g);
+        }
+        self.render_planets(glyphs, planets, view, g);
+        self.render_entities(entities, view, g);
+        self.render_particles(view, g);
+
+        if let Some(i) = view.ship {
+            self.render_score(glyphs, i, view, g);
+            self.render_popups(glyphs, i, view, g);
+            self.render_heat(i, view, g);
+            self.render_health(i, view, g);
+            self.render_fuel(i, view, g);
+            self.render_abilities(i, view, g);
+        }
+        self.render_minimap(view, g);
+        if let Some(i) = view.ship {
+            self.render_hint(i, view, g);
+        }
+    }
+
+    /// Draw the lines from a ship to the nearest planet and the planet it's attached to.
+    fn render_beams(&self, i: usize, view: &View, g: &mut G2d) {
+        let ref ship = self.world.ships[i];
+        let (ship_pos, _) = self.interpolation.ship(&self.world, i);
+        let ref draw_state = view.context.draw_state;
+        let nearest_beam = [ship_pos.x,
+                            ship_pos.y,
+                            ship.closest_planet_coords.x,
+                            ship.closest_planet_coords.y];
+        Line::new(GREEN, 1.0).draw(nearest_beam, draw_state, view.camera, g);
+
+        // Draw the attached beam
+        let attached = self.interpolation.planet(ship.get_planet(&self.world.space));
+        let attached_beam = [ship_pos.x, ship_pos.y, attached.x, attached.y];
+        Line::new(BLUE, 1.0).draw(attached_beam, draw_state, view.camera, g);
+    }
+
+    /// Draw a ship's tether, if it's hanging from one: a thicker beam along the attached one,
+    /// which the planet covers up to its surface.
+    fn render_tether(&self, i: usize, view: &View, g: &mut G2d) {
+        let ref ship = self.world.ships[i];
+        if ship.tether.is_none() {
+            return;
+        }
+        let (ship_pos, _) = self.interpolation.ship(&self.world, i);
+        let planet = self.interpolation.planet(ship.get_planet(&self.world.space));
+        let tether = [ship_pos.x, ship_pos.y, planet.x, planet.y];
+        Line::new(LIGHTBLUE, 3.0).draw(tether, &view.context.draw_state, view.camera, g);
+    }
+
+    /// Draw the background stars, furthest layer first.
+    fn render_starfield(&self, view: &View, g: &mut G2d) {
+        for layer in 0..LAYERS.len() {
+            let stars = self.starfield.get_visible_stars(layer,
+                                                         view.camera_pos,
+                                                         view.size.width as f64,
+                                                         view.size.height as f64);
+            for star in stars {
+                let color = [star.brightness, star.brightness, star.brightness, 1.0];
+                Rectangle::new(color).draw(rectangle::square(0.0, 0.0, star.size),
+                                           &view.context.draw_state,
+                                           view.context.transform.trans(star.pos.x, star.pos.y),
+                                           g);
+            }
+        }
+    }
+
+    fn render_minimap(&self, view: &View, g: &mut G2d) {
+        let ref draw_state = view.context.draw_state;
+        let trans = view.context.transform.trans(50.0, 50.0);
+        Image::new().draw(&self.minimap, draw_state, trans, g);
+        let scale = (self.space_bounds.1.x - self.space_bounds.0.x) / MINI_SIZE;
+        // the minimap only has the planets that stay put
+        for planet in self.world.space.get_all_planets() {
+            if planet.motion.is_some() && planet.radius > 0.0 {
+                let (mini_x, mini_y) = shrink_to_bounds(MINI_SIZE,
+                                                        MINI_SIZE,
+                                                        self.space_bounds.0,
+                                                        self.space_bounds.1,
+                                                        planet.pos);
+                let radius = (planet.radius / scale).max(1.0);
+                let circle = ellipse::circle(mini_x as f64, mini_y as f64, radius);
+                Ellipse::new(planet_color(planet.kind)).draw(circle, draw_state, trans, g);
+            }
+        }
+        // draw a dot representing each ship
+        for (i, ship) in self.world.ships.iter().enumerate() {
             let (mini_x, mini_y) = shrink_to_bounds(MINI_SIZE,
                                                     MINI_SIZE,
                                                     self.space_bounds.0,
                                                     self.space_bounds.1,
-                                                    self.space.get_focus());
+                                                    ship.get_pos(&self.world.space));
 
-            let size = SHIP_SIZE / ((self.space_bounds.1.x - self.space_bounds.0.x) / MINI_SIZE);
+            let size = SHIP_SIZE / scale;
 
-            rectangle(RED,
-                      rectangle::square(0.0, 0.0, size),
-                      trans.trans(mini_x as f64, mini_y as f64).rot_rad(self.rotation),
-                      g);
+            Rectangle::new(player_color(i)).draw(rectangle::square(0.0, 0.0, size),
+                                                 draw_state,
+                                                 trans.trans(mini_x as f64, mini_y as f64)
+                                                     .rot_rad(ship.rotation),
+                                                 g);
+        }
+        // show the spectator which part of the space they're looking at
+        if self.spectator.is_some() {
+            let corner = |x: f64, y: f64| {
+                shrink_to_bounds(MINI_SIZE,
+                                 MINI_SIZE,
+                                 self.space_bounds.0,
+                                 self.space_bounds.1,
+                                 Point::new(view.camera_pos.x + x / view.zoom,
+                                            view.camera_pos.y + y / view.zoom))
+            };
+            let (x0, y0) = corner(0.0, 0.0);
+            let (x1, y1) = corner(view.size.width as f64, view.size.height as f64);
+            let r = rectangle::rectangle_by_corners(x0 as f64, y0 as f64, x1 as f64, y1 as f64);
+            Rectangle::new_border(GREEN, 1.0).draw(r, draw_state, trans, g);
         }
         {
             let r = rectangle::rectangle_by_corners(0.0, 0.0, MINI_SIZE, MINI_SIZE);
-            Rectangle::new_border(WHITE, 1.0).draw(r, &context.draw_state, trans, g);
+            Rectangle::new_border(WHITE, 1.0).draw(r, draw_state, trans, g);
         }
     }
 
@@ -107,54 +283,294 @@ impl App {
                                               g);
     }
 
-    fn render_score(&self, glyphs: &mut Glyphs, context: &Context, g: &mut G2d) {
-        text::Text::new_color(WHITE, 20).draw(&format!("{}", self.score),
+    fn render_score(&self, glyphs: &mut Glyphs, i: usize, view: &View, g: &mut G2d) {
+        let score = self.world.ships[i].score;
+        let label = if self.world.ships.len() == 1 && self.net.is_none() {
+            format!("{}", score)
+        } else {
+            format!("P{}: {}", i + 1, score)
+        };
+        // credits are only worth anything where there's a shop to spend them in
+        let label = if self.world.shop {
+            format!("{}   {} credits", label, self.world.ships[i].credits)
+        } else {
+            label
+        };
+        text::Text::new_color(WHITE, 20).draw(&label,
                                               glyphs,
-                                              &context.draw_state,
-                                              context.transform.trans(20.0, 20.0),
+                                              &view.context.draw_state,
+                                              view.context.transform.trans(20.0, 20.0),
                                               g);
     }
 
-    fn render_ship(&self,
+    /// Float the score changes up past the score.
+    fn render_popups(&self, glyphs: &mut Glyphs, i: usize, view: &View, g: &mut G2d) {
+        for popup in self.hud.get_popups(i) {
+            let t = popup.progress();
+            let mut color = popup.color;
+            color[3] *= (1.0 - t) as f32;
+            let y = 40.0 - 25.0 * t;
+            text::Text::new_color(color, 16).draw(popup.text,
+                                                  glyphs,
+                                                  &view.context.draw_state,
+                                                  view.context.transform.trans(80.0, y),
+                                                  g);
+        }
+    }
+
+    /// Show how close the ship is to burning up, once it's started heating up.
+    fn render_heat(&self, i: usize, view: &View, g: &mut G2d) {
+        let heat = self.world.ships[i].heat;
+        if heat <= 0.0 {
+            return;
+        }
+        let ref draw_state = view.context.draw_state;
+        let width = 100.0;
+        Rectangle::new(DARKRED).draw([20.0, 30.0, width, 6.0],
+                                     draw_state,
+                                     view.context.transform,
+                                     g);
+        Rectangle::new(ORANGE).draw([20.0, 30.0, width * (heat / MAX_HEAT).min(1.0), 6.0],
+                                    draw_state,
+                                    view.context.transform,
+                                    g);
+    }
+
+    /// Show how much fuel is left for flying.
+    fn render_fuel(&self, i: usize, view: &View, g: &mut G2d) {
+        let fuel = self.world.ships[i].fuel;
+        let ref draw_state = view.context.draw_state;
+        let width = 100.0;
+        Rectangle::new(BLUE).draw([20.0, 54.0, width, 6.0], draw_state, view.context.transform, g);
+        Rectangle::new(LIGHTBLUE).draw([20.0, 54.0, width * (fuel / MAX_FUEL).min(1.0), 6.0],
+                                       draw_state,
+                                       view.context.transform,
+                                       g);
+    }
+
+    /// Show the moves the ship has ready: the dash filling back up after it's been used, a square
+    /// while the double jump is still there, and how far a super jump has charged.
+    fn render_abilities(&self, i: usize, view: &View, g: &mut G2d) {
+        let ref ship = self.world.ships[i];
+        let ref draw_state = view.context.draw_state;
+        let transform = view.context.transform;
+        let dash = 1.0 - ship.dash_cooldown / DASH_COOLDOWN;
+        Rectangle::new(GRAY).draw([20.0, 64.0, 40.0, 6.0], draw_state, transform, g);
+        Rectangle::new(WHITE).draw([20.0, 64.0, 40.0 * dash, 6.0], draw_state, transform, g);
+        let double_jump = if ship.double_jumped || ship.pounding { GRAY } else { WHITE };
+        Rectangle::new(double_jump).draw([68.0, 63.0, 8.0, 8.0], draw_state, transform, g);
+        if ship.charge > 0.0 {
+            let width = 100.0;
+            Rectangle::new(GRAY).draw([20.0, 74.0, width, 6.0], draw_state, transform, g);
+            Rectangle::new(WHITE).draw([20.0, 74.0, width * ship.charge / MAX_CHARGE, 6.0],
+                                       draw_state,
+                                       transform,
+                                       g);
+        }
+    }
+
+    /// Show how many more hits the ship can take this level, one square each.
+    fn render_health(&self, i: usize, view: &View, g: &mut G2d) {
+        for n in 0..self.world.ships[i].health {
+            Rectangle::new(GREEN).draw([20.0 + n as f64 * 12.0, 40.0, 8.0, 8.0],
+                                       &view.context.draw_state,
+                                       view.context.transform,
+                                       g);
+        }
+    }
+
+    /// Draw player `player`'s side of the shop over their view: what they can afford, what
+    /// they've got, and where their cursor is.
+    fn render_shop(&self,
                    glyphs: &mut Glyphs,
-                   ship_pos: Point,
-                   camera: Transform,
-                   context: &Context,
+                   shop: &Shop,
+                   player: usize,
+                   view: &View,
                    g: &mut G2d) {
+        let ref ship = self.world.ships[self.players[player].ship];
+        let (width, height) = (360.0, 260.0);
+        let left = (view.size.width as f64 - width) / 2.0;
+        let top = (view.size.height as f64 - height) / 2.0;
+        let ref draw_state = view.context.draw_state;
+        let transform = view.context.transform;
+        let frame = [left, top, width, height];
+        Rectangle::new([0.0, 0.0, 0.0, 0.8]).draw(frame, draw_state, transform, g);
+        Rectangle::new_border(WHITE, 1.0).draw(frame, draw_state, transform, g);
+
+        let heading = format!("Level {} done. Credits: {}", self.world.level, ship.credits);
+        let mut lines = vec![(heading, WHITE), ("".to_string(), WHITE)];
+        for idx in 0..Shop::num_items() {
+            let cursor = if shop.get_cursor(player) == idx { "> " } else { "  " };
+            let line = match Shop::item(idx) {
+                Some(upgrade) => {
+                    let level = ship.upgrades.get(upgrade);
+                    match ship.upgrades.price(upgrade) {
+                        Some(price) => {
+                            let color = if price <= ship.credits { WHITE } else { GRAY };
+                            (format!("{}{} ({}/{}): {}",
+                                     cursor,
+                                     upgrade.name(),
+                                     level,
+                                     MAX_UPGRADE_LEVEL,
+                                     price),
+                             color)
+                        }
+                        None => (format!("{}{}: maxed out", cursor, upgrade.name()), GRAY),
+                    }
+                }
+                None if shop.is_ready(player) => {
+                    (format!("{}Waiting for the others...", cursor), GREEN)
+                }
+                None => (format!("{}Next level", cursor), GREEN),
+            };
+            lines.push(line);
+        }
+        lines.push(("".to_string(), WHITE));
+        lines.push(("Up/down: choose  Jump: buy".to_string(), GRAY));
+        for (i, &(ref line, color)) in lines.iter().enumerate() {
+            let line_pos = transform.trans(left + 20.0, top + 30.0 + i as f64 * 20.0);
+            text::Text::new_color(color, 16).draw(line, glyphs, draw_state, line_pos, g);
+        }
+    }
+
+    /// Say what the spectator is looking at, and how to look at something else.
+    fn render_spectator_hud(&self,
+                            glyphs: &mut Glyphs,
+                            spectator: &Spectator,
+                            following: Option<usize>,
+                            context: &Context,
+                            g: &mut G2d,
+                            window_size: Size) {
+        let status = match following {
+            Some(i) => format!("Following P{}", i + 1),
+            None => "Free camera".to_string(),
+        };
+        let status = match self.replay {
+            Some(ref replay) if replay.finished() => format!("{} - replay finished", status),
+            _ => status,
+        };
+        let lines = [format!("{} (zoom {:.1}x)", status, spectator.zoom),
+                     "Arrows/WASD: pan  Q/E or wheel: zoom  1-9: follow a ship  N: next ship  \
+                      F: free camera"
+                         .to_string()];
+        for (i, line) in lines.iter().enumerate() {
+            let y = window_size.height as f64 - 40.0 + i as f64 * 20.0;
+            text::Text::new_color(WHITE, 16)
+                .draw(line, glyphs, &context.draw_state, context.transform.trans(20.0, y), g);
+        }
+    }
+
+    /// Draw the name of the game and how to start playing, over the autopilot's game.
+    fn render_title(&self, glyphs: &mut Glyphs, context: &Context, g: &mut G2d, window_size: Size) {
+        let center = window_size.width as f64 / 2.0;
+        let middle = window_size.height as f64 / 2.0;
+        text::Text::new_color(WHITE, 64).draw("Circles",
+                                              glyphs,
+                                              &context.draw_state,
+                                              context.transform.trans(center - 110.0, middle),
+                                              g);
+        let mut lines = vec!["Press space to play".to_string()];
+        if let Some(ref title) = self.title {
+            let ref settings = title.settings;
+            let adaptive = if settings.adaptive { ", adaptive" } else { "" };
+            lines.push(format!("Difficulty: {:?}{}", settings.preset, adaptive));
+            let levels = settings.generator.map_or("any", |generator| generator.name());
+            lines.push(format!("Levels: {}", levels));
+        }
+        if let Some(best) = self.profile.high_scores.first() {
+            lines.push(format!("High score: {}", best));
+        }
+        for (i, line) in lines.iter().enumerate() {
+            let line_pos = context.transform.trans(center - 90.0, middle + 50.0 + i as f64 * 24.0);
+            text::Text::new_color(WHITE, 20)
+                .draw(line, glyphs, &context.draw_state, line_pos, g);
+        }
+    }
+
+    /// Draw the profile statistics and high scores over the middle of the screen.
+    fn render_stats(&self, glyphs: &mut Glyphs, context: &Context, g: &mut G2d, view_size: Size) {
+        let (width, height) = (400.0, 420.0);
+        let left = (view_size.width as f64 - width) / 2.0;
+        let top = (view_size.height as f64 - height) / 2.0;
+        rectangle([0.0, 0.0, 0.0, 0.8],
+                  [left, top, width, height],
+                  context.transform,
+                  g);
+        Rectangle::new_border(WHITE, 1.0)
+            .draw([left, top, width, height], &context.draw_state, context.transform, g);
+
+        let ref p = self.profile;
+        let mut lines = vec![format!("Levels completed: {}", p.levels_completed),
+                             format!("Deaths: {}", p.deaths),
+                             format!("Crawlers killed: {}", p.crawlers_killed),
+                             format!("Shots fired: {}", p.shots_fired),
+                             format!("Accuracy: {:.1}%", p.accuracy() * 100.0),
+                             format!("Distance flown: {:.0}", p.distance_flown),
+                             format!("Current streak: {}", p.streak),
+                             format!("Best streak: {}", p.best_streak),
+                             "".to_string(),
+                             "High scores:".to_string()];
+        for (i, score) in p.high_scores.iter().enumerate() {
+            lines.push(format!("  {}. {}", i + 1, score));
+        }
+        for (i, line) in lines.iter().enumerate() {
+            let line_pos = context.transform.trans(left + 20.0, top + 30.0 + i as f64 * 19.0);
+            text::Text::new_color(WHITE, 16)
+                .draw(line, glyphs, &context.draw_state, line_pos, g);
+        }
+    }
+
+    fn render_ship(&self, glyphs: &mut Glyphs, i: usize, view: &View, g: &mut G2d) {
+        let ref ship = self.world.ships[i];
+        let (ship_pos, rotation) = self.interpolation.ship(&self.world, i);
+        if !circle_in_view(ship_pos, SHIP_SIZE, view) {
+            return;
+        }
         let square = rectangle::square(0.0, 0.0, SHIP_SIZE);
-        let ship_transform = camera.trans(ship_pos.x, ship_pos.y)
-            .rot_rad(self.rotation)
-            .trans(-(SHIP_SIZE / 2.0), -(SHIP_SIZE / 2.0));
-        rectangle(RED, square, ship_transform, g);
+        let ship_center = view.camera.trans(ship_pos.x, ship_pos.y).rot_rad(rotation);
+        let ship_transform = ship_center.trans(-(SHIP_SIZE / 2.0), -(SHIP_SIZE / 2.0));
+        match self.sprites.ship {
+            Some(ref sprite) => draw_sprite(sprite, SHIP_SIZE, ship_center, view, g),
+            None => {
+                Rectangle::new(player_color(i))
+                    .draw(square, &view.context.draw_state, ship_transform, g)
+            }
+        }
         self.debug(g,
-                   &context,
+                   &view.context,
                    glyphs,
                    WHITE,
                    ship_transform,
-                   &format!("{} h={:.1}", ship_pos, self.height));
+                   &format!("{} h={:.1}", ship_pos, ship.height));
     }
 
     fn render_planets(&self,
                       glyphs: &mut Glyphs,
                       planets: Vec<(PlanetIndex, &Planet)>,
-                      camera: Transform,
-                      context: &Context,
-                      g: &mut G2d,
-                      view_size: Size) {
+                      view: &View,
+                      g: &mut G2d) {
+        let ref draw_state = view.context.draw_state;
         for (pidx, planet) in planets {
-            if circle_in_view(planet.pos, planet.radius, self.camera_pos, view_size) {
-                let planet_transform = camera.trans(planet.pos.x, planet.pos.y);
-                let color = if planet.bouncy { LIGHTBLUE } else { BLUE };
-                if !self.debug {
+            let planet_pos = self.interpolation.planet(planet);
+            if planet.radius > 0.0 && circle_in_view(planet_pos, planet.radius, view) {
+                let planet_transform = view.camera.trans(planet_pos.x, planet_pos.y);
+                let color = planet_color(planet.kind);
+                let ref sprites = self.sprites.planets[planet.kind as usize];
+                if !self.debug && !sprites.is_empty() {
+                    // pick a texture by the planet's id, so each planet keeps its texture even
+                    // when it moves
+                    let choice = planet.get_id() % sprites.len();
+                    draw_sprite(&sprites[choice], planet.radius * 2.0, planet_transform, view, g);
+                } else if !self.debug {
                     let circle = ellipse::circle(0.0, 0.0, planet.radius);
-                    ellipse(color, circle, planet_transform, g);
+                    Ellipse::new(color).draw(circle, draw_state, planet_transform, g);
                 } else {
                     let side = (planet.radius * 2.0) / ((2.0 as f64).sqrt());
                     let circle = rectangle::square(-side / 2.0, -side / 2.0, side);
-                    rectangle(color, circle, planet_transform, g);
+                    Rectangle::new(color).draw(circle, draw_state, planet_transform, g);
                 }
                 self.debug(g,
-                           &context,
+                           &view.context,
                            glyphs,
                            WHITE,
                            planet_transform,
@@ -165,84 +581,156 @@ impl App {
                                     planet.radius));
             }
         }
-        if circle_in_view(self.space.get_magic_planet(),
-                          MAGIC_PLANET_SIZE,
-                          self.camera_pos,
-                          view_size) {
-            let planet_gfx = ellipse::circle(0.0, 0.0, MAGIC_PLANET_SIZE);
-            ellipse(RED,
-                    planet_gfx,
-                    camera.trans(self.space.get_magic_planet().x,
-                                 self.space.get_magic_planet().y),
-                    g);
-        }
-    }
-
-    fn render_bugs(&self,
-                   bugs: Vec<(Area, usize)>,
-                   camera: Transform,
-                   g: &mut G2d,
-                   view_size: Size) {
-        for (area, idx) in bugs {
-            let bug = self.space.get_bug(area, idx);
-            let planet = self.space.get_planet(bug.attached);
-            let bug_pos = rotated_position(planet.pos, bug.rotation, planet.radius + CRAWLER_SIZE);
-            if circle_in_view(bug_pos, CRAWLER_SIZE, self.camera_pos, view_size) {
-                let bug_gfx = ellipse::circle(0.0, 0.0, CRAWLER_SIZE);
-                ellipse(DARKRED, bug_gfx, camera.trans(bug_pos.x, bug_pos.y), g);
+        if circle_in_view(self.world.space.get_magic_planet(), MAGIC_PLANET_SIZE, view) {
+            let planet_transform = view.camera.trans(self.world.space.get_magic_planet().x,
+                                                     self.world.space.get_magic_planet().y);
+            match self.sprites.magic_planet {
+                Some(ref sprite) => {
+                    draw_sprite(sprite, MAGIC_PLANET_SIZE * 2.0, planet_transform, view, g)
+                }
+                None => {
+                    let planet_gfx = ellipse::circle(0.0, 0.0, MAGIC_PLANET_SIZE);
+                    Ellipse::new(RED).draw(planet_gfx, draw_state, planet_transform, g);
+                }
             }
         }
     }
 
-    fn render_bullets(&self,
-                      bullet_gfx: [f64; 4],
-                      camera: Transform,
-                      g: &mut G2d,
-                      view_size: Size) {
-        for bullet in self.bullets.iter() {
-            if circle_in_view(bullet.pos, BULLET_SIZE, self.camera_pos, view_size) {
-                rectangle(RED, bullet_gfx, camera.trans(bullet.pos.x, bullet.pos.y), g);
+    fn render_entities(&self, entities: Vec<Entity>, view: &View, g: &mut G2d) {
+        let ref components = self.world.space.entities;
+        for entity in entities {
+            let (renderable, size, transform) = match (components.renderables.get(entity),
+                                                       components.colliders.get(entity),
+                                                       self.interpolation
+                                                           .entity(&self.world, entity)) {
+                (Some(&renderable), Some(collider), Some(transform)) => {
+                    (renderable, collider.radius, transform)
+                }
+                _ => continue,
+            };
+            let pos = transform.pos;
+            if !circle_in_view(pos, size, view) {
+                continue;
+            }
+            let entity_transform = view.camera.trans(pos.x, pos.y);
+            match renderable {
+                Renderable::Crawler { facing_right } => {
+                    let frames = &self.sprites.crawler;
+                    if frames.is_empty() {
+                        let bug_gfx = ellipse::circle(0.0, 0.0, size);
+                        Ellipse::new(DARKRED)
+                            .draw(bug_gfx, &view.context.draw_state, entity_transform, g);
+                    } else {
+                        let frame = (self.time / CRAWLER_FRAME_TIME) as usize % frames.len();
+                        // sprites face "up" away from the planet, and walk towards +y
+                        let bug_transform = entity_transform.rot_rad(transform.rotation);
+                        let bug_transform = if facing_right {
+                            bug_transform
+                        } else {
+                            bug_transform.flip_v()
+                        };
+                        draw_sprite(&frames[frame], size * 2.0, bug_transform, view, g);
+                    }
+                }
+                Renderable::FuelCan => {
+                    let can_gfx = rectangle::square(-size, -size, size * 2.0);
+                    let ref draw_state = view.context.draw_state;
+                    Rectangle::new(LIGHTBLUE).draw(can_gfx, draw_state, entity_transform, g);
+                    Rectangle::new_border(WHITE, 1.0)
+                        .draw(can_gfx, draw_state, entity_transform, g);
+                }
+                Renderable::Bullet => {
+                    let owner = components.projectiles.get(entity).map_or(0, |p| p.owner);
+                    let bullet_gfx = rectangle::square(-size / 2.0, -size / 2.0, size);
+                    Rectangle::new(player_color(owner))
+                        .draw(bullet_gfx, &view.context.draw_state, entity_transform, g);
+                }
+            }
+        }
+    }
+
+    fn render_particles(&self, view: &View, g: &mut G2d) {
+        for particle in self.particles.get_alive() {
+            if circle_in_view(particle.pos, particle.size, view) {
+                let gfx = rectangle::square(-particle.size / 2.0,
+                                            -particle.size / 2.0,
+                                            particle.size);
+                Rectangle::new(particle.color()).draw(gfx,
+                                                      &view.context.draw_state,
+                                                      view.camera
+                                                          .trans(particle.pos.x, particle.pos.y),
+                                                      g);
             }
         }
     }
 
     /// Draw the hint towards the magic planet
-    fn render_hint(&self, ship_pos: Point, camera: Transform, g: &mut G2d) {
+    fn render_hint(&self, i: usize, view: &View, g: &mut G2d) {
         const DOWN_RIGHT_RAD: f64 = PI / 4.0;
         const UP_RIGHT_RAD: f64 = -DOWN_RIGHT_RAD;
         const DOWN_LEFT_RAD: f64 = PI * (3.0 / 4.0);
         const UP_LEFT_RAD: f64 = -DOWN_LEFT_RAD;
 
-        let magic_dir = direction_from_to(ship_pos, self.space.get_magic_planet());
+        let rotation = self.world.ships[i].rotation;
+        let ship_pos = self.world.ships[i].get_pos(&self.world.space);
+        let ref draw_state = view.context.draw_state;
+        let magic_dir = direction_from_to(ship_pos, self.world.space.get_magic_planet());
 
         let dot = rectangle::square(-2.5, -2.5, 5.0);
         for rot in [DOWN_RIGHT_RAD, UP_RIGHT_RAD, DOWN_LEFT_RAD, UP_LEFT_RAD].iter() {
-            let corner_rot = rot + self.rotation;
+            let corner_rot = rot + rotation;
             if self.debug {
                 let corner = rotated_position(ship_pos, corner_rot, SHIP_SIZE / 2.0);
-                rectangle(BLUE, dot, camera.trans(corner.x, corner.y), g);
+                let corner_transform = view.camera.trans(corner.x, corner.y);
+                Rectangle::new(BLUE).draw(dot, draw_state, corner_transform, g);
             }
         }
 
-        let hint_rad = if DOWN_RIGHT_RAD + self.rotation > magic_dir &&
-                          magic_dir > UP_RIGHT_RAD + self.rotation {
-            self.rotation
-        } else if DOWN_LEFT_RAD + self.rotation > magic_dir &&
-                                 magic_dir > DOWN_RIGHT_RAD + self.rotation {
-            self.rotation + PI / 2.0
-        } else if UP_LEFT_RAD + self.rotation < magic_dir &&
-                                 magic_dir < UP_RIGHT_RAD + self.rotation {
-            self.rotation - PI / 2.0
+        let hint_rad = if DOWN_RIGHT_RAD + rotation > magic_dir &&
+                          magic_dir > UP_RIGHT_RAD + rotation {
+            rotation
+        } else if DOWN_LEFT_RAD + rotation > magic_dir &&
+                                 magic_dir > DOWN_RIGHT_RAD + rotation {
+            rotation + PI / 2.0
+        } else if UP_LEFT_RAD + rotation < magic_dir &&
+                                 magic_dir < UP_RIGHT_RAD + rotation {
+            rotation - PI / 2.0
         } else {
-            self.rotation - PI
+            rotation - PI
         };
         let hint_pos = rotated_position(ship_pos, hint_rad, 30.0);
-        ellipse(GREEN, dot, camera.trans(hint_pos.x, hint_pos.y), g);
+        Ellipse::new(GREEN).draw(dot, draw_state, view.camera.trans(hint_pos.x, hint_pos.y), g);
     }
 }
 
+/// Draw a texture stretched to a `size` by `size` square centered on the origin of `transform`.
+fn draw_sprite(texture: &G2dTexture, size: f64, transform: Transform, view: &View, g: &mut G2d) {
+    let (width, height) = texture.get_size();
+    Image::new().draw(texture,
+                      &view.context.draw_state,
+                      transform.trans(-size / 2.0, -size / 2.0)
+                          .scale(size / width as f64, size / height as f64),
+                      g);
+}
+
 /// Check if a circle is in the current viewport.
-fn circle_in_view(point: Point, radius: f64, camera: Point, view_size: Size) -> bool {
-    point.x + radius > camera.x && point.x - radius < camera.x + view_size.width as f64 ||
-    point.y + radius > camera.y && point.y - radius < camera.y + view_size.height as f64
+fn circle_in_view(point: Point, radius: f64, view: &View) -> bool {
+    let camera = view.camera_pos;
+    let (width, height) = (view.size.width as f64 / view.zoom,
+                           view.size.height as f64 / view.zoom);
+    point.x + radius > camera.x && point.x - radius < camera.x + width ||
+    point.y + radius > camera.y && point.y - radius < camera.y + height
+}
+
+/// The colour a kind of planet is drawn in when there's no sprite for it, and on the minimap.
+pub fn planet_color(kind: PlanetKind) -> [f32; 4] {
+    match kind {
+        PlanetKind::Normal => BLUE,
+        PlanetKind::Bouncy => LIGHTBLUE,
+        PlanetKind::Icy => ICE,
+        PlanetKind::Sticky => PURPLE,
+        PlanetKind::Hot => ORANGE,
+        PlanetKind::GasGiant => TAN,
+        PlanetKind::Crumbling => BROWN,
+    }
 }
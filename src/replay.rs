@@ -0,0 +1,130 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+
+use net::{ServerMessage, Snapshot};
+use world::{World, GameEvent};
+
+/// Recorder writes down what happens in a world, one snapshot per frame, so it can be watched
+/// again with `Replay`. Each frame in the file is the time since the recording started (as the
+/// bits of an f64), the length of the snapshot and the snapshot itself, encoded the same way the
+/// server sends it. All big-endian.
+pub struct Recorder {
+    file: BufWriter<File>,
+    time: f64,
+    tick: u32,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> io::Result<Recorder> {
+        Ok(Recorder {
+            file: BufWriter::new(File::create(path)?),
+            time: 0.0,
+            tick: 0,
+        })
+    }
+
+    /// Record the world as it is after an update, along with the events that happened during it.
+    pub fn record(&mut self,
+                  world: &World,
+                  events: &[GameEvent],
+                  time_delta: f64)
+                  -> io::Result<()> {
+        self.time += time_delta;
+        self.tick += 1;
+        let events = events.iter().map(|&event| (self.tick, event)).collect();
+        let snapshot = Snapshot::from_world(world, self.tick, None, 0, events);
+        let data = ServerMessage::Snapshot(snapshot).encode();
+        self.file.write_all(&u64_bytes(self.time.to_bits()))?;
+        self.file.write_all(&u64_bytes(data.len() as u64)[4..])?;
+        self.file.write_all(&data)
+    }
+}
+
+/// Replay plays back a file written by `Recorder`.
+pub struct Replay {
+    frames: Vec<(f64, Snapshot)>,
+    next: usize, // the first frame that hasn't been shown yet
+    time: f64,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> io::Result<Replay> {
+        let mut data = vec![];
+        File::open(path)?.read_to_end(&mut data)?;
+        let mut frames = vec![];
+        let mut pos = 0;
+        while pos < data.len() {
+            if pos + 12 > data.len() {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "replay is cut short"));
+            }
+            let time = f64::from_bits(read_u64(&data[pos..pos + 8]));
+            let len = read_u64(&data[pos + 8..pos + 12]) as usize;
+            pos += 12;
+            if pos + len > data.len() {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "replay is cut short"));
+            }
+            match ServerMessage::decode(&data[pos..pos + len])? {
+                ServerMessage::Snapshot(snapshot) => frames.push((time, snapshot)),
+                _ => {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                              "replay contains something other than snapshots"))
+                }
+            }
+            pos += len;
+        }
+        Ok(Replay {
+            frames: frames,
+            next: 0,
+            time: 0.0,
+        })
+    }
+
+    pub fn finished(&self) -> bool {
+        self.next >= self.frames.len()
+    }
+
+    /// Move the world to where it was `time_delta` seconds further into the recording. Returns the
+    /// events that happened in between.
+    pub fn update(&mut self, world: &mut World, time_delta: f64) -> Vec<GameEvent> {
+        self.time += time_delta;
+        let mut events = vec![];
+        let mut latest = None;
+        while self.next < self.frames.len() && self.frames[self.next].0 <= self.time {
+            // `sync` makes its own LevelReset when the level changes
+            events.extend(self.frames[self.next]
+                .1
+                .events
+                .iter()
+                .map(|&(_, event)| event)
+                .filter(|&event| event != GameEvent::LevelReset));
+            latest = Some(self.next);
+            self.next += 1;
+        }
+        if let Some(i) = latest {
+            let ref snapshot = self.frames[i].1;
+            snapshot.sync(world);
+            // nothing moves the planets between frames here, so keep them exactly in step
+            world.set_time(snapshot.time);
+            for (i, state) in snapshot.ships.iter().enumerate().take(world.ships.len()) {
+                state.apply(world, i, true);
+            }
+            let ship_positions = world.get_ship_positions();
+            world.space.focus(&ship_positions);
+        }
+        events
+    }
+}
+
+fn u64_bytes(n: u64) -> [u8; 8] {
+    let mut bytes = [0; 8];
+    for i in 0..8 {
+        bytes[i] = (n >> (56 - i * 8)) as u8;
+    }
+    bytes
+}
+
+/// Read a big-endian number from up to 8 bytes.
+fn read_u64(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0, |n, &b| (n << 8) | b as u64)
+}
@@ -0,0 +1,411 @@
+use std::f64::consts::PI;
+
+use space::{Space, Planet, PlanetIndex, PlanetKind};
+use calc::{Point, pt, rotated_position, direction_from_to};
+use world::TICK_TIME;
+use upgrade::Upgrades;
+use ecs::Entity;
+
+pub const SHIP_SIZE: f64 = 50.0;
+pub const SPEED: f64 = 5.0;
+pub const FLY_SPEED: f64 = 3.0;
+pub const JUMP_SPEED: f64 = 10.0;
+pub const AIR_CONTROL_MOD: f64 = 0.40;
+pub const BULLET_SPEED: f64 = 1000.0;
+pub const BULLET_SIZE: f64 = 5.0;
+pub const ACCELERATION: f64 = 5.0;
+pub const FIRE_COOLDOWN: f64 = 0.1;
+pub const GRAVITY: f64 = 20.0;
+// how far from a planet's surface a ship can attach to it
+pub const ATTACH_RANGE: f64 = 400.0;
+// how hard a tether reels the ship in, on top of gravity
+pub const TETHER_PULL: f64 = 30.0;
+// how many seconds of holding down it takes to charge a jump all the way, and how much faster a
+// fully charged jump goes than a normal one
+pub const MAX_CHARGE: f64 = 1.0;
+pub const SUPER_JUMP: f64 = 0.8;
+// how fast a dash goes along the surface, in radians per second, for how many seconds, and how
+// long until the next one
+pub const DASH_SPEED: f64 = 15.0;
+pub const DASH_TIME: f64 = 0.15;
+pub const DASH_COOLDOWN: f64 = 1.0;
+// how fast a ground pound comes down, in distance per tick, and how far from where it lands it
+// kills crawlers
+pub const POUND_SPEED: f64 = 20.0;
+pub const POUND_RADIUS: f64 = 150.0;
+// a full tank, and how much of it flying uses per second, on top of which speeding up or slowing
+// down uses some more
+pub const MAX_FUEL: f64 = 3.0;
+pub const FLY_BURN: f64 = 1.0;
+pub const BOOST_BURN: f64 = 0.5;
+// how fast the tank fills back up while the ship's on the ground, per second
+pub const REFUEL_RATE: f64 = 1.5;
+// how quickly sliding on ice catches up with what the controls are asking for, per second
+pub const ICE_GRIP: f64 = 1.5;
+pub const STICKY_MOD: f64 = 0.3;
+// seconds a ship can stay on a hot planet before it burns up
+pub const MAX_HEAT: f64 = 3.0;
+// how much faster a ship cools down than it heats up
+pub const COOLING: f64 = 0.5;
+
+/// The controls for one ship.
+pub struct GameInput {
+    pub left: bool,
+    pub right: bool,
+    pub down: bool,
+    pub ducked: bool, // like `jumped`, for down: it has charged a jump or started a pound
+    pub up: bool,
+    pub jump: bool,
+    pub jumped: bool,
+    pub dash: bool,
+    pub shoot_target: Option<Point>,
+    pub shooting: bool,
+    pub attach: bool,
+    pub thrown: bool, // like `jumped`: a tether has been thrown since attach was pressed
+    pub cursor: Option<[f64; 2]>,
+}
+
+impl GameInput {
+    pub fn new() -> GameInput {
+        GameInput {
+            left: false,
+            right: false,
+            down: false,
+            ducked: false,
+            up: false,
+            jump: false,
+            jumped: false,
+            dash: false,
+            shoot_target: None,
+            attach: false,
+            thrown: false,
+            cursor: None,
+            shooting: false,
+        }
+    }
+}
+
+/// Ship is everything about one player's ship. The ship doesn't have a position of its own: it's
+/// always `height` away from the center of `attached_planet`, at angle `rotation`.
+pub struct Ship {
+    pub input: GameInput,
+    pub score: i64,
+    pub rotation: f64, // ship rotation / position along the orbit
+    pub flying: bool,
+    pub jumping: bool,
+    pub exit_speed: f64, // away from the planet, in distance per tick
+    pub double_jumped: bool, // whether the ship has jumped again since it left the ground
+    pub pounding: bool, // coming down in a ground pound
+    pub charge: f64, // seconds spent charging a super jump
+    pub dash_time: f64, // seconds left of the current dash
+    pub dash_dir: f64, // 1 for a dash to the right, -1 for one to the left
+    pub dash_cooldown: f64, // seconds until the ship can dash again
+    pub height: f64,
+    pub slide: f64, // how fast the ship is sliding around an icy planet, in radians per second
+    pub swing: f64, // how fast the ship swings around its planet in the air, in radians per second
+    // the length of the tether from the center of `attached_planet`, while attach is held
+    pub tether: Option<f64>,
+    pub heat: f64, // seconds spent on hot planets, less cooling down since
+    pub fuel: f64, // what's left for flying
+    pub attached_planet: PlanetIndex,
+    pub closest_planet_coords: Point, // redundant data, optimization
+    pub fire_cooldown: f64,
+    pub camera_pos: Point,
+    pub prev_pos: Point, // for measuring distance flown
+    // where on the first planet the ship starts, so two ships don't start on top of each other
+    pub spawn_rotation: f64,
+    pub credits: u64, // earned by winning and killing crawlers, spent on upgrades
+    pub upgrades: Upgrades,
+    pub health: u32, // hits the ship can still take this level before one counts
+    pub entity: Option<Entity>, // its body, once `World` has given it one in this level's space
+}
+
+impl Ship {
+    pub fn new(space: &Space, spawn_rotation: f64) -> Ship {
+        let attached_planet_idx = space.get_first_planet();
+        let planet = space.get_start_planet();
+        Ship {
+            input: GameInput::new(),
+            score: 0,
+            camera_pos: pt(-0.0, -0.0),
+            prev_pos: planet.pos,
+            flying: false,
+            jumping: false,
+            fire_cooldown: 0.0,
+            exit_speed: 0.0,
+            double_jumped: false,
+            pounding: false,
+            charge: 0.0,
+            dash_time: 0.0,
+            dash_dir: 1.0,
+            dash_cooldown: 0.0,
+            height: planet.radius,
+            slide: 0.0,
+            swing: 0.0,
+            tether: None,
+            heat: 0.0,
+            fuel: MAX_FUEL,
+            rotation: spawn_rotation,
+            attached_planet: attached_planet_idx,
+            closest_planet_coords: planet.pos,
+            spawn_rotation: spawn_rotation,
+            credits: 0,
+            upgrades: Upgrades::default(),
+            health: 0,
+            entity: None,
+        }
+    }
+
+    /// Give the ship a set of upgrades, and the health that comes with them.
+    pub fn equip(&mut self, upgrades: Upgrades) {
+        self.upgrades = upgrades;
+        self.health = upgrades.extra_health();
+    }
+
+    /// Put the ship back on the first planet of `space`. The score, input, camera, credits,
+    /// upgrades and health are kept. Its bullets are entities in the space, so the world clears
+    /// those.
+    pub fn respawn(&mut self, space: &Space) {
+        let attached_planet_idx = space.get_first_planet();
+        self.attached_planet = attached_planet_idx;
+        let attached_planet = space.get_start_planet();
+        self.height = attached_planet.radius;
+        self.closest_planet_coords = attached_planet.pos;
+        self.prev_pos = attached_planet.pos;
+        self.flying = false;
+        self.jumping = false;
+        self.fire_cooldown = 0.0;
+        self.exit_speed = 0.0;
+        self.double_jumped = false;
+        self.pounding = false;
+        self.charge = 0.0;
+        self.dash_time = 0.0;
+        self.dash_cooldown = 0.0;
+        self.slide = 0.0;
+        self.swing = 0.0;
+        self.tether = None;
+        self.heat = 0.0;
+        self.fuel = MAX_FUEL;
+        self.rotation = self.spawn_rotation;
+    }
+
+    pub fn get_pos(&self, space: &Space) -> Point {
+        rotated_position(self.get_planet(space).pos, self.rotation, self.height)
+    }
+
+    /// The planet the ship is attached to. If the handle has gone stale (say the ship is from
+    /// before a level reset) it's taken to be on the first planet until the world respawns it.
+    pub fn get_planet<'a>(&self, space: &'a Space) -> &'a Planet {
+        space.get_planet(self.attached_planet).unwrap_or_else(|| space.get_start_planet())
+    }
+
+    /// Whether the ship's planet handle still points at a planet of `space`.
+    pub fn is_attached(&self, space: &Space) -> bool {
+        space.get_planet(self.attached_planet).is_some()
+    }
+
+    pub fn in_air(&self) -> bool {
+        self.flying || self.jumping
+    }
+
+    /// How fast the ship is going, in distance per second: away from its planet, and around it.
+    pub fn velocity(&self) -> Point {
+        let out = self.exit_speed / TICK_TIME;
+        let around = self.swing * self.height;
+        let (sin, cos) = self.rotation.sin_cos();
+        pt(out * cos - around * sin, out * sin + around * cos)
+    }
+
+    /// Set how fast the ship is going, in distance per second, by splitting it up into going away
+    /// from its planet and going around it. The ship's planet, rotation and height have to be
+    /// set first.
+    pub fn set_velocity(&mut self, vel: Point) {
+        let (sin, cos) = self.rotation.sin_cos();
+        self.exit_speed = (vel.x * cos + vel.y * sin) * TICK_TIME;
+        self.swing = (vel.y * cos - vel.x * sin) / self.height;
+    }
+
+    /// Hang from the ship's planet on a tether as long as it is now. The ship is off the ground
+    /// until it lands again.
+    pub fn throw_tether(&mut self) {
+        self.tether = Some(self.height);
+        self.input.thrown = true;
+        if !self.flying {
+            self.jumping = true;
+        }
+    }
+
+    /// Reel the ship in on its tether, and let go of it once attach isn't held any more. The
+    /// tether only ever gets shorter, and it stops the ship going further out than its length.
+    pub fn update_tether(&mut self, time_delta: f64) {
+        let length = match self.tether {
+            Some(length) if self.input.attach => length,
+            _ => {
+                self.tether = None;
+                return;
+            }
+        };
+        self.exit_speed -= TETHER_PULL * time_delta;
+        if self.height > length {
+            self.height = length;
+            self.exit_speed = self.exit_speed.min(0.0);
+        }
+        self.tether = Some(length.min(self.height));
+    }
+
+    /// Fire at `target` if the gun has cooled down. Returns which way the bullet went if one was
+    /// fired; the world makes the bullet.
+    pub fn update_shoot(&mut self, target: Point, ship_pos: Point, time_delta: f64) -> Option<f64> {
+        if self.fire_cooldown <= 0.0 {
+            self.fire_cooldown = self.upgrades.fire_cooldown();
+            Some(direction_from_to(ship_pos, target))
+        } else {
+            self.fire_cooldown -= time_delta;
+            None
+        }
+    }
+
+    /// Apply the movement input. `surface` is the kind of planet the ship is attached to, which
+    /// matters when it's on the ground. Flying burns fuel; once it's gone the engine stops and the
+    /// ship drifts back down. In the air the ship keeps swinging around its planet, faster the
+    /// closer it gets.
+    ///
+    /// On the ground, holding down charges up a super jump, and dash runs along the surface. In
+    /// the air, jumping again gives a double jump, and down starts a ground pound, which the world
+    /// checks for crawlers when it lands. Returns true if the ship just jumped.
+    pub fn update_movement(&mut self, surface: PlanetKind, time_delta: f64) -> bool {
+        let mut jumped = false;
+        let height = self.height;
+        if !self.flying {
+            if self.input.up && self.fuel > 0.0 {
+                self.flying = true;
+                self.exit_speed = FLY_SPEED;
+            }
+        } else {
+            self.height += self.exit_speed * time_delta / TICK_TIME;
+            if self.fuel > 0.0 {
+                let mut burn = FLY_BURN;
+                if self.input.down {
+                    self.exit_speed -= ACCELERATION * time_delta;
+                    burn += BOOST_BURN;
+                }
+                if self.input.up {
+                    self.exit_speed += ACCELERATION * time_delta;
+                    burn += BOOST_BURN;
+                }
+                self.fuel = (self.fuel - burn * time_delta).max(0.0);
+            } else {
+                self.exit_speed -= GRAVITY * time_delta;
+            }
+        }
+        if !self.in_air() {
+            self.fuel = (self.fuel + REFUEL_RATE * time_delta).min(MAX_FUEL);
+            if self.input.down {
+                self.charge = (self.charge + time_delta).min(MAX_CHARGE);
+                self.input.ducked = true;
+            } else {
+                self.charge = 0.0;
+            }
+        }
+
+        if !self.jumping {
+            if self.input.jump && !self.input.jumped {
+                self.jumping = true;
+                self.exit_speed = self.upgrades.jump_speed() *
+                                  (1.0 + SUPER_JUMP * self.charge / MAX_CHARGE);
+                self.charge = 0.0;
+                self.input.jumped = true;
+                jumped = true;
+            }
+        } else {
+            if self.input.jump && !self.input.jumped && !self.double_jumped && !self.pounding {
+                self.double_jumped = true;
+                self.exit_speed = self.upgrades.jump_speed();
+                self.input.jumped = true;
+                jumped = true;
+            }
+            if self.input.down && !self.input.ducked && !self.flying && !self.pounding {
+                self.pounding = true;
+                self.exit_speed = -POUND_SPEED;
+                self.input.ducked = true;
+            }
+            self.exit_speed -= GRAVITY * time_delta;
+            self.height += self.exit_speed * time_delta / TICK_TIME;
+            if !self.input.jump {
+                let cut_off = self.upgrades.jump_speed() / 2.0;
+                if self.exit_speed > cut_off {
+                    self.exit_speed = cut_off;
+                }
+            }
+        }
+
+        if self.in_air() && self.swing != 0.0 {
+            self.swing *= (height / self.height).powi(2);
+            self.rotation += self.swing * time_delta;
+        }
+
+        self.dash_cooldown = (self.dash_cooldown - time_delta).max(0.0);
+        if self.in_air() {
+            self.dash_time = 0.0;
+        } else if self.input.dash && self.dash_cooldown <= 0.0 {
+            self.dash_time = DASH_TIME;
+            self.dash_dir = if self.input.left && !self.input.right { -1.0 } else { 1.0 };
+            self.dash_cooldown = DASH_COOLDOWN;
+        }
+
+        if self.flying || self.jumping {
+            let air_control = self.upgrades.air_control();
+            if self.input.left {
+                self.rotation -= SPEED * air_control * time_delta
+            }
+            if self.input.right {
+                self.rotation += SPEED * air_control * time_delta
+            }
+        } else if self.dash_time > 0.0 {
+            self.rotation += self.dash_dir * DASH_SPEED * time_delta;
+            self.dash_time -= time_delta;
+        } else {
+            let mut push = 0.0;
+            if self.input.left {
+                push -= SPEED;
+            }
+            if self.input.right {
+                push += SPEED;
+            }
+            match surface {
+                PlanetKind::Icy => {
+                    self.slide += (push - self.slide) * (ICE_GRIP * time_delta).min(1.0);
+                    self.rotation += self.slide * time_delta;
+                }
+                PlanetKind::Sticky => self.rotation += push * STICKY_MOD * time_delta,
+                _ => self.rotation += push * time_delta,
+            }
+        }
+        if surface != PlanetKind::Icy || self.flying || self.jumping {
+            self.slide = 0.0;
+        }
+        jumped
+    }
+
+    /// Heat up if the ship is standing on a hot planet, otherwise cool down. Returns true if the
+    /// ship has burned up.
+    pub fn update_heat(&mut self, surface: PlanetKind, time_delta: f64) -> bool {
+        if surface == PlanetKind::Hot && !self.in_air() {
+            self.heat += time_delta;
+        } else {
+            self.heat = (self.heat - COOLING * time_delta).max(0.0);
+        }
+        self.heat >= MAX_HEAT
+    }
+
+    /// Put a bound on rotation, because maybe something bad will happen if someone spins in one
+    /// direction for an hour
+    pub fn bound_rotation(&mut self) {
+        if self.rotation > PI {
+            self.rotation -= 2.0 * PI
+        }
+        if self.rotation < -PI {
+            self.rotation += 2.0 * PI
+        }
+    }
+}
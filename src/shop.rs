@@ -0,0 +1,71 @@
+use circles::ship::Ship;
+use circles::upgrade::{Upgrade, UPGRADES};
+
+/// The shop between levels, where the players spend their credits on upgrades. Each player has a
+/// cursor on the list of upgrades, which has "next level" at the bottom; the next level starts
+/// once every player has picked that.
+pub struct Shop {
+    cursors: Vec<usize>, // by player, an index into `UPGRADES`, or one past the end
+    ready: Vec<bool>,
+}
+
+impl Shop {
+    pub fn new(num_players: usize) -> Shop {
+        Shop {
+            cursors: vec![0; num_players],
+            ready: vec![false; num_players],
+        }
+    }
+
+    /// What a place in the list is: an upgrade, or None for going on to the next level.
+    pub fn item(idx: usize) -> Option<Upgrade> {
+        UPGRADES.get(idx).cloned()
+    }
+
+    /// How many things are in the list.
+    pub fn num_items() -> usize {
+        UPGRADES.len() + 1
+    }
+
+    pub fn get_cursor(&self, player: usize) -> usize {
+        self.cursors[player]
+    }
+
+    pub fn is_ready(&self, player: usize) -> bool {
+        self.ready[player]
+    }
+
+    /// Move a player's cursor up or down the list, wrapping around. A player who was ready and
+    /// moves again isn't any more.
+    pub fn move_cursor(&mut self, player: usize, down: bool) {
+        let n = Shop::num_items();
+        let ref mut cursor = self.cursors[player];
+        *cursor = if down { (*cursor + 1) % n } else { (*cursor + n - 1) % n };
+        self.ready[player] = false;
+    }
+
+    /// Buy the upgrade under a player's cursor for their ship, or say they're ready for the next
+    /// level. Returns true if something was bought.
+    pub fn choose(&mut self, player: usize, ship: &mut Ship) -> bool {
+        match Shop::item(self.cursors[player]) {
+            Some(upgrade) => {
+                let mut upgrades = ship.upgrades;
+                if upgrades.buy(upgrade, &mut ship.credits) {
+                    ship.equip(upgrades);
+                    true
+                } else {
+                    false
+                }
+            }
+            None => {
+                self.ready[player] = true;
+                false
+            }
+        }
+    }
+
+    /// Whether every player is ready for the next level.
+    pub fn all_ready(&self) -> bool {
+        self.ready.iter().all(|&ready| ready)
+    }
+}
@@ -5,63 +5,191 @@ extern crate nalgebra as na;
 use ncollide::shape::Ball;
 use ncollide::bounding_volume::aabb;
 use std::collections::HashMap;
-use std::f64::consts::PI;
-use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-use self::rand::distributions::{IndependentSample, Range};
-use calc::{Point, pt, coll_pt, rotated_position};
+use self::rand::{Rng, SeedableRng, XorShiftRng};
+use calc::{Point, pt, coll_pt, distance, rotated_position};
+use reach::{self, Envelope, Problem};
+use difficulty::Difficulty;
+use generator::{self, LevelGenerator, RandomWalk};
+use ecs::{Entities, Entity, Transform};
+use ship::MAX_FUEL;
 
 use ncollide::bounding_volume::BoundingVolume;
 
-const AREA_WIDTH: f64 = 2560.0;
-const AREA_HEIGHT: f64 = 2560.0;
+pub const AREA_WIDTH: f64 = 2560.0;
+pub const AREA_HEIGHT: f64 = 2560.0;
 
 pub const MAGIC_PLANET_SIZE: f64 = 200.0;
-const MIN_PLANET_DISTANCE: f64 = 150.0;
-const MAX_PLANET_DISTANCE: f64 = 400.0;
-const MIN_PLANET_SIZE: f64 = 35.0;
-const MAX_PLANET_SIZE: f64 = 100.0;
-const PATH_VARIANCE: f64 = 0.1; // applied to radians
-const CRAWLER_PERCENTAGE: f64 = 0.5;
-const BOUNCY_PERCENTAGE: f64 = 0.5;
-const NUM_PLANETS: u32 = 3;
+// how many levels to generate from one seed before making the last one winnable by hand
+const MAX_ATTEMPTS: u32 = 20;
+
+// every space gets its own id, so handles from one space aren't mistaken for another's
+static NEXT_SPACE_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// How generating a level went. A generator whose first tries often can't be won, or that needs
+/// patching up at all, is doing something wrong, however good the levels that come out are.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Generation {
+    pub attempts: u32, // how many levels were generated, counting the one that was kept
+    pub first_problem: Option<Problem>, // why the first one couldn't be won, if it couldn't
+    pub repaired: bool, // none of them could be won, so the last one was patched up
+    pub problem: Option<Problem>, // what's still wrong with it, which should be nothing
+}
+
+/// What a planet is like to be on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlanetKind {
+    Normal,
+    /// Throws ships back up when they land on it.
+    Bouncy,
+    /// Slippery: ships keep sliding after they stop pushing.
+    Icy,
+    /// Ships can only crawl along it.
+    Sticky,
+    /// Burns ships that stay on it too long.
+    Hot,
+    /// Not solid, so it can't be landed on or attached to, but flying through it is slow going.
+    GasGiant,
+    /// Starts shrinking when a ship lands on it, until it's gone.
+    Crumbling,
+}
+
+pub const PLANET_KINDS: [PlanetKind; 7] = [PlanetKind::Normal,
+                                           PlanetKind::Bouncy,
+                                           PlanetKind::Icy,
+                                           PlanetKind::Sticky,
+                                           PlanetKind::Hot,
+                                           PlanetKind::GasGiant,
+                                           PlanetKind::Crumbling];
+
+/// How a planet moves. Where it is only depends on how long the level has been going, so every
+/// machine agrees on it.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Motion {
+    /// Going around another planet, which mustn't be orbiting anything itself.
+    Orbit {
+        parent: PlanetIndex,
+        distance: f64, // between the centers
+        speed: f64, // radians per second, negative for clockwise
+        phase: f64, // where it starts, in radians
+    },
+    /// Going round and round a closed path, at a steady speed.
+    Path { points: Vec<Point>, speed: f64 },
+}
+
+impl Motion {
+    /// A circle that a planet with this motion always has its center inside, as (center, radius).
+    pub fn sweep(&self, space: &Space) -> (Point, f64) {
+        match *self {
+            Motion::Orbit { parent, distance, .. } => (space.parent_pos(parent), distance),
+            Motion::Path { ref points, .. } => {
+                let n = points.len() as f64;
+                let center = pt(points.iter().map(|p| p.x).sum::<f64>() / n,
+                                points.iter().map(|p| p.y).sum::<f64>() / n);
+                let radius = points.iter().map(|&p| distance(center, p)).fold(0.0, f64::max);
+                (center, radius)
+            }
+        }
+    }
+
+    /// Where a planet with this motion is `time` seconds into the level.
+    fn position(&self, time: f64, space: &Space) -> Point {
+        match *self {
+            Motion::Orbit { parent, distance, speed, phase } => {
+                rotated_position(space.parent_pos(parent), phase + speed * time, distance)
+            }
+            Motion::Path { ref points, speed } => {
+                let segments: Vec<(Point, Point)> = points.iter()
+                    .zip(points.iter().cycle().skip(1))
+                    .map(|(&a, &b)| (a, b))
+                    .collect();
+                let length: f64 = segments.iter().map(|&(a, b)| distance(a, b)).sum();
+                if length <= 0.0 {
+                    return points[0];
+                }
+                let mut along = (speed * time) % length;
+                for &(a, b) in segments.iter() {
+                    let segment = distance(a, b);
+                    if along <= segment {
+                        let t = along / segment;
+                        return pt(a.x + (b.x - a.x) * t, a.y + (b.y - a.y) * t);
+                    }
+                    along -= segment;
+                }
+                points[0]
+            }
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Planet {
     pub pos: Point,
     pub radius: f64,
-    pub bouncy: bool,
+    pub kind: PlanetKind,
+    pub crumbling: bool, // whether it's been landed on and is shrinking
+    pub motion: Option<Motion>,
+    pub moved: Point, // how far it moved in the last update
+    id: usize, // unique within its space, so a handle can tell it's still this planet
+    entity: Option<Entity>, // its body, once it's in a space
 }
 
-#[derive(Debug)]
-pub struct Bullet {
-    pub pos: Point,
-    pub dir: f64, // radians
-    pub speed: f64,
+impl Planet {
+    pub fn new(pos: Point, radius: f64, kind: PlanetKind) -> Planet {
+        Planet {
+            pos: pos,
+            radius: radius,
+            kind: kind,
+            crumbling: false,
+            motion: None,
+            moved: pt(0.0, 0.0),
+            id: 0,
+            entity: None,
+        }
+    }
+
+    /// Which planet of its space this is. It doesn't change when the planet moves, and the same
+    /// seed gives the same planets the same ids.
+    pub fn get_id(&self) -> usize {
+        self.id
+    }
+
+    /// The planet's body in the space it's in.
+    pub fn get_entity(&self) -> Option<Entity> {
+        self.entity
+    }
+
+    /// Whether ships can land on it and attach to it. Gas giants aren't solid, and neither is
+    /// what's left of a planet that's crumbled away.
+    pub fn is_solid(&self) -> bool {
+        self.kind != PlanetKind::GasGiant && self.radius > 0.0
+    }
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+/// A handle to a planet. It remembers which space it came from and which planet it was, so a
+/// handle that has gone stale (the level was reset, or the planet moved to another area) or that
+/// belongs to another space is found out by `Space::get_planet` rather than giving the wrong
+/// planet or panicking.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
 pub struct PlanetIndex {
-    // Keep this private, so users can't construct PlanetIndexes. This marginally improves safety,
-    // since Space::get_planet is unsafe if passed PlanetIndexes that weren't returned from
-    // Space::get_nearby_planets. However, it's only marginal since the user could have multiple
-    // Space instances, and cause panics by passing one space's PlanetIndex objects to a different
-    // space. Need dependent types.
+    // Keep these private, so the only handles are ones a Space gave out.
+    space: usize,
     area: Area,
     idx: usize,
+    id: usize, // the planet's id, which no later planet in the slot will have
 }
 
 impl PlanetIndex {
     pub fn get_area(&self) -> Area {
         self.area
     }
-}
 
-#[derive(Debug, PartialEq)]
-pub struct CrawlerBug {
-    pub attached: PlanetIndex,
-    pub rotation: f64,
-    pub moves_right: bool,
+    /// The area and index within the area, for sending over the network. Use
+    /// `Space::planet_index` to turn them back into a PlanetIndex.
+    pub fn to_parts(&self) -> (Area, usize) {
+        (self.area, self.idx)
+    }
 }
 
 pub type Area = (i32, i32);
@@ -69,33 +197,317 @@ type BoundingBox = ncollide::bounding_volume::AABB<na::Point2<f64>>;
 
 /// Space is responsible for holding all the planets in the universe, generating planets when the
 /// ship moves through space, and also giving a view of nearby planets. It is responsible for
-/// holding the position of the ship (`current_point`) to give a safe way to see nearby planets.
+/// holding the positions of the ships (`current_points`) to give a safe way to see nearby planets.
+/// Everything in the level, the planets included, is also an entity in `entities`.
 #[derive(Debug)]
 pub struct Space {
+    id: usize,
     // Keep this private!
-    // this uses a HashMap for CrawlerBugs so that they're easier to delete
-    areas: HashMap<Area, (Vec<Planet>, HashMap<usize, CrawlerBug>)>,
-    current_point: Point,
+    areas: HashMap<Area, Vec<Planet>>,
+    pub entities: Entities,
+    current_points: Vec<Point>,
     magic_planet: Point,
+    planet_count: usize, // for giving planets ids
+    time: f64, // how long the level has been going, which is what moving planets go by
+    // the PlanetIndexes that changed when planets moved between areas in the last update
+    moves: Vec<(PlanetIndex, PlanetIndex)>,
 }
 
 impl Space {
     pub fn new() -> Self {
-        let mut sp = Space {
+        Space::with_seed(rand::random())
+    }
+
+    /// Generate a space at normal difficulty.
+    pub fn with_seed(seed: u32) -> Self {
+        Space::with_difficulty(seed, &Difficulty::normal())
+    }
+
+    /// Generate a space with the random walk generator.
+    pub fn with_difficulty(seed: u32, difficulty: &Difficulty) -> Self {
+        Space::with_generator(seed, difficulty, &RandomWalk)
+    }
+
+    /// Generate a space. The same seed, difficulty and generator always generate the same space,
+    /// which is how network clients get the same level as the server without it being sent to
+    /// them.
+    pub fn with_generator(seed: u32, difficulty: &Difficulty, generator: &LevelGenerator) -> Self {
+        Space::generate(seed, difficulty, generator).0
+    }
+
+    /// Generate a space like `with_generator`, and say how it went.
+    pub fn generate(seed: u32,
+                    difficulty: &Difficulty,
+                    generator: &LevelGenerator)
+                    -> (Self, Generation) {
+        let mut sp = Space::blank();
+        let id = sp.id;
+        // XorShift needs a non-zero seed, and gives similar results for similar seeds, so mix the
+        // seed up a bit.
+        let mut rng = XorShiftRng::from_seed([seed ^ 0x2545F491,
+                                              seed.wrapping_mul(0x9E3779B1),
+                                              seed.rotate_left(16) ^ 0x85EBCA6B,
+                                              0xC2B2AE35]);
+        // the first few numbers out of a freshly seeded XorShift aren't very random
+        for _ in 0..16 {
+            rng.next_u32();
+        }
+        let envelope = Envelope::ship();
+        let mut generation = Generation {
+            attempts: 0,
+            first_problem: None,
+            repaired: false,
+            problem: None,
+        };
+        // throw away levels that can't be won. The retries come out of the same generator, so
+        // this still always gives the same space for the same seed.
+        while generation.attempts < MAX_ATTEMPTS {
+            sp.areas.clear();
+            sp.entities = Entities::new(id);
+            sp.planet_count = 0;
+            generator.generate(&mut sp, &mut rng, difficulty);
+            generator::add_fuel_cans(&mut sp);
+            generation.attempts += 1;
+            match reach::check(&sp, &envelope) {
+                Ok(_) => return (sp, generation),
+                Err(problem) => {
+                    if generation.attempts == 1 {
+                        generation.first_problem = Some(problem);
+                    }
+                }
+            }
+        }
+        // out of luck: patch up the last one instead
+        generation.repaired = true;
+        generation.problem = generator::make_solvable(&mut sp).err();
+        (sp, generation)
+    }
+
+    /// A space with nothing in it but the first planet, for a client that doesn't know yet which
+    /// level it's on.
+    pub fn empty() -> Self {
+        let mut sp = Space::blank();
+        generator::add_start(&mut sp);
+        sp
+    }
+
+    /// A space with nothing in it at all, not even the first planet.
+    fn blank() -> Self {
+        let id = NEXT_SPACE_ID.fetch_add(1, Ordering::Relaxed);
+        Space {
+            id: id,
             areas: HashMap::new(),
-            current_point: pt(0.0, 0.0),
+            entities: Entities::new(id),
+            current_points: vec![pt(0.0, 0.0)],
             magic_planet: pt(0.0, 0.0),
+            planet_count: 0,
+            time: 0.0,
+            moves: vec![],
+        }
+    }
+
+    /// Add a planet. Generators must add the first planet first, at the origin. A moving planet
+    /// is put where its motion says it starts, whatever its `pos`.
+    pub fn add_planet(&mut self, mut planet: Planet) -> PlanetIndex {
+        if let Some(pos) = planet.motion.as_ref().map(|motion| motion.position(self.time, self)) {
+            planet.pos = pos;
+        }
+        self.add_planet_to_area(planet)
+    }
+
+    /// How long the level has been going.
+    pub fn get_time(&self) -> f64 {
+        self.time
+    }
+
+    /// Move the moving planets on by `time_delta` seconds.
+    pub fn move_planets(&mut self, time_delta: f64) {
+        let time = self.time + time_delta;
+        self.set_time(time);
+    }
+
+    /// Put the moving planets where they are `time` seconds into the level, and what's attached
+    /// to them with them. A planet that leaves its area moves to the new one. That changes its
+    /// PlanetIndex, and maybe that of the planet that takes its place, so anything holding on to a
+    /// PlanetIndex should pass it through `relocated` afterwards.
+    pub fn set_time(&mut self, time: f64) {
+        self.time = time;
+        self.moves.clear();
+        let (orbiting, travelling): (Vec<PlanetIndex>, Vec<PlanetIndex>) =
+            self.get_all_planet_indices()
+                .into_iter()
+                .filter(|&idx| self.planet(idx).motion.is_some())
+                .partition(|&idx| match self.planet(idx).motion {
+                    Some(Motion::Orbit { .. }) => true,
+                    _ => false,
+                });
+        // moons go last, so they go around where their parents have got to
+        for idx in travelling.into_iter().chain(orbiting) {
+            let pos = match self.planet(idx).motion {
+                Some(ref motion) => motion.position(time, self),
+                None => continue,
+            };
+            let planet = &mut self.areas.get_mut(&idx.area).unwrap()[idx.idx];
+            planet.moved = pt(pos.x - planet.pos.x, pos.y - planet.pos.y);
+            planet.pos = pos;
+        }
+        loop {
+            let misplaced = self.get_all_planet_indices()
+                .into_iter()
+                .find(|&idx| Space::area_for_point(self.planet(idx).pos) != idx.area);
+            match misplaced {
+                Some(idx) => self.change_area(idx),
+                None => break,
+            }
+        }
+        self.update_attached();
+    }
+
+    /// The body system for planets: bring each planet's transform and collider up to date with
+    /// where it is and how big it is.
+    pub fn update_bodies(&mut self) {
+        let ref mut entities = self.entities;
+        for planet in self.areas.values().flat_map(|planets| planets) {
+            let entity = match planet.entity {
+                Some(entity) => entity,
+                None => continue,
+            };
+            if let Some(transform) = entities.transforms.get_mut(entity) {
+                transform.pos = planet.pos;
+            }
+            if let Some(collider) = entities.colliders.get_mut(entity) {
+                collider.radius = planet.radius;
+            }
+        }
+    }
+
+    /// The attachment system: put everything attached to a planet where it is now. Anything whose
+    /// planet is gone stays where it was.
+    pub fn update_attached(&mut self) {
+        let placed: Vec<(Entity, Transform)> = self.entities
+            .attachments
+            .iter()
+            .filter_map(|(entity, attachment)| {
+                self.get_planet(attachment.planet).map(|planet| {
+                    let pos = rotated_position(planet.pos,
+                                               attachment.rotation,
+                                               planet.radius + attachment.height);
+                    (entity,
+                     Transform {
+                         pos: pos,
+                         rotation: attachment.rotation,
+                     })
+                })
+            })
+            .collect();
+        for (entity, transform) in placed {
+            self.entities.transforms.insert(entity, transform);
+        }
+    }
+
+    /// What a PlanetIndex from before the last update has turned into.
+    pub fn relocated(&self, idx: PlanetIndex) -> PlanetIndex {
+        self.moves.iter().fold(idx, |idx, &(from, to)| if idx == from { to } else { idx })
+    }
+
+    /// Move a planet to the area it's now in. The last planet of its old area takes its place.
+    fn change_area(&mut self, from: PlanetIndex) {
+        let planet = self.areas.get_mut(&from.area).unwrap().swap_remove(from.idx);
+        let replaced = self.areas[&from.area].len();
+        let to = self.add_planet_to_area(planet);
+        self.move_planet_index(from, to);
+        if from.idx < replaced {
+            let last = PlanetIndex {
+                idx: replaced,
+                ..self.planet_handle(from.area, from.idx)
+            };
+            let now = self.planet_handle(from.area, from.idx);
+            self.move_planet_index(last, now);
+        }
+    }
+
+    /// Give a new planet an id and file it under its area. Moving planets keep their ids.
+    fn add_planet_to_area(&mut self, mut planet: Planet) -> PlanetIndex {
+        if planet.id == 0 {
+            self.planet_count += 1;
+            planet.id = self.planet_count;
+            planet.entity = Some(self.entities.spawn_planet(planet.pos, planet.radius));
+        }
+        let area = Space::area_for_point(planet.pos);
+        let idx = {
+            let planets = self.areas.entry(area).or_insert_with(Vec::new);
+            planets.push(planet);
+            planets.len() - 1
         };
-        sp.generate_level();
-        sp
+        self.planet_handle(area, idx)
+    }
+
+    /// Look up a planet from a handle that was just made, so can't be stale.
+    fn planet(&self, idx: PlanetIndex) -> &Planet {
+        &self.areas[&idx.area][idx.idx]
+    }
+
+    /// Where the planet a moon orbits is. Parents are never removed, but if one were the moon would
+    /// go around the origin rather than panic.
+    fn parent_pos(&self, parent: PlanetIndex) -> Point {
+        self.get_planet(parent).map_or(pt(0.0, 0.0), |planet| planet.pos)
+    }
+
+    /// A handle to the planet in a slot, which must exist.
+    fn planet_handle(&self, area: Area, idx: usize) -> PlanetIndex {
+        PlanetIndex {
+            space: self.id,
+            area: area,
+            idx: idx,
+            id: self.areas[&area][idx].id,
+        }
+    }
+
+    /// Point everything in the space that was on the planet at `from` to `to`.
+    fn move_planet_index(&mut self, from: PlanetIndex, to: PlanetIndex) {
+        self.moves.push((from, to));
+        for entity in self.entities.attached_to(from) {
+            if let Some(attachment) = self.entities.attachments.get_mut(entity) {
+                attachment.planet = to;
+            }
+        }
+        for planets in self.areas.values_mut() {
+            for planet in planets.iter_mut() {
+                if let Some(Motion::Orbit { ref mut parent, .. }) = planet.motion {
+                    if *parent == from {
+                        *parent = to;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Put a crawler on a planet. Returns None if there's no such planet.
+    pub fn add_bug(&mut self,
+                   planet: PlanetIndex,
+                   rotation: f64,
+                   moves_right: bool)
+                   -> Option<Entity> {
+        if self.get_planet(planet).is_none() {
+            return None;
+        }
+        let bug = self.entities.spawn_crawler(planet, rotation, moves_right);
+        self.update_attached();
+        Some(bug)
+    }
+
+    /// Put a fuel can that fills a tank at `pos`.
+    pub fn add_fuel_can(&mut self, pos: Point) -> Entity {
+        self.entities.spawn_fuel_can(pos, MAX_FUEL)
     }
 
-    pub fn focus(&mut self, p: Point) {
-        self.current_point = p;
+    pub fn set_magic_planet(&mut self, pos: Point) {
+        self.magic_planet = pos;
     }
 
-    pub fn get_focus(&self) -> Point {
-        self.current_point
+    /// Set the points that "nearby" is measured from, usually the positions of the ships.
+    pub fn focus(&mut self, points: &[Point]) {
+        self.current_points = points.to_vec();
     }
 
     /// Get an AABB that contains all of the planets in this space, plus a margin.
@@ -131,7 +543,17 @@ impl Space {
     }
 
     pub fn get_all_planets(&self) -> Vec<&Planet> {
-        self.areas.iter().flat_map(|(_, &(ref planets, _))| planets).collect()
+        self.areas.values().flat_map(|planets| planets).collect()
+    }
+
+    /// Get the index of every planet in the space.
+    pub fn get_all_planet_indices(&self) -> Vec<PlanetIndex> {
+        self.areas
+            .iter()
+            .flat_map(|(&area, planets)| {
+                (0..planets.len()).map(move |idx| self.planet_handle(area, idx))
+            })
+            .collect()
     }
 
     /// Return all nearby planets
@@ -139,16 +561,10 @@ impl Space {
         self.get_nearby_areas()
             .iter()
             .flat_map::<Vec<(PlanetIndex, &Planet)>, _>(|area| {
-                if let Some(&(ref planets, _)) = self.areas.get(&area) {
+                if let Some(planets) = self.areas.get(&area) {
                     planets.iter()
                         .enumerate()
-                        .map(|(i, p)| {
-                            (PlanetIndex {
-                                 area: area.clone(),
-                                 idx: i,
-                             },
-                             p)
-                        })
+                        .map(|(i, p)| (self.planet_handle(*area, i), p))
                         .collect()
                 } else {
                     vec![]
@@ -158,134 +574,162 @@ impl Space {
             .collect()
     }
 
-    // Return indices of nearby CrawlerBugs.
-    pub fn get_nearby_bugs(&self) -> Vec<(Area, usize)> {
-        self.get_nearby_areas()
+    /// The entities in the nearby areas.
+    pub fn get_nearby_entities(&self) -> Vec<Entity> {
+        let areas = self.get_nearby_areas();
+        self.entities
+            .transforms
             .iter()
-            .flat_map::<Vec<(Area, usize)>, _>(|area| {
-                if let Some(&(_, ref bugs)) = self.areas.get(&area) {
-                    bugs.keys().map(|i| (area.clone(), i.clone())).collect()
-                } else {
-                    vec![]
-                }
+            .filter(|&(_, transform)| areas.contains(&Space::area_for_point(transform.pos)))
+            .map(|(entity, _)| entity)
+            .collect()
+    }
+
+    /// The nearby entities that kill ships, like the crawlers.
+    pub fn get_nearby_hazards(&self) -> Vec<Entity> {
+        self.get_nearby_entities()
+            .into_iter()
+            .filter(|&entity| self.entities.hazards.contains(entity))
+            .collect()
+    }
+
+    /// Get the (area, index, radius) of every planet that's crumbling, for sending over the
+    /// network.
+    pub fn get_crumbling_planets(&self) -> Vec<(Area, usize, f64)> {
+        self.areas
+            .iter()
+            .flat_map(|(area, planets)| {
+                planets.iter()
+                    .enumerate()
+                    .filter(|&(_, planet)| planet.crumbling)
+                    .map(move |(idx, planet)| (*area, idx, planet.radius))
             })
             .collect()
     }
 
-    /// Get a bug. Only safe when using Area and usize as returned from get_nearby_bugs.
-    pub fn get_bug(&self, area: Area, idx: usize) -> &CrawlerBug {
-        self.areas[&area].1.get(&idx).unwrap()
+    /// Make the planets in a list from `get_crumbling_planets` crumble, and set their radiuses.
+    pub fn sync_crumbling_planets(&mut self, planets: &[(Area, usize, f64)]) {
+        for &(area, idx, radius) in planets.iter() {
+            if let Some(planet) = self.planet_index(area, idx).and_then(|planet| {
+                self.get_planet_mut(planet)
+            }) {
+                planet.crumbling = true;
+                planet.radius = radius;
+            }
+        }
     }
-    /// Get a mutable bug. Only safe when using Area and usize as returned from get_nearby_bugs.
-    pub fn get_bug_mut(&mut self, area: Area, idx: usize) -> &mut CrawlerBug {
-        self.areas.get_mut(&area).unwrap().1.get_mut(&idx).unwrap()
+
+    /// Make the crawlers match a list of (slot, rotation) received from the server: crawlers that
+    /// aren't in the list have been killed. Crawlers are all made with the level, in the same
+    /// order everywhere, so they're in the same slots here as on the server.
+    pub fn sync_bugs(&mut self, bugs: &[(usize, f64)]) {
+        for entity in self.entities.ais.entities() {
+            match bugs.iter().find(|&&(idx, _)| idx == entity.get_index()) {
+                Some(&(_, rotation)) => {
+                    if let Some(attachment) = self.entities.attachments.get_mut(entity) {
+                        attachment.rotation = rotation;
+                    }
+                }
+                None => {
+                    self.entities.despawn(entity);
+                }
+            }
+        }
+        self.update_attached();
     }
-    /// Delete a bug. Only safe when using Area and usize as returned from get_nearby_bugs.
-    pub fn delete_bug(&mut self, area: Area, idx: usize) {
-        let ref mut bugs = self.areas.get_mut(&area).unwrap().1;
-        bugs.remove(&idx);
+
+    /// Every crawler's slot and rotation, for sending over the network.
+    pub fn get_all_bugs(&self) -> Vec<(usize, f64)> {
+        self.entities
+            .ais
+            .entities()
+            .into_iter()
+            .filter_map(|entity| {
+                self.entities
+                    .attachments
+                    .get(entity)
+                    .map(|attachment| (entity.get_index(), attachment.rotation))
+            })
+            .collect()
     }
 
-    /// Look up a specific planet. This is safe only when using a PlanetIndex returned from *this
-    /// instance's* get_nearby_planets method
-    pub fn get_planet(&self, idx: PlanetIndex) -> &Planet {
-        &self.areas[&idx.area].0[idx.idx]
+    /// Make the pickups match the slots of the ones still there on the server: the rest have been
+    /// picked up. Like the crawlers, they're made with the level, so the slots are the same.
+    pub fn sync_pickups(&mut self, pickups: &[usize]) {
+        for entity in self.entities.pickups.entities() {
+            if !pickups.contains(&entity.get_index()) {
+                self.entities.despawn(entity);
+            }
+        }
     }
 
-    fn get_nearby_areas(&self) -> Vec<Area> {
-        let area = self.get_central_area();
-        let (x, y) = (area.0, area.1);
-        vec![(x - 1, y - 1),
-             (x - 1, y + 0),
-             (x - 1, y + 1),
-             (x + 0, y - 1),
-             (x + 0, y + 0),
-             (x + 0, y + 1),
-             (x + 1, y - 1),
-             (x + 1, y + 0),
-             (x + 1, y + 1)]
-    }
-
-    fn area_for_point(p: Point) -> Area {
-        let x = p.x / AREA_WIDTH;
-        let y = p.y / AREA_HEIGHT;
-        (x.floor() as i32, y.floor() as i32)
+    /// The slot of every pickup that's still there, for sending over the network.
+    pub fn get_all_pickups(&self) -> Vec<usize> {
+        self.entities.pickups.entities().iter().map(|entity| entity.get_index()).collect()
     }
 
-    pub fn get_central_area(&self) -> Area {
-        Space::area_for_point(self.current_point)
+    /// Turn the parts from `PlanetIndex::to_parts` back into a PlanetIndex, if there is such a
+    /// planet in this space.
+    pub fn planet_index(&self, area: Area, idx: usize) -> Option<PlanetIndex> {
+        match self.areas.get(&area) {
+            Some(planets) if idx < planets.len() => Some(self.planet_handle(area, idx)),
+            _ => None,
+        }
     }
 
-    pub fn get_first_planet(&self) -> PlanetIndex {
-        PlanetIndex {
-            area: (0, 0),
-            idx: 0,
+    /// Look up a specific planet. Returns None if the handle is from another space, or has gone
+    /// stale.
+    pub fn get_planet(&self, idx: PlanetIndex) -> Option<&Planet> {
+        if idx.space != self.id {
+            return None;
         }
+        self.areas
+            .get(&idx.area)
+            .and_then(|planets| planets.get(idx.idx))
+            .and_then(|planet| if planet.id == idx.id { Some(planet) } else { None })
     }
 
-    /// Generate planets around the current center point
-    fn generate_level(&mut self) {
-        // use an absolute bug count to index bugs so that we can safely delete them even while
-        // looping over them.
-        // I use Atomic only so I can avoid "unsafe" blocks which would be needed for static mut
-        static BUG_COUNT: AtomicUsize = ATOMIC_USIZE_INIT;
-
-        let range_circle = Range::new(-2.0 * PI, 2.0 * PI);
-        let range_distance = Range::new(MIN_PLANET_DISTANCE, MAX_PLANET_DISTANCE);
-        let range_radius = Range::new(MIN_PLANET_SIZE, MAX_PLANET_SIZE);
-        let range_direction = Range::new(-PI * PATH_VARIANCE, PI * PATH_VARIANCE);
-        let range_percent = Range::new(0.0, 1.0);
-        let range_bool = Range::new(0, 2);
-        let mut rng = rand::thread_rng();
-
-        self.areas.insert((0, 0),
-                          (vec![Planet {
-                                    bouncy: false,
-                                    radius: 50.0,
-                                    pos: pt(0.0, 0.0),
-                                }],
-                           HashMap::new()));
-        let mut prev_pos = pt(0.0, 0.0);
-        let mut prev_rot = range_circle.ind_sample(&mut rng);
-
-        for _ in 0..NUM_PLANETS {
-            let radius = range_radius.ind_sample(&mut rng);
-            let distance = range_distance.ind_sample(&mut rng);
-            let direction = range_direction.ind_sample(&mut rng);
-            let direction = direction + prev_rot;
-            let pos = rotated_position(prev_pos, direction, distance);
-            let planet = Planet {
-                pos: pos,
-                radius: radius,
-                bouncy: range_percent.ind_sample(&mut rng) > BOUNCY_PERCENTAGE,
-            };
-            let area = Self::area_for_point(pos);
-            self.areas.entry(area).or_insert((vec![], HashMap::new()));
-            if let Some(area_content) = self.areas.get_mut(&area) {
-                area_content.0.push(planet);
-            }
-            prev_pos = pos;
-            prev_rot = direction;
-            // and the bug
-            if range_percent.ind_sample(&mut rng) > CRAWLER_PERCENTAGE {
-                let rot = range_circle.ind_sample(&mut rng);
-                let planet_num = self.areas[&area].0.len() - 1;
-                self.areas
-                    .get_mut(&area)
-                    .unwrap()
-                    .1
-                    .insert(BUG_COUNT.fetch_add(1, Ordering::SeqCst),
-                            CrawlerBug {
-                                moves_right: range_bool.ind_sample(&mut rng) == 1,
-                                rotation: rot,
-                                attached: PlanetIndex {
-                                    area: area,
-                                    idx: planet_num,
-                                },
-                            });
+    /// Look up a specific planet to change it. The same rules as `get_planet` apply.
+    pub fn get_planet_mut(&mut self, idx: PlanetIndex) -> Option<&mut Planet> {
+        if idx.space != self.id {
+            return None;
+        }
+        self.areas
+            .get_mut(&idx.area)
+            .and_then(|planets| planets.get_mut(idx.idx))
+            .and_then(|planet| if planet.id == idx.id { Some(planet) } else { None })
+    }
+
+    /// Get the areas around each focus point, without duplicates.
+    fn get_nearby_areas(&self) -> Vec<Area> {
+        let mut areas = vec![];
+        for &point in self.current_points.iter() {
+            let (x, y) = Space::area_for_point(point);
+            for dx in -1..2 {
+                for dy in -1..2 {
+                    let area = (x + dx, y + dy);
+                    if !areas.contains(&area) {
+                        areas.push(area);
+                    }
+                }
             }
         }
+        areas
+    }
+
+    pub fn area_for_point(p: Point) -> Area {
+        let x = p.x / AREA_WIDTH;
+        let y = p.y / AREA_HEIGHT;
+        (x.floor() as i32, y.floor() as i32)
+    }
+
+    /// The planet the ships start on. Generators always add it first, at the origin.
+    pub fn get_first_planet(&self) -> PlanetIndex {
+        self.planet_handle((0, 0), 0)
+    }
 
-        self.magic_planet = rotated_position(prev_pos, prev_rot, 400.0);
+    /// The first planet itself, which is always there.
+    pub fn get_start_planet(&self) -> &Planet {
+        &self.areas[&(0, 0)][0]
     }
 }
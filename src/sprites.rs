@@ -0,0 +1,88 @@
+use std::path::Path;
+
+use piston_window::{G2dTexture, PistonWindow, Texture, TextureSettings, Flip};
+use im;
+
+use circles::space::{PlanetKind, PLANET_KINDS};
+
+/// Seconds each frame of the crawler animation is shown for.
+pub const CRAWLER_FRAME_TIME: f64 = 0.15;
+
+/// Sprites holds the textures loaded from `assets/sprites`. Every one of them is optional: when a
+/// file is missing the renderer falls back to drawing the old primitive shapes.
+///
+/// Sprite sheets (`crawler.png` and the planet sheets) are horizontal strips of square frames, so
+/// a 256x64 sheet holds four 64x64 frames.
+pub struct Sprites {
+    pub ship: Option<G2dTexture>,
+    pub magic_planet: Option<G2dTexture>,
+    pub crawler: Vec<G2dTexture>, // animation frames
+    pub planets: Vec<Vec<G2dTexture>>, // a sheet per planet kind, one is picked for each planet
+}
+
+impl Sprites {
+    pub fn load(window: &mut PistonWindow, folder: &Path) -> Self {
+        Sprites {
+            ship: load_texture(window, &folder.join("ship.png")),
+            magic_planet: load_texture(window, &folder.join("magic_planet.png")),
+            crawler: load_sheet(window, &folder.join("crawler.png")),
+            planets: PLANET_KINDS.iter()
+                .map(|&kind| load_sheet(window, &folder.join(planet_sheet(kind))))
+                .collect(),
+        }
+    }
+}
+
+/// The file the textures for a kind of planet are read from.
+fn planet_sheet(kind: PlanetKind) -> &'static str {
+    match kind {
+        PlanetKind::Normal => "planets.png",
+        PlanetKind::Bouncy => "bouncy_planets.png",
+        PlanetKind::Icy => "icy_planets.png",
+        PlanetKind::Sticky => "sticky_planets.png",
+        PlanetKind::Hot => "hot_planets.png",
+        PlanetKind::GasGiant => "gas_giants.png",
+        PlanetKind::Crumbling => "crumbling_planets.png",
+    }
+}
+
+fn load_texture(window: &mut PistonWindow, path: &Path) -> Option<G2dTexture> {
+    match Texture::from_path(&mut window.factory, path, Flip::None, &TextureSettings::new()) {
+        Ok(texture) => Some(texture),
+        Err(e) => {
+            println!("Not using sprite {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Load a sprite sheet and slice it into one texture per frame.
+fn load_sheet(window: &mut PistonWindow, path: &Path) -> Vec<G2dTexture> {
+    let sheet = match im::open(path) {
+        Ok(img) => img.to_rgba(),
+        Err(e) => {
+            println!("Not using sprite sheet {}: {}", path.display(), e);
+            return vec![];
+        }
+    };
+    let frame_size = sheet.height();
+    if frame_size == 0 {
+        return vec![];
+    }
+    let mut frames = vec![];
+    for frame in 0..(sheet.width() / frame_size) {
+        let frame_img: im::ImageBuffer<im::Rgba<u8>, Vec<u8>> =
+            im::ImageBuffer::from_fn(frame_size, frame_size, |x, y| {
+                *sheet.get_pixel(frame * frame_size + x, y)
+            });
+        match Texture::from_image(&mut window.factory, &frame_img, &TextureSettings::new()) {
+            Ok(texture) => frames.push(texture),
+            Err(e) => {
+                // half a sheet would animate oddly; draw the shapes instead
+                println!("Not using sprite sheet {}: {}", path.display(), e);
+                return vec![];
+            }
+        }
+    }
+    frames
+}
@@ -0,0 +1,106 @@
+extern crate rand;
+
+use self::rand::{Rng, SeedableRng, XorShiftRng};
+use circles::calc::{Point, pt};
+use circles::space::{Area, Space, AREA_WIDTH, AREA_HEIGHT};
+
+/// One layer of the starfield. Layers with a smaller `parallax` are further away, so they scroll
+/// past the camera more slowly.
+pub struct Layer {
+    pub parallax: f64,
+    pub stars_per_area: u32,
+    pub size: f64,
+    pub brightness: f32,
+}
+
+pub const LAYERS: [Layer; 3] = [Layer {
+                                     parallax: 0.1,
+                                     stars_per_area: 300,
+                                     size: 1.0,
+                                     brightness: 0.4,
+                                 },
+                                 Layer {
+                                     parallax: 0.25,
+                                     stars_per_area: 120,
+                                     size: 1.5,
+                                     brightness: 0.6,
+                                 },
+                                 Layer {
+                                     parallax: 0.5,
+                                     stars_per_area: 40,
+                                     size: 2.0,
+                                     brightness: 0.9,
+                                 }];
+
+#[derive(Debug, Clone, Copy)]
+pub struct Star {
+    pub pos: Point, // in screen coordinates
+    pub size: f64,
+    pub brightness: f32,
+}
+
+/// Starfield generates the background stars. Nothing is stored: the stars in each `Area` of each
+/// layer are generated from an RNG seeded with the area's coordinates, so the same area always
+/// gets the same stars no matter how far the ship travels.
+pub struct Starfield {
+    seed: u32,
+}
+
+impl Starfield {
+    pub fn new(seed: u32) -> Self {
+        Starfield { seed: seed }
+    }
+
+    /// Get the stars of a layer that are visible from `camera`, in screen coordinates.
+    pub fn get_visible_stars(&self,
+                             layer: usize,
+                             camera: Point,
+                             view_width: f64,
+                             view_height: f64)
+                             -> Vec<Star> {
+        let layer_camera = pt(camera.x * LAYERS[layer].parallax,
+                              camera.y * LAYERS[layer].parallax);
+        let min = Space::area_for_point(layer_camera);
+        let max = Space::area_for_point(pt(layer_camera.x + view_width,
+                                           layer_camera.y + view_height));
+        let mut stars = vec![];
+        for x in min.0..(max.0 + 1) {
+            for y in min.1..(max.1 + 1) {
+                for star in self.generate_area(layer, (x, y)) {
+                    let pos = pt(star.pos.x - layer_camera.x, star.pos.y - layer_camera.y);
+                    if pos.x >= 0.0 && pos.x < view_width && pos.y >= 0.0 && pos.y < view_height {
+                        stars.push(Star { pos: pos, ..star });
+                    }
+                }
+            }
+        }
+        stars
+    }
+
+    /// Generate the stars of one area of a layer, in layer coordinates.
+    fn generate_area(&self, layer: usize, area: Area) -> Vec<Star> {
+        let mut rng = self.area_rng(layer, area);
+        let origin = pt(area.0 as f64 * AREA_WIDTH, area.1 as f64 * AREA_HEIGHT);
+        let params = &LAYERS[layer];
+        (0..params.stars_per_area)
+            .map(|_| {
+                Star {
+                    pos: pt(origin.x + rng.gen::<f64>() * AREA_WIDTH,
+                            origin.y + rng.gen::<f64>() * AREA_HEIGHT),
+                    size: params.size * (0.5 + rng.gen::<f64>()),
+                    brightness: params.brightness * (0.5 + 0.5 * rng.gen::<f32>()),
+                }
+            })
+            .collect()
+    }
+
+    fn area_rng(&self, layer: usize, area: Area) -> XorShiftRng {
+        // XorShift produces similar streams for similar seeds, so scramble the coordinates first.
+        // The last word can never be zero, since an all-zero seed is invalid.
+        let scramble = |n: u32| n.wrapping_mul(0x9E3779B1).rotate_left(16).wrapping_mul(0x85EBCA6B);
+        XorShiftRng::from_seed([scramble(self.seed),
+                                scramble(area.0 as u32),
+                                scramble(area.1 as u32 ^ 0x5bd1e995),
+                                scramble(layer as u32) | 1])
+    }
+}
@@ -0,0 +1,138 @@
+use ship::{JUMP_SPEED, AIR_CONTROL_MOD, FIRE_COOLDOWN, ATTACH_RANGE};
+
+// how many times each upgrade can be bought
+pub const MAX_UPGRADE_LEVEL: u32 = 5;
+// what the first of each upgrade costs; each one after that costs this much more
+const BASE_COST: u64 = 10;
+
+/// Something that can be bought for a ship in the shop between levels.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Upgrade {
+    Jump,
+    AirControl,
+    FireRate,
+    AttachRange,
+    Health,
+}
+
+/// Every upgrade, in the order the shop lists them.
+pub const UPGRADES: [Upgrade; 5] = [Upgrade::Jump,
+                                    Upgrade::AirControl,
+                                    Upgrade::FireRate,
+                                    Upgrade::AttachRange,
+                                    Upgrade::Health];
+
+impl Upgrade {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Upgrade::Jump => "Jump height",
+            Upgrade::AirControl => "Air control",
+            Upgrade::FireRate => "Fire rate",
+            Upgrade::AttachRange => "Attach range",
+            Upgrade::Health => "Extra health",
+        }
+    }
+
+    /// What it's called in the profile.
+    pub fn key(&self) -> &'static str {
+        match *self {
+            Upgrade::Jump => "upgrade_jump",
+            Upgrade::AirControl => "upgrade_air_control",
+            Upgrade::FireRate => "upgrade_fire_rate",
+            Upgrade::AttachRange => "upgrade_attach_range",
+            Upgrade::Health => "upgrade_health",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Upgrade> {
+        UPGRADES.iter().find(|upgrade| upgrade.key() == key).cloned()
+    }
+
+    /// What it costs to buy once more when it's already been bought `level` times.
+    pub fn cost(&self, level: u32) -> u64 {
+        BASE_COST * (level as u64 + 1)
+    }
+}
+
+/// How many of each upgrade a ship has, and what that makes of its stats.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Upgrades {
+    pub jump: u32,
+    pub air_control: u32,
+    pub fire_rate: u32,
+    pub attach_range: u32,
+    pub health: u32,
+}
+
+impl Upgrades {
+    pub fn get(&self, upgrade: Upgrade) -> u32 {
+        match upgrade {
+            Upgrade::Jump => self.jump,
+            Upgrade::AirControl => self.air_control,
+            Upgrade::FireRate => self.fire_rate,
+            Upgrade::AttachRange => self.attach_range,
+            Upgrade::Health => self.health,
+        }
+    }
+
+    pub fn set(&mut self, upgrade: Upgrade, level: u32) {
+        let level = level.min(MAX_UPGRADE_LEVEL);
+        match upgrade {
+            Upgrade::Jump => self.jump = level,
+            Upgrade::AirControl => self.air_control = level,
+            Upgrade::FireRate => self.fire_rate = level,
+            Upgrade::AttachRange => self.attach_range = level,
+            Upgrade::Health => self.health = level,
+        }
+    }
+
+    /// What buying `upgrade` once more would cost, or None if it's been bought as often as it
+    /// can be.
+    pub fn price(&self, upgrade: Upgrade) -> Option<u64> {
+        let level = self.get(upgrade);
+        if level < MAX_UPGRADE_LEVEL {
+            Some(upgrade.cost(level))
+        } else {
+            None
+        }
+    }
+
+    /// Buy `upgrade` once more out of `credits`. Returns false, and spends nothing, if it's maxed
+    /// out or too expensive.
+    pub fn buy(&mut self, upgrade: Upgrade, credits: &mut u64) -> bool {
+        match self.price(upgrade) {
+            Some(price) if price <= *credits => {
+                *credits -= price;
+                let level = self.get(upgrade);
+                self.set(upgrade, level + 1);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// How fast the ship leaves the ground when it jumps.
+    pub fn jump_speed(&self) -> f64 {
+        JUMP_SPEED * (1.0 + 0.1 * self.jump as f64)
+    }
+
+    /// How much of its walking speed the ship can turn with in the air.
+    pub fn air_control(&self) -> f64 {
+        AIR_CONTROL_MOD * (1.0 + 0.25 * self.air_control as f64)
+    }
+
+    /// Seconds between shots.
+    pub fn fire_cooldown(&self) -> f64 {
+        FIRE_COOLDOWN * 0.85f64.powi(self.fire_rate as i32)
+    }
+
+    /// How far from a planet's surface the ship can attach to it.
+    pub fn attach_range(&self) -> f64 {
+        ATTACH_RANGE + 100.0 * self.attach_range as f64
+    }
+
+    /// How many hits the ship can take in a level before one counts.
+    pub fn extra_health(&self) -> u32 {
+        self.health
+    }
+}
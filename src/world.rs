@@ -0,0 +1,1106 @@
+use std::f64::consts::PI;
+use std::mem;
+
+use ncollide::query;
+use ncollide::shape::Ball;
+
+use space::{Space, PlanetIndex, PlanetKind, MAGIC_PLANET_SIZE};
+use calc::{Point, pt, rotated_position, coll_pt, coll_vec, direction_from_to, distance};
+use ship::{Ship, SHIP_SIZE, JUMP_SPEED, MAX_FUEL, POUND_RADIUS};
+use difficulty::{Difficulty, Adaptive};
+use generator::Generator;
+use grid::Grid;
+use ecs::{Entity, Body, Collider, Pickup, Transform, Velocity};
+
+pub const CRAWLER_SIZE: f64 = 25.0;
+pub const FUEL_CAN_SIZE: f64 = 12.0;
+/// How many times a second the world is updated. The game, the server and the playtests all move
+/// the world on in steps of `TICK_TIME`, whatever the frame rate, so it plays out the same
+/// everywhere.
+pub const TICK_RATE: u32 = 60;
+pub const TICK_TIME: f64 = 1.0 / 60.0;
+// how much of its speed a ship loses per second while inside a gas giant
+const GAS_DRAG: f64 = 2.0;
+// how fast a crumbling planet shrinks, in radius per second
+const CRUMBLE_SPEED: f64 = 20.0;
+// bullets this far from their ship along either axis are gone
+const BULLET_RANGE: f64 = 5000.0;
+// credits for reaching the magic planet, and for each crawler killed
+pub const WIN_CREDITS: u64 = 10;
+pub const CRAWLER_CREDITS: u64 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    /// One player, alone.
+    Solo,
+    /// Several players; the first to reach the magic planet gets the point.
+    Race,
+    /// Players who can shoot each other. Shooting another ship is worth a point.
+    Versus,
+}
+
+/// Something that happened in the world. The world doesn't play sounds, draw explosions or keep
+/// statistics itself; whoever owns it drains these after each update and hands them to each
+/// system that cares (the sounds, the particles, the HUD, the player's statistics), so a new
+/// system only has to look at the events rather than at the simulation. The world's own scoring
+/// works the same way, off the events as they're emitted.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GameEvent {
+    Shot(usize), // a ship fired a bullet
+    Jumped(usize),
+    Landed { ship: usize, pos: Point, dir: f64 },
+    Bounced { ship: usize, pos: Point, dir: f64 },
+    /// A ship threw a tether to another planet. `dir` points from the ship to it.
+    Attached { ship: usize, pos: Point, dir: f64 },
+    BugKilled { by: usize, pos: Point },
+    /// A ship was killed by a crawler, or by another ship (`by`).
+    ShipHit {
+        ship: usize,
+        by: Option<usize>,
+        pos: Point,
+    },
+    /// A ship was hit, but had the health to take it.
+    ShipDamaged { ship: usize, pos: Point },
+    /// A ship flew into a pickup, like a fuel can.
+    PickedUp { ship: usize, pos: Point },
+    /// A ship landed from a ground pound, crushing the crawlers around `pos`.
+    Pounded { ship: usize, pos: Point },
+    MagicPlanetReached(usize),
+    /// A new level was generated.
+    LevelReset,
+}
+
+impl GameEvent {
+    /// The event as it is once ship `removed` is gone and the ships after it have moved down one,
+    /// or None if it's about that ship.
+    pub fn after_removing(&self, removed: usize) -> Option<GameEvent> {
+        let ship = |i: usize| if i == removed {
+            None
+        } else if i > removed {
+            Some(i - 1)
+        } else {
+            Some(i)
+        };
+        Some(match *self {
+            GameEvent::Shot(i) => GameEvent::Shot(ship(i)?),
+            GameEvent::Jumped(i) => GameEvent::Jumped(ship(i)?),
+            GameEvent::Landed { ship: i, pos, dir } => {
+                GameEvent::Landed {
+                    ship: ship(i)?,
+                    pos: pos,
+                    dir: dir,
+                }
+            }
+            GameEvent::Bounced { ship: i, pos, dir } => {
+                GameEvent::Bounced {
+                    ship: ship(i)?,
+                    pos: pos,
+                    dir: dir,
+                }
+            }
+            GameEvent::Attached { ship: i, pos, dir } => {
+                GameEvent::Attached {
+                    ship: ship(i)?,
+                    pos: pos,
+                    dir: dir,
+                }
+            }
+            GameEvent::BugKilled { by, pos } => {
+                GameEvent::BugKilled {
+                    by: ship(by)?,
+                    pos: pos,
+                }
+            }
+            GameEvent::ShipHit { ship: i, by, pos } => {
+                GameEvent::ShipHit {
+                    ship: ship(i)?,
+                    by: match by {
+                        Some(by) => Some(ship(by)?),
+                        None => None,
+                    },
+                    pos: pos,
+                }
+            }
+            GameEvent::ShipDamaged { ship: i, pos } => {
+                GameEvent::ShipDamaged {
+                    ship: ship(i)?,
+                    pos: pos,
+                }
+            }
+            GameEvent::PickedUp { ship: i, pos } => {
+                GameEvent::PickedUp {
+                    ship: ship(i)?,
+                    pos: pos,
+                }
+            }
+            GameEvent::Pounded { ship: i, pos } => {
+                GameEvent::Pounded {
+                    ship: ship(i)?,
+                    pos: pos,
+                }
+            }
+            GameEvent::MagicPlanetReached(i) => GameEvent::MagicPlanetReached(ship(i)?),
+            GameEvent::LevelReset => GameEvent::LevelReset,
+        })
+    }
+}
+
+/// Something that happened during an update which costs a ship its life or ends the level. These
+/// are collected during the update and dealt with at the end, so nothing gets reset halfway
+/// through.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Outcome {
+    Won(usize),
+    Crashed(usize),
+    Shot { victim: usize, shooter: usize },
+}
+
+/// World is the whole simulation: the space and the ships in it. It knows nothing about windows,
+/// input devices or sound, so it can run on a server or in a test.
+pub struct World {
+    pub mode: Mode,
+    pub space: Space,
+    pub ships: Vec<Ship>,
+    pub level: u32, // how many levels have been played, including this one
+    pub seed: u32, // the seed of the current level's space
+    pub difficulty: Difficulty, // what the current level was generated with
+    pub generator: Option<Generator>, // None to pick one for each level from its seed
+    // if set, picks the difficulty of each level from how the last few went
+    pub adaptive: Option<Adaptive>,
+    pub shop: bool, // the players visit the shop between levels, so credits are worth something
+    // if set, a finished level waits for `next_level` instead of going straight on to the next
+    // one
+    pub stop_at_level_end: bool,
+    pub level_over: bool, // the level has been won or lost, and nothing moves until the next one
+    events: Vec<GameEvent>,
+    // the broad phase, refilled every tick
+    planet_grid: Grid<PlanetIndex>,
+    hazard_grid: Grid<Entity>,
+}
+
+impl World {
+    pub fn new(mode: Mode, num_ships: usize, seed: u32) -> World {
+        World::with_difficulty(mode, num_ships, seed, Difficulty::normal())
+    }
+
+    pub fn with_difficulty(mode: Mode,
+                           num_ships: usize,
+                           seed: u32,
+                           difficulty: Difficulty)
+                           -> World {
+        World::with_generator(mode,
+                              num_ships,
+                              seed,
+                              difficulty,
+                              Some(Generator::RandomWalk))
+    }
+
+    pub fn with_generator(mode: Mode,
+                          num_ships: usize,
+                          seed: u32,
+                          difficulty: Difficulty,
+                          generator: Option<Generator>)
+                          -> World {
+        let space = level_space(seed, &difficulty, generator);
+        let mut world = World::with_space(mode, space, seed, difficulty, generator);
+        for _ in 0..num_ships {
+            world.add_ship();
+        }
+        world
+    }
+
+    /// A world with no ships and no level, just the first planet, for a client to fill in from the
+    /// server's first snapshot. It calls itself level 0, which is never played, so the snapshot
+    /// always loads its own level rather than taking this one for it.
+    pub fn empty(mode: Mode) -> World {
+        let mut world = World::with_space(mode, Space::empty(), 0, Difficulty::normal(), None);
+        world.level = 0;
+        world
+    }
+
+    fn with_space(mode: Mode,
+                  space: Space,
+                  seed: u32,
+                  difficulty: Difficulty,
+                  generator: Option<Generator>)
+                  -> World {
+        World {
+            mode: mode,
+            space: space,
+            ships: vec![],
+            level: 1,
+            seed: seed,
+            difficulty: difficulty,
+            generator: generator,
+            adaptive: None,
+            shop: false,
+            stop_at_level_end: false,
+            level_over: false,
+            events: vec![],
+            planet_grid: Grid::new(),
+            hazard_grid: Grid::new(),
+        }
+    }
+
+    /// Add a ship at the start of the level. Returns its index.
+    pub fn add_ship(&mut self) -> usize {
+        // spread the ships around the first planet so they don't start on top of each other
+        let spawn_rotation = self.ships.len() as f64 * PI * 0.6;
+        let ship = Ship::new(&self.space, spawn_rotation);
+        self.ships.push(ship);
+        self.update_bodies();
+        self.ships.len() - 1
+    }
+
+    /// Remove a ship. This changes the index of every ship after it!
+    pub fn remove_ship(&mut self, i: usize) {
+        if let Some(entity) = self.ships.remove(i).entity {
+            self.space.entities.despawn(entity);
+        }
+        for bullet in self.space.entities.projectiles_of(i) {
+            self.space.entities.despawn(bullet);
+        }
+        self.events = self.events.iter().filter_map(|event| event.after_removing(i)).collect();
+        let ref mut bodies = self.space.entities.bodies;
+        for entity in bodies.entities() {
+            if let Some(&mut Body::Ship(ref mut ship)) = bodies.get_mut(entity) {
+                if *ship > i {
+                    *ship -= 1;
+                }
+            }
+        }
+        let ref mut projectiles = self.space.entities.projectiles;
+        for bullet in projectiles.entities() {
+            if let Some(projectile) = projectiles.get_mut(bullet) {
+                if projectile.owner > i {
+                    projectile.owner -= 1;
+                }
+            }
+        }
+    }
+
+    /// Put a ship back at the start, without its bullets.
+    fn respawn(&mut self, i: usize) {
+        self.ships[i].respawn(&self.space);
+        for bullet in self.space.entities.projectiles_of(i) {
+            self.space.entities.despawn(bullet);
+        }
+    }
+
+    /// Take the events that have happened since the last call.
+    pub fn drain_events(&mut self) -> Vec<GameEvent> {
+        mem::replace(&mut self.events, vec![])
+    }
+
+    /// Queue an event for whoever owns the world, keeping the score and the credits as it goes.
+    fn emit(&mut self, event: GameEvent) {
+        self.score(&event);
+        self.events.push(event);
+    }
+
+    /// Reaching the magic planet is worth a point, crashing costs one, and shooting another ship
+    /// is worth one to the shooter. Winning and killing crawlers earn credits too.
+    fn score(&mut self, event: &GameEvent) {
+        match *event {
+            GameEvent::MagicPlanetReached(ship) => {
+                self.ships[ship].score += 1;
+                self.ships[ship].credits += WIN_CREDITS;
+            }
+            GameEvent::BugKilled { by, .. } => self.ships[by].credits += CRAWLER_CREDITS,
+            GameEvent::ShipHit { ship, by: None, .. } => self.ships[ship].score -= 1,
+            GameEvent::ShipHit { by: Some(shooter), .. } => self.ships[shooter].score += 1,
+            _ => {}
+        }
+    }
+
+    /// Start a new level, with every ship back at the start. The next seed is derived from the
+    /// current one, so every machine that started from the same seed gets the same levels.
+    pub fn next_level(&mut self) {
+        self.seed = self.seed.wrapping_mul(1103515245).wrapping_add(12345);
+        self.level += 1;
+        self.load_level(self.seed);
+    }
+
+    /// Replace the space with the one generated from `seed`, and put the ships at the start with
+    /// their health back.
+    pub fn load_level(&mut self, seed: u32) {
+        self.seed = seed;
+        self.space = level_space(seed, &self.difficulty, self.generator);
+        self.level_over = false;
+        for ship in self.ships.iter_mut() {
+            ship.respawn(&self.space);
+            ship.health = ship.upgrades.extra_health();
+        }
+        self.emit(GameEvent::LevelReset);
+    }
+
+    pub fn get_ship_positions(&self) -> Vec<Point> {
+        self.ships.iter().map(|ship| ship.get_pos(&self.space)).collect()
+    }
+
+    pub fn update(&mut self, time_delta: f64) {
+        if self.level_over {
+            return;
+        }
+        self.move_planets(time_delta);
+        self.fill_planet_grid();
+        self.update_bodies();
+        let ship_positions = self.get_ship_positions();
+        let mut outcomes = vec![];
+        let first_event = self.events.len();
+        for i in 0..self.ships.len() {
+            self.update_ship(i, ship_positions[i], time_delta, &mut outcomes);
+        }
+        self.update_bullets(time_delta);
+
+        let pounds: Vec<(usize, Point)> = self.events[first_event..]
+            .iter()
+            .filter_map(|event| match *event {
+                GameEvent::Pounded { ship, pos } => Some((ship, pos)),
+                _ => None,
+            })
+            .collect();
+        self.update_hazards(&pounds, time_delta, &mut outcomes);
+        self.update_planets(time_delta, &mut outcomes);
+        if self.mode == Mode::Versus {
+            self.update_versus(&ship_positions, time_delta, &mut outcomes);
+        }
+        self.space.focus(&ship_positions);
+
+        for outcome in outcomes {
+            // once the level has been reset, anything else that happened is about the old level
+            if self.resolve(outcome) {
+                break;
+            }
+        }
+    }
+
+    /// Move the moving planets on, carrying along the ships attached to them.
+    pub fn move_planets(&mut self, time_delta: f64) {
+        self.space.move_planets(time_delta);
+        self.follow_planets();
+    }
+
+    /// Put the moving planets where they are `time` seconds into the level.
+    pub fn set_time(&mut self, time: f64) {
+        self.space.set_time(time);
+        self.follow_planets();
+    }
+
+    /// Ships are positioned relative to their planet, so they go wherever it goes; all that's left
+    /// is to keep up with planets that have moved to another area. A ship whose planet can't be
+    /// found at all (it was put in from another space) starts again.
+    fn follow_planets(&mut self) {
+        for ship in self.ships.iter_mut() {
+            ship.attached_planet = self.space.relocated(ship.attached_planet);
+            if !ship.is_attached(&self.space) {
+                ship.respawn(&self.space);
+            }
+        }
+    }
+
+    /// The body system: bring the transforms and colliders of the planets and the ships up to
+    /// date. A ship that has no body in this space, because it's new or the level has changed
+    /// since, gets one.
+    fn update_bodies(&mut self) {
+        self.space.update_bodies();
+        for i in 0..self.ships.len() {
+            let pos = self.ships[i].get_pos(&self.space);
+            let rotation = self.ships[i].rotation;
+            let ref mut entities = self.space.entities;
+            match self.ships[i].entity {
+                Some(entity) if entities.is_alive(entity) => {
+                    entities.transforms.insert(entity,
+                                               Transform {
+                                                   pos: pos,
+                                                   rotation: rotation,
+                                               });
+                }
+                _ => self.ships[i].entity = Some(entities.spawn_ship(i, pos, rotation)),
+            }
+        }
+    }
+
+    /// File the nearby planets in the broad phase.
+    fn fill_planet_grid(&mut self) {
+        self.planet_grid.clear();
+        for (planet_index, planet) in self.space.get_nearby_planets() {
+            self.planet_grid.insert(planet_index, planet.pos, planet.radius);
+        }
+    }
+
+    /// Move one ship without moving anything else or letting it win or die. Network clients use
+    /// this to predict where their own ship is before the server tells them.
+    pub fn predict_ship(&mut self, i: usize, time_delta: f64) {
+        self.fill_planet_grid();
+        let ship_pos = self.ships[i].get_pos(&self.space);
+        let mut outcomes = vec![];
+        self.update_ship(i, ship_pos, time_delta, &mut outcomes);
+        self.update_bullets(time_delta);
+    }
+
+    /// Move one ship and nothing else: no shooting, no bullets, no pickups and no crumbling, and
+    /// no events. Used for re-applying inputs that the server hasn't seen yet on top of the state
+    /// it sent, so the rest of the world, which `predict_ship` already moved on once for each of
+    /// those inputs, isn't moved on again.
+    pub fn replay_ship(&mut self, i: usize, time_delta: f64) {
+        self.fill_planet_grid();
+        let ship_pos = self.ships[i].get_pos(&self.space);
+        let num_events = self.events.len();
+        let mut outcomes = vec![];
+        self.step_ship(i, ship_pos, time_delta, &mut outcomes);
+        self.events.truncate(num_events);
+    }
+
+    fn update_ship(&mut self,
+                   i: usize,
+                   ship_pos: Point,
+                   time_delta: f64,
+                   outcomes: &mut Vec<Outcome>) {
+        let shoot_target = {
+            let ref ship = self.ships[i];
+            if ship.input.shooting {
+                // with no mouse to aim with, shoot straight ahead
+                Some(ship.input
+                    .shoot_target
+                    .unwrap_or(rotated_position(ship_pos, ship.rotation, SHIP_SIZE)))
+            } else {
+                None
+            }
+        };
+
+        // It would be nice if more of these methods took &self instead of &mut self, and we
+        // assigned the results
+        if let Some(target) = shoot_target {
+            if let Some(dir) = self.ships[i].update_shoot(target, ship_pos, time_delta) {
+                self.space.entities.spawn_bullet(i, ship_pos, dir);
+                self.emit(GameEvent::Shot(i));
+            }
+        }
+
+        self.update_pickups(i, ship_pos);
+        let landed_on = self.step_ship(i, ship_pos, time_delta, outcomes);
+        if let Some(planet) = landed_on.and_then(|idx| self.space.get_planet_mut(idx)) {
+            if planet.kind == PlanetKind::Crumbling {
+                planet.crumbling = true;
+            }
+        }
+    }
+
+    /// The part of updating a ship that only changes the ship itself: moving it, and landing or
+    /// attaching it. Returns the planet it landed on, if it did.
+    fn step_ship(&mut self,
+                 i: usize,
+                 ship_pos: Point,
+                 time_delta: f64,
+                 outcomes: &mut Vec<Outcome>)
+                 -> Option<PlanetIndex> {
+        let surface = self.ships[i].get_planet(&self.space).kind;
+        if self.ships[i].update_movement(surface, time_delta) {
+            self.emit(GameEvent::Jumped(i));
+        }
+        let in_gas = self.space
+            .get_nearby_planets()
+            .iter()
+            .any(|&(_, planet)| {
+                planet.kind == PlanetKind::GasGiant &&
+                distance(ship_pos, planet.pos) < planet.radius
+            });
+        if in_gas {
+            self.ships[i].exit_speed *= 1.0 - (GAS_DRAG * time_delta).min(1.0);
+        }
+        self.ships[i].update_tether(time_delta);
+        if self.ships[i].update_heat(surface, time_delta) &&
+           !outcomes.contains(&Outcome::Crashed(i)) {
+            outcomes.push(Outcome::Crashed(i));
+        }
+        let (closest_planet_idx, closest_planet_distance, landed_on) =
+            self.update_collision(i, ship_pos, outcomes);
+        self.update_attach(i, closest_planet_idx, closest_planet_distance, ship_pos);
+        self.ships[i].bound_rotation();
+        landed_on
+    }
+
+    /// Announce something that happened, and reset the level or respawn the ship if it calls for
+    /// that. Returns true if the level was reset.
+    fn resolve(&mut self, outcome: Outcome) -> bool {
+        match outcome {
+            Outcome::Won(i) => {
+                self.emit(GameEvent::MagicPlanetReached(i));
+                self.adapt(true);
+                self.end_level();
+                true
+            }
+            Outcome::Crashed(i) => {
+                let pos = self.ships[i].get_pos(&self.space);
+                // a crash it has the health for just sends the ship back to the start
+                if self.take_hit(i, pos) {
+                    self.respawn(i);
+                    return false;
+                }
+                self.emit(GameEvent::ShipHit {
+                    ship: i,
+                    by: None,
+                    pos: pos,
+                });
+                if self.mode == Mode::Solo {
+                    self.adapt(false);
+                    self.end_level();
+                    true
+                } else {
+                    self.respawn(i);
+                    false
+                }
+            }
+            Outcome::Shot { victim, shooter } => {
+                let pos = self.ships[victim].get_pos(&self.space);
+                if self.take_hit(victim, pos) {
+                    return false;
+                }
+                self.emit(GameEvent::ShipHit {
+                    ship: victim,
+                    by: Some(shooter),
+                    pos: pos,
+                });
+                self.respawn(victim);
+                false
+            }
+        }
+    }
+
+    /// Use up some of a ship's health on a hit, if it has any left. Returns false if it doesn't,
+    /// and the hit counts.
+    fn take_hit(&mut self, i: usize, pos: Point) -> bool {
+        if self.ships[i].health == 0 {
+            return false;
+        }
+        self.ships[i].health -= 1;
+        self.emit(GameEvent::ShipDamaged {
+            ship: i,
+            pos: pos,
+        });
+        true
+    }
+
+    /// Go on to the next level, or stop and wait for `next_level` if `stop_at_level_end` is set.
+    fn end_level(&mut self) {
+        if self.stop_at_level_end {
+            self.level_over = true;
+        } else {
+            self.next_level();
+        }
+    }
+
+    /// Tell the adaptive difficulty, if there is one, how the level went, and pick the
+    /// difficulty of the next one.
+    fn adapt(&mut self, won: bool) {
+        if let Some(ref mut adaptive) = self.adaptive {
+            adaptive.record(won);
+            self.difficulty = adaptive.difficulty();
+        }
+    }
+
+    /// Update game state based on collision. The ship is swept from `ship_pos` to where it's moved
+    /// to this tick, so that it can't fly straight through a small planet between two ticks.
+    /// Returns the closest planet and the distance to it (for use in attachment), and the planet
+    /// the ship landed on, if it did.
+    fn update_collision(&mut self,
+                        i: usize,
+                        ship_pos: Point,
+                        outcomes: &mut Vec<Outcome>)
+                        -> (PlanetIndex, f64, Option<PlanetIndex>) {
+        let ship_ball = Ball::new(SHIP_SIZE / 2.0);
+        let na_ship_pos = coll_pt(ship_pos);
+        let moved_to = self.ships[i].get_pos(&self.space);
+        let motion = pt(moved_to.x - ship_pos.x, moved_to.y - ship_pos.y);
+        // anything closer than the planet the ship's attached to, or that it could have hit
+        let reach = self.ships[i].height.max(distance(ship_pos, moved_to)) + SHIP_SIZE / 2.0;
+        let candidates = self.planet_grid.query(ship_pos, reach);
+        let ship = &mut self.ships[i];
+        let mut closest_planet_distance = ship.height;
+        let mut closest_planet_idx: PlanetIndex = ship.attached_planet;
+
+        // check if the player found the magic planet
+        {
+            let planet_ball = Ball::new(MAGIC_PLANET_SIZE);
+            let planet_pos = coll_pt(self.space.get_magic_planet());
+            if let Some(_) = query::contact(&na_ship_pos,
+                                            &ship_ball,
+                                            &planet_pos,
+                                            &planet_ball,
+                                            0.0) {
+                outcomes.push(Outcome::Won(i));
+                return (closest_planet_idx, closest_planet_distance, None);
+            }
+        }
+
+        let mut landed_on = None;
+        let mut landings = vec![];
+        for planet_index in candidates {
+            let planet = match self.space.get_planet(planet_index) {
+                Some(planet) if planet.is_solid() => planet,
+                _ => continue,
+            };
+            let planet_ball = Ball::new(planet.radius);
+            let planet_pos = coll_pt(planet.pos);
+
+            // Check if this is the closest planet
+            let distance = query::distance(&na_ship_pos, &ship_ball, &planet_pos, &planet_ball);
+            if distance < closest_planet_distance {
+                closest_planet_distance = distance;
+                closest_planet_idx = planet_index;
+                ship.closest_planet_coords = pt(planet.pos.x, planet.pos.y);
+            }
+            let collided =
+                if query::contact(&na_ship_pos, &ship_ball, &planet_pos, &planet_ball, -1.0)
+                    .is_some() {
+                    Some(ship_pos)
+                } else if distance > 1.0 {
+                    // it wasn't touching at the start of the tick, but it might have gone through.
+                    // Ships walking on a planet are left out, or cutting the corner between two
+                    // ticks would look like landing.
+                    sweep(ship_pos, motion, &ship_ball, planet.pos, &planet_ball)
+                        .map(|t| pt(ship_pos.x + motion.x * t, ship_pos.y + motion.y * t))
+                } else {
+                    None
+                };
+            if let Some(hit_pos) = collided {
+                // We are landing on a new planet
+                ship.attached_planet = planet_index;
+                ship.flying = false;
+                ship.swing = 0.0;
+                ship.tether = None;
+                ship.double_jumped = false;
+                ship.height = planet.radius + (SHIP_SIZE / 2.0);
+                ship.rotation = direction_from_to(planet.pos, hit_pos);
+                let surface = rotated_position(planet.pos, ship.rotation, planet.radius);
+                landed_on = Some(planet_index);
+                if ship.pounding {
+                    ship.pounding = false;
+                    landings.push(GameEvent::Pounded {
+                        ship: i,
+                        pos: surface,
+                    });
+                }
+
+                if planet.kind == PlanetKind::Bouncy {
+                    ship.jumping = true;
+                    ship.exit_speed = JUMP_SPEED;
+                    landings.push(GameEvent::Bounced {
+                        ship: i,
+                        pos: surface,
+                        dir: ship.rotation,
+                    });
+                } else {
+                    ship.jumping = false;
+                    ship.exit_speed = 0.0;
+                    landings.push(GameEvent::Landed {
+                        ship: i,
+                        pos: surface,
+                        dir: ship.rotation,
+                    });
+                }
+            }
+        }
+        for event in landings {
+            self.emit(event);
+        }
+        (closest_planet_idx, closest_planet_distance, landed_on)
+    }
+
+    /// Handle use of the "attach" ability: throw a tether to the closest planet, if it's in range.
+    /// The ship keeps going the way it was, but from then on it goes around the new planet.
+    fn update_attach(&mut self,
+                     i: usize,
+                     closest_planet_idx: PlanetIndex,
+                     closest_planet_distance: f64,
+                     ship_pos: Point) {
+        {
+            let ship = &mut self.ships[i];
+            let planet = match self.space.get_planet(closest_planet_idx) {
+                Some(planet) => planet,
+                None => return,
+            };
+            if !ship.input.attach || ship.input.thrown || ship.tether.is_some() ||
+               closest_planet_idx == ship.attached_planet ||
+               closest_planet_distance > ship.upgrades.attach_range() {
+                return;
+            }
+            let vel = ship.velocity();
+            ship.attached_planet = closest_planet_idx;
+            ship.rotation = (ship_pos.y - ship.closest_planet_coords.y)
+                .atan2(ship_pos.x - ship.closest_planet_coords.x);
+            ship.height = closest_planet_distance + planet.radius + (SHIP_SIZE / 2.0);
+            ship.set_velocity(vel);
+            ship.throw_tether();
+        }
+        let dir = self.ships[i].rotation + PI;
+        self.emit(GameEvent::Attached {
+            ship: i,
+            pos: ship_pos,
+            dir: dir,
+        });
+    }
+
+    /// Shrink the crumbling planets. Ships standing on one go down with it; once it's gone they
+    /// fall towards the closest planet that's left, and whatever else was on it is gone with
+    /// it.
+    fn update_planets(&mut self, time_delta: f64, outcomes: &mut Vec<Outcome>) {
+        let crumbling: Vec<PlanetIndex> = self.space
+            .get_nearby_planets()
+            .into_iter()
+            .filter(|&(_, planet)| planet.crumbling && planet.radius > 0.0)
+            .map(|(idx, _)| idx)
+            .collect();
+        for planet_index in crumbling {
+            let radius = match self.space.get_planet_mut(planet_index) {
+                Some(planet) => {
+                    planet.radius = (planet.radius - CRUMBLE_SPEED * time_delta).max(0.0);
+                    planet.radius
+                }
+                None => continue,
+            };
+            for i in 0..self.ships.len() {
+                if self.ships[i].attached_planet != planet_index {
+                    continue;
+                }
+                if radius > 0.0 {
+                    if !self.ships[i].in_air() {
+                        self.ships[i].height = radius + SHIP_SIZE / 2.0;
+                    }
+                } else if !self.drop_ship(i) && !outcomes.contains(&Outcome::Crashed(i)) {
+                    outcomes.push(Outcome::Crashed(i));
+                }
+            }
+            if radius <= 0.0 {
+                for entity in self.space.entities.attached_to(planet_index) {
+                    self.space.entities.despawn(entity);
+                }
+            }
+        }
+    }
+
+    /// Attach a ship whose planet has gone to the closest solid planet, and let it fall. Returns
+    /// false if there's nothing nearby to fall to.
+    fn drop_ship(&mut self, i: usize) -> bool {
+        let ship_pos = self.ships[i].get_pos(&self.space);
+        let closest = self.space
+            .get_nearby_planets()
+            .into_iter()
+            .filter(|&(_, planet)| planet.is_solid())
+            .map(|(idx, planet)| (distance(ship_pos, planet.pos) - planet.radius, idx, planet.pos))
+            .fold(None, |best: Option<(f64, PlanetIndex, Point)>, candidate| {
+                match best {
+                    Some(best) if best.0 <= candidate.0 => Some(best),
+                    _ => Some(candidate),
+                }
+            });
+        match closest {
+            Some((_, planet_index, planet_pos)) => {
+                let ref mut ship = self.ships[i];
+                ship.attached_planet = planet_index;
+                ship.rotation = direction_from_to(planet_pos, ship_pos);
+                ship.height = distance(planet_pos, ship_pos);
+                ship.flying = false;
+                ship.jumping = true;
+                ship.exit_speed = 0.0;
+                ship.swing = 0.0;
+                ship.tether = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Let ship `i` pick up whatever it's flown into.
+    fn update_pickups(&mut self, i: usize, ship_pos: Point) {
+        let ship_ball = Ball::new(SHIP_SIZE / 2.0);
+        let ref entities = self.space.entities;
+        let picked_up: Vec<(Entity, Pickup, Point)> = entities.pickups
+            .iter()
+            .filter_map(|(entity, &pickup)| {
+                let (pos, radius) = match (entities.get_pos(entity),
+                                           entities.colliders.get(entity)) {
+                    (Some(pos), Some(collider)) => (pos, collider.radius),
+                    _ => return None,
+                };
+                let pickup_ball = Ball::new(radius);
+                query::contact(&coll_pt(ship_pos), &ship_ball, &coll_pt(pos), &pickup_ball, 0.0)
+                    .map(|_| (entity, pickup, pos))
+            })
+            .collect();
+        for (entity, pickup, pos) in picked_up {
+            match pickup {
+                Pickup::Fuel(fuel) => {
+                    let ref mut ship = self.ships[i];
+                    ship.fuel = (ship.fuel + fuel).min(MAX_FUEL);
+                }
+            }
+            self.space.entities.despawn(entity);
+            self.emit(GameEvent::PickedUp {
+                ship: i,
+                pos: pos,
+            });
+        }
+    }
+
+    /// The velocity system, and getting rid of bullets that have gone too far from their ship.
+    fn update_bullets(&mut self, time_delta: f64) {
+        self.space.entities.update_velocities(time_delta);
+        let ship_positions = self.get_ship_positions();
+        let ref mut entities = self.space.entities;
+        let gone: Vec<Entity> = entities.projectiles
+            .iter()
+            .filter(|&(bullet, projectile)| {
+                match (entities.get_pos(bullet), ship_positions.get(projectile.owner)) {
+                    (Some(pos), Some(ship_pos)) => {
+                        (pos.x - ship_pos.x).abs() > BULLET_RANGE ||
+                        (pos.y - ship_pos.y).abs() > BULLET_RANGE
+                    }
+                    _ => true,
+                }
+            })
+            .map(|(bullet, _)| bullet)
+            .collect();
+        for bullet in gone {
+            entities.despawn(bullet);
+        }
+    }
+
+    /// Move the nearby entities that move by themselves, then check the hazards among them
+    /// against the ships and the bullets. The ground pounds that landed this tick, by ship and
+    /// where they landed, crush the hazards around them before anything else.
+    fn update_hazards(&mut self,
+                      pounds: &[(usize, Point)],
+                      time_delta: f64,
+                      outcomes: &mut Vec<Outcome>) {
+        let nearby = self.space.get_nearby_entities();
+        self.space.entities.update_ai(&nearby, self.difficulty.crawler_speed, time_delta);
+        self.space.update_attached();
+        self.hazard_grid.clear();
+        for &entity in nearby.iter() {
+            if let Some((pos, ball)) = self.hazard_shape(entity) {
+                self.hazard_grid.insert(entity, pos, ball.radius());
+            }
+        }
+
+        for &(i, pound_pos) in pounds.iter() {
+            for hazard in self.hazard_grid.query(pound_pos, POUND_RADIUS) {
+                let crushed = match self.hazard_shape(hazard) {
+                    Some((pos, ball)) => distance(pos, pound_pos) < POUND_RADIUS + ball.radius(),
+                    None => false,
+                };
+                if crushed {
+                    self.kill_hazard(hazard, i);
+                }
+            }
+        }
+
+        for (ship, body) in self.space.entities.bodies.iter() {
+            let i = match *body {
+                Body::Ship(i) => i,
+                Body::Planet => continue,
+            };
+            let (ship_pos, ship_ball) = match self.entity_shape(ship) {
+                Some(shape) => shape,
+                None => continue,
+            };
+            let na_ship_pos = coll_pt(ship_pos);
+            for hazard in self.hazard_grid.query(ship_pos, ship_ball.radius()) {
+                let (pos, ball) = match self.hazard_shape(hazard) {
+                    Some(shape) => shape,
+                    None => continue,
+                };
+                let uhoh = query::contact(&coll_pt(pos), &ball, &na_ship_pos, &ship_ball, 0.0);
+                if uhoh.is_some() && !outcomes.contains(&Outcome::Crashed(i)) {
+                    outcomes.push(Outcome::Crashed(i));
+                }
+            }
+        }
+
+        // which ship killed each hazard; the first bullet to hit one gets it
+        let mut killed: Vec<(Entity, usize)> = vec![];
+        for (bullet, projectile) in self.space.entities.projectiles.iter() {
+            let (from, motion, bball) = match self.bullet_path(bullet, time_delta) {
+                Some(path) => path,
+                None => continue,
+            };
+            for hazard in self.hazard_grid.query_sweep(from, motion, bball.radius()) {
+                if killed.iter().any(|&(dead, _)| dead == hazard) {
+                    continue;
+                }
+                let (pos, ball) = match self.hazard_shape(hazard) {
+                    Some(shape) => shape,
+                    None => continue,
+                };
+                if sweep(from, motion, &bball, pos, &ball).is_some() {
+                    killed.push((hazard, projectile.owner));
+                }
+            }
+        }
+        for (hazard, i) in killed {
+            self.kill_hazard(hazard, i);
+        }
+    }
+
+    /// Get rid of a hazard that ship `i` killed, if it's still there.
+    fn kill_hazard(&mut self, hazard: Entity, i: usize) {
+        let pos = match self.space.entities.get_pos(hazard) {
+            Some(pos) => pos,
+            None => return,
+        };
+        if self.space.entities.despawn(hazard) {
+            self.emit(GameEvent::BugKilled {
+                by: i,
+                pos: pos,
+            });
+        }
+    }
+
+    /// Where a hazard is and what it collides as, if it's still there.
+    fn hazard_shape(&self, entity: Entity) -> Option<(Point, Ball<f64>)> {
+        if !self.space.entities.hazards.contains(entity) {
+            return None;
+        }
+        self.entity_shape(entity)
+    }
+
+    /// Where an entity is and what it collides as, if it's there and collides at all.
+    fn entity_shape(&self, entity: Entity) -> Option<(Point, Ball<f64>)> {
+        let ref entities = self.space.entities;
+        match (entities.transforms.get(entity), entities.colliders.get(entity)) {
+            (Some(transform), Some(collider)) => Some((transform.pos, Ball::new(collider.radius))),
+            _ => None,
+        }
+    }
+
+    /// Where a bullet was at the start of the tick, how far it's gone since, and what it collides
+    /// as.
+    fn bullet_path(&self, bullet: Entity, time_delta: f64) -> Option<(Point, Point, Ball<f64>)> {
+        let ref entities = self.space.entities;
+        match (entities.transforms.get(bullet),
+               entities.velocities.get(bullet),
+               entities.colliders.get(bullet)) {
+            (Some(&Transform { pos, .. }), Some(&Velocity(vel)), Some(&Collider { radius })) => {
+                let motion = pt(vel.x * time_delta, vel.y * time_delta);
+                Some((pt(pos.x - motion.x, pos.y - motion.y), motion, Ball::new(radius)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Check for ships being hit by the other ships' bullets.
+    fn update_versus(&mut self,
+                     ship_positions: &[Point],
+                     time_delta: f64,
+                     outcomes: &mut Vec<Outcome>) {
+        let ship_ball = Ball::new(SHIP_SIZE / 2.0);
+        let mut hits = vec![];
+        for (bullet, projectile) in self.space.entities.projectiles.iter() {
+            let (from, motion, bball) = match self.bullet_path(bullet, time_delta) {
+                Some(path) => path,
+                None => continue,
+            };
+            let shooter = projectile.owner;
+            let victim = ship_positions.iter().enumerate().position(|(victim, &ship_pos)| {
+                victim != shooter && sweep(from, motion, &bball, ship_pos, &ship_ball).is_some()
+            });
+            if let Some(victim) = victim {
+                hits.push((bullet, victim, shooter));
+            }
+        }
+        for (bullet, victim, shooter) in hits {
+            self.space.entities.despawn(bullet);
+            outcomes.push(Outcome::Shot {
+                victim: victim,
+                shooter: shooter,
+            });
+        }
+    }
+}
+
+/// Sweep a ball that moved by `motion` from `from` over the last tick against a ball that stayed
+/// put. Returns how far through the tick, from 0 to 1, they first touched, if they did.
+fn sweep(from: Point,
+         motion: Point,
+         ball: &Ball<f64>,
+         other_pos: Point,
+         other: &Ball<f64>)
+         -> Option<f64> {
+    if query::contact(&coll_pt(from), ball, &coll_pt(other_pos), other, 0.0).is_some() {
+        return Some(0.0);
+    }
+    let still = coll_vec(pt(0.0, 0.0));
+    match query::time_of_impact(&coll_pt(from),
+                                &coll_vec(motion),
+                                ball,
+                                &coll_pt(other_pos),
+                                &still,
+                                other) {
+        Some(t) if t <= 1.0 => Some(t),
+        _ => None,
+    }
+}
+
+/// Generate the space for a level.
+fn level_space(seed: u32, difficulty: &Difficulty, generator: Option<Generator>) -> Space {
+    let generator = generator.unwrap_or_else(|| Generator::for_seed(seed));
+    Space::with_generator(seed, difficulty, &*generator.get())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaying_only_moves_the_ship() {
+        let mut world = World::empty(Mode::Solo);
+        let i = world.add_ship();
+        let ship_pos = world.ships[i].get_pos(&world.space);
+        let bullet = world.space.entities.spawn_bullet(i, ship_pos, 0.0);
+        let can = world.space.entities.spawn_fuel_can(ship_pos, 1.0);
+        world.ships[i].input.right = true;
+        let rotation = world.ships[i].rotation;
+
+        for _ in 0..10 {
+            world.replay_ship(i, TICK_TIME);
+        }
+        assert!(world.ships[i].rotation != rotation);
+        assert_eq!(world.space.entities.get_pos(bullet), Some(ship_pos));
+        assert!(world.space.entities.pickups.get(can).is_some());
+        assert!(world.events.is_empty());
+
+        world.ships[i].rotation = rotation;
+        world.predict_ship(i, TICK_TIME);
+        assert!(world.space.entities.get_pos(bullet) != Some(ship_pos));
+        assert!(world.space.entities.pickups.get(can).is_none());
+    }
+
+    #[test]
+    fn ships_and_planets_have_bodies() {
+        let mut world = World::new(Mode::Race, 3, 7);
+        world.update(TICK_TIME);
+        for planet in world.space.get_all_planets() {
+            let entity = planet.get_entity().unwrap();
+            let ref entities = world.space.entities;
+            assert_eq!(entities.bodies.get(entity), Some(&Body::Planet));
+            assert_eq!(entities.get_pos(entity), Some(planet.pos));
+            assert_eq!(entities.colliders.get(entity).unwrap().radius, planet.radius);
+        }
+
+        world.remove_ship(0);
+        world.update(TICK_TIME);
+        let ref entities = world.space.entities;
+        let ships: Vec<&Body> = entities.bodies
+            .iter()
+            .map(|(_, body)| body)
+            .filter(|&body| *body != Body::Planet)
+            .collect();
+        assert_eq!(ships, vec![&Body::Ship(0), &Body::Ship(1)]);
+        for (i, ship) in world.ships.iter().enumerate() {
+            let entity = ship.entity.unwrap();
+            assert_eq!(entities.bodies.get(entity), Some(&Body::Ship(i)));
+            assert_eq!(entities.get_pos(entity), Some(ship.get_pos(&world.space)));
+        }
+    }
+}